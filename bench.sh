@@ -1,2 +1,2 @@
 #!/usr/bin/env bash
-rustup run nightly cargo bench
\ No newline at end of file
+rustup run nightly cargo bench --features nightly
\ No newline at end of file
@@ -0,0 +1,271 @@
+//! Derives `risp::convert::FromRisp` and `risp::convert::IntoRisp` for structs and enums.
+//!
+//! Structs are converted from and into maps with keyword keys.
+//! Unit enum variants are keywords, other variants maps with a single entry like `{:transpose 12}`.
+//!
+//! Field attributes:
+//! * `#[risp(rename = "name")]` uses another key
+//! * `#[risp(default)]` uses `Default::default()` if the key is missing
+//! * `#[risp(flatten)]` flattens nested vectors like `risp::convert::flatten_into`
+//! * `#[risp(optional)]` for `Option` fields, which are `None` if the key is missing or nil
+
+extern crate proc_macro;
+extern crate proc_macro2;
+#[macro_use]
+extern crate quote;
+extern crate syn;
+
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as Tokens;
+use syn::{Attribute, Data, DeriveInput, Fields, FieldsNamed, Generics, Ident, LitStr, parse_quote};
+
+#[proc_macro_derive(FromRisp, attributes(risp))]
+pub fn derive_from_risp(input: TokenStream) -> TokenStream {
+    let input = syn::parse_macro_input!(input as DeriveInput);
+    expand_from_risp(&input).unwrap_or_else(|err| err.to_compile_error()).into()
+}
+
+#[proc_macro_derive(IntoRisp, attributes(risp))]
+pub fn derive_into_risp(input: TokenStream) -> TokenStream {
+    let input = syn::parse_macro_input!(input as DeriveInput);
+    expand_into_risp(&input).unwrap_or_else(|err| err.to_compile_error()).into()
+}
+
+#[derive(Default)]
+struct Options {
+    rename: Option<String>,
+    default: bool,
+    flatten: bool,
+    optional: bool,
+}
+
+fn parse_options(attrs: &[Attribute]) -> syn::Result<Options> {
+    let mut options = Options::default();
+    for attr in attrs.iter().filter(|attr| attr.path().is_ident("risp")) {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("rename") {
+                let name: LitStr = meta.value()?.parse()?;
+                options.rename = Some(name.value());
+            } else if meta.path.is_ident("default") {
+                options.default = true;
+            } else if meta.path.is_ident("flatten") {
+                options.flatten = true;
+            } else if meta.path.is_ident("optional") {
+                options.optional = true;
+            } else {
+                return Err(meta.error("unknown risp attribute, expected rename, default, flatten or optional"));
+            }
+            Ok(())
+        })?;
+    }
+    Ok(options)
+}
+
+fn snake_case(name: &str) -> String {
+    let mut result = String::new();
+    for (i, c) in name.chars().enumerate() {
+        if c.is_uppercase() && i > 0 {
+            result.push('_');
+        }
+        result.extend(c.to_lowercase());
+    }
+    result
+}
+
+fn add_bound(generics: &Generics, bound: Tokens) -> Generics {
+    let mut generics = generics.clone();
+    for param in generics.type_params_mut() {
+        param.bounds.push(parse_quote!(#bound));
+    }
+    generics
+}
+
+fn expand_from_risp(input: &DeriveInput) -> syn::Result<Tokens> {
+    let name = &input.ident;
+    let generics = add_bound(&input.generics, quote!(::risp::convert::FromRisp));
+    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+    let body = match input.data {
+        Data::Struct(ref data) => match data.fields {
+            Fields::Named(ref fields) => {
+                let construct = from_named_fields(quote!(#name), fields, &name.to_string())?;
+                quote!(Ok(#construct))
+            }
+            Fields::Unnamed(ref fields) if fields.unnamed.len() == 1 => {
+                quote!(Ok(#name(::risp::convert::FromRisp::from_risp(risp)?)))
+            }
+            _ => return Err(syn::Error::new_spanned(input, "FromRisp can only be derived for structs with named fields or a single unnamed field")),
+        },
+        Data::Enum(ref data) => {
+            let mut keyword_arms = vec![];
+            let mut map_arms = vec![];
+            for variant in &data.variants {
+                let variant_ident = &variant.ident;
+                let key = parse_options(&variant.attrs)?.rename.unwrap_or_else(|| snake_case(&variant_ident.to_string()));
+                match variant.fields {
+                    Fields::Unit => keyword_arms.push(quote!(#key => Ok(#name::#variant_ident),)),
+                    Fields::Unnamed(ref fields) if fields.unnamed.len() == 1 => map_arms.push(quote!(
+                        #key => ::risp::convert::FromRisp::from_risp(risp)
+                            .map(#name::#variant_ident)
+                            .map_err(|e| e.at_key(#key)),
+                    )),
+                    Fields::Named(ref fields) => {
+                        let construct = from_named_fields(quote!(#name::#variant_ident), fields, &format!("{}::{}", name, variant_ident))?;
+                        map_arms.push(quote!(#key => {
+                            let convert = move || -> Result<Self, ::risp::types::RispError> { Ok(#construct) };
+                            convert().map_err(|e| e.at_key(#key))
+                        }));
+                    }
+                    _ => return Err(syn::Error::new_spanned(variant, "FromRisp can't be derived for variants with multiple unnamed fields")),
+                }
+            }
+            let type_name = name.to_string();
+            quote! {
+                match risp {
+                    ::risp::types::RispType::Keyword(ref variant) | ::risp::types::RispType::Str(ref variant) => match variant.as_str() {
+                        #(#keyword_arms)*
+                        _ => Err(::risp::types::error(format!("Unknown {} variant :{}", #type_name, variant)))
+                    },
+                    ::risp::types::RispType::Map(map) if map.len() == 1 => {
+                        let (variant, risp) = map.into_iter().next().unwrap();
+                        match variant {
+                            ::risp::types::RispType::Keyword(ref variant) | ::risp::types::RispType::Str(ref variant) => match variant.as_str() {
+                                #(#map_arms)*
+                                _ => Err(::risp::types::error(format!("Unknown {} variant :{}", #type_name, variant)))
+                            },
+                            _ => Err(::risp::types::error(format!("Expected Keyword as {} variant but got {:?}", #type_name, variant)))
+                        }
+                    }
+                    _ => Err(::risp::types::error(format!("Expected Keyword or Map with a single entry for {} but got {:?}", #type_name, risp)))
+                }
+            }
+        }
+        Data::Union(_) => return Err(syn::Error::new_spanned(input, "FromRisp can't be derived for unions")),
+    };
+    Ok(quote! {
+        impl #impl_generics ::risp::convert::FromRisp for #name #ty_generics #where_clause {
+            fn from_risp(risp: ::risp::types::RispType) -> Result<Self, ::risp::types::RispError> {
+                #body
+            }
+        }
+    })
+}
+
+/// Constructs `path { ... }` from the map in the variable `risp`.
+fn from_named_fields(path: Tokens, fields: &FieldsNamed, type_name: &str) -> syn::Result<Tokens> {
+    let mut field_values = vec![];
+    for field in &fields.named {
+        let ident = field.ident.as_ref().unwrap();
+        let options = parse_options(&field.attrs)?;
+        let key = options.rename.unwrap_or_else(|| ident.to_string());
+        let value = if options.optional {
+            quote! {
+                match risp.get::<::risp::types::RispType>(#key)? {
+                    None | Some(::risp::types::RispType::Nil) => None,
+                    Some(value) => Some(::risp::convert::FromRisp::from_risp(value).map_err(|e| e.at_key(#key))?)
+                }
+            }
+        } else {
+            let convert = if options.flatten {
+                quote!(::risp::convert::flatten_into(value).map_err(|e| e.at_key(#key))?)
+            } else {
+                quote!(::risp::convert::FromRisp::from_risp(value).map_err(|e| e.at_key(#key))?)
+            };
+            let missing = if options.default {
+                quote!(::std::default::Default::default())
+            } else {
+                let message = format!("Missing key :{} for {}", key, type_name);
+                quote!(return Err(::risp::types::error(#message)))
+            };
+            quote! {
+                match risp.get::<::risp::types::RispType>(#key)? {
+                    Some(value) => #convert,
+                    None => #missing
+                }
+            }
+        };
+        field_values.push(quote!(#ident: #value));
+    }
+    Ok(quote!(#path { #(#field_values),* }))
+}
+
+fn expand_into_risp(input: &DeriveInput) -> syn::Result<Tokens> {
+    let name = &input.ident;
+    let generics = add_bound(&input.generics, quote!(::risp::convert::IntoRisp));
+    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+    let body = match input.data {
+        Data::Struct(ref data) => match data.fields {
+            Fields::Named(ref fields) => {
+                let idents: Vec<&Ident> = fields.named.iter().map(|field| field.ident.as_ref().unwrap()).collect();
+                let map = into_named_fields(fields)?;
+                quote! {
+                    let #name { #(#idents),* } = self;
+                    #map
+                }
+            }
+            Fields::Unnamed(ref fields) if fields.unnamed.len() == 1 => quote!(::risp::convert::IntoRisp::into_risp(self.0)),
+            _ => return Err(syn::Error::new_spanned(input, "IntoRisp can only be derived for structs with named fields or a single unnamed field")),
+        },
+        Data::Enum(ref data) => {
+            let mut arms = vec![];
+            for variant in &data.variants {
+                let variant_ident = &variant.ident;
+                let key = parse_options(&variant.attrs)?.rename.unwrap_or_else(|| snake_case(&variant_ident.to_string()));
+                let single_entry_map = |value: Tokens| quote! {{
+                    let mut __map = ::risp::types::IndexMap::new();
+                    __map.insert(::risp::types::keyword(#key), #value);
+                    ::risp::types::RispType::Map(__map)
+                }};
+                arms.push(match variant.fields {
+                    Fields::Unit => quote!(#name::#variant_ident => ::risp::types::keyword(#key),),
+                    Fields::Unnamed(ref fields) if fields.unnamed.len() == 1 => {
+                        let map = single_entry_map(quote!(::risp::convert::IntoRisp::into_risp(value)));
+                        quote!(#name::#variant_ident(value) => #map,)
+                    }
+                    Fields::Named(ref fields) => {
+                        let idents: Vec<&Ident> = fields.named.iter().map(|field| field.ident.as_ref().unwrap()).collect();
+                        let map = single_entry_map(into_named_fields(fields)?);
+                        quote!(#name::#variant_ident { #(#idents),* } => #map,)
+                    }
+                    _ => return Err(syn::Error::new_spanned(variant, "IntoRisp can't be derived for variants with multiple unnamed fields")),
+                });
+            }
+            quote! {
+                match self {
+                    #(#arms)*
+                }
+            }
+        }
+        Data::Union(_) => return Err(syn::Error::new_spanned(input, "IntoRisp can't be derived for unions")),
+    };
+    Ok(quote! {
+        impl #impl_generics ::risp::convert::IntoRisp for #name #ty_generics #where_clause {
+            fn into_risp(self) -> ::risp::types::RispType {
+                #body
+            }
+        }
+    })
+}
+
+/// Builds a map from the fields, which are bound to variables with the field names.
+fn into_named_fields(fields: &FieldsNamed) -> syn::Result<Tokens> {
+    let mut inserts = vec![];
+    for field in &fields.named {
+        let ident = field.ident.as_ref().unwrap();
+        let options = parse_options(&field.attrs)?;
+        let key = options.rename.unwrap_or_else(|| ident.to_string());
+        inserts.push(if options.optional {
+            quote! {
+                if let Some(value) = #ident {
+                    __map.insert(::risp::types::keyword(#key), ::risp::convert::IntoRisp::into_risp(value));
+                }
+            }
+        } else {
+            quote!(__map.insert(::risp::types::keyword(#key), ::risp::convert::IntoRisp::into_risp(#ident));)
+        });
+    }
+    Ok(quote! {{
+        let mut __map = ::risp::types::IndexMap::new();
+        #(#inserts)*
+        ::risp::types::RispType::Map(__map)
+    }})
+}
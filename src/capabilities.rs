@@ -0,0 +1,152 @@
+#![allow(clippy::needless_pass_by_value)]
+
+use std::collections::hash_map::RandomState;
+use std::env;
+use std::fs;
+use std::hash::{BuildHasher, Hasher};
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+use limits::check_string_length;
+use types::*;
+use types::RispType::*;
+
+/// Access to the host, which makes evaluation non-deterministic and is therefore not granted by default.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Capability {
+    /// env/get reads environment variables.
+    Env,
+    /// time/now returns the current time.
+    Time,
+    /// random/seed returns a random seed.
+    Random,
+    /// fs/read and fs/exists? read files.
+    FsRead,
+}
+
+pub type Builtin = fn(Vec<RispType>) -> RispResult;
+
+pub const ALL_CAPABILITIES: [Capability; 4] = [Capability::Env, Capability::Time, Capability::Random, Capability::FsRead];
+
+impl Capability {
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Capability::Env => "env",
+            Capability::Time => "time",
+            Capability::Random => "random",
+            Capability::FsRead => "fs-read",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Capability> {
+        ALL_CAPABILITIES.iter().find(|capability| capability.name() == name).cloned()
+    }
+
+    /// The builtins, which are only available if the capability is granted.
+    pub fn functions(&self) -> Vec<(&'static str, Builtin)> {
+        match *self {
+            Capability::Env => vec![("env/get", getenv)],
+            Capability::Time => vec![("time/now", now)],
+            Capability::Random => vec![("random/seed", random_seed)],
+            Capability::FsRead => vec![("fs/read", read_file), ("fs/exists?", file_exists)],
+        }
+    }
+}
+
+/// The capability, which provides the builtin with the name.
+pub fn required_capability(name: &str) -> Option<Capability> {
+    ALL_CAPABILITIES.iter()
+        .find(|capability| capability.functions().iter().any(|&(function_name, _)| function_name == name))
+        .cloned()
+}
+
+/// The error for a script, which uses a builtin of a capability which is not granted.
+pub fn not_granted_error(name: &str, capability: Capability) -> RispError {
+    error(format!("Capability {} not granted, which is needed by {}", capability.name(), name))
+        .with_kind(ErrorKind::CapabilityNotGranted)
+}
+
+fn string_arg(name: &str, args: &[RispType]) -> Result<String, RispError> {
+    match args {
+        [Str(s)] => Ok(s.clone()),
+        [other] => Err(error(format!("{} wants a String but got {}", name, other))),
+        _ => Err(error(format!("{} needs 1 argument but got {}", name, args.len())))
+    }
+}
+
+/// Returns the value of the environment variable or nil if it's not set.
+fn getenv(args: Vec<RispType>) -> RispResult {
+    let name = string_arg("env/get", &args)?;
+    Ok(env::var(name).map(Str).unwrap_or(Nil))
+}
+
+/// Milliseconds since 1970-01-01 UTC.
+fn now(args: Vec<RispType>) -> RispResult {
+    if !args.is_empty() {
+        return error_result(format!("time/now needs 0 arguments but got {}", args.len()));
+    }
+    let millis = SystemTime::now().duration_since(UNIX_EPOCH).map_err(|e| error(e.to_string()))?.as_millis();
+    Ok(Int(millis as i64))
+}
+
+/// A non-negative random Int, e.g. as seed for with-seed.
+fn random_seed(args: Vec<RispType>) -> RispResult {
+    if !args.is_empty() {
+        return error_result(format!("random/seed needs 0 arguments but got {}", args.len()));
+    }
+    let random = RandomState::new().build_hasher().finish();
+    Ok(Int((random >> 1) as i64))
+}
+
+fn read_file(args: Vec<RispType>) -> RispResult {
+    let path = string_arg("fs/read", &args)?;
+    if let Ok(metadata) = fs::metadata(&path) {
+        check_string_length("fs/read", Some(metadata.len() as usize))?;
+    }
+    fs::read_to_string(&path).map(Str).map_err(|e| error(format!("fs/read can't read {}: {}", path, e)))
+}
+
+fn file_exists(args: Vec<RispType>) -> RispResult {
+    let path = string_arg("fs/exists?", &args)?;
+    Ok(Bool(Path::new(&path).exists()))
+}
+
+/* ------------------------------ Tests ----------------------------------------------- */
+
+#[cfg(test)]
+use core::create_core_environment;
+
+#[test]
+fn test_capabilities_are_not_granted_by_default() {
+    let mut env = create_core_environment();
+    let err = ::eval_risp_script("(env/get \"HOME\")", &mut env).unwrap_err();
+    assert_eq!(err, error("Capability env not granted, which is needed by env/get").with_kind(ErrorKind::CapabilityNotGranted));
+    assert_eq!(err.kind(), ErrorKind::CapabilityNotGranted);
+    assert_eq!(::eval_risp_script("time/now", &mut env), Err(error("Capability time not granted, which is needed by time/now").with_kind(ErrorKind::CapabilityNotGranted)));
+    assert_eq!(::eval_risp_script("(fs/read \"Cargo.toml\")", &mut env),
+               Err(error("Capability fs-read not granted, which is needed by fs/read").with_kind(ErrorKind::CapabilityNotGranted)));
+}
+
+#[test]
+fn test_granted_capabilities() {
+    let mut env = create_core_environment();
+    for capability in &ALL_CAPABILITIES {
+        env.grant(*capability);
+    }
+    env::set_var("RISP_TEST_CAPABILITY", "granted");
+    assert_eq!(::eval_risp_script("(env/get \"RISP_TEST_CAPABILITY\")", &mut env), Ok(string("granted")));
+    assert_eq!(::eval_risp_script("(env/get \"RISP_TEST_UNSET\")", &mut env), Ok(Nil));
+    assert_eq!(::eval_risp_script("(env/get 1)", &mut env), error_result("env/get wants a String but got 1"));
+    assert!(matches!(::eval_risp_script("(time/now)", &mut env), Ok(Int(millis)) if millis > 1_500_000_000_000));
+    assert!(matches!(::eval_risp_script("(random/seed)", &mut env), Ok(Int(seed)) if seed >= 0));
+    assert_eq!(::eval_risp_script("(fs/exists? \"Cargo.toml\")", &mut env), Ok(Bool(true)));
+    assert!(matches!(::eval_risp_script("(fs/read \"Cargo.toml\")", &mut env), Ok(Str(ref s)) if s.contains("[package]")));
+    assert!(::eval_risp_script("(fs/read \"missing.risp\")", &mut env).unwrap_err().to_string().starts_with("fs/read can't read missing.risp: "));
+}
+
+#[test]
+fn test_capability_names() {
+    assert_eq!(Capability::from_name("fs-read"), Some(Capability::FsRead));
+    assert_eq!(Capability::from_name("network"), None);
+    assert_eq!(required_capability("random/seed"), Some(Capability::Random));
+    assert_eq!(required_capability("rep"), None);
+}
@@ -2,78 +2,313 @@ use types::RispType;
 use types::RispType::*;
 use types::RispError;
 use types::*;
-use std::collections::HashMap;
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::convert::TryFrom;
+use std::hash::Hash;
+use std::iter::FromIterator;
 
 
-impl Into<Result<RispType, RispError>> for RispType {
-    fn into(self) -> Result<RispType, RispError> {
-        Ok(self)
+/// Conversion of evaluated risp values into Rust values, can be derived with risp-derive.
+pub trait FromRisp: Sized {
+    fn from_risp(risp: RispType) -> Result<Self, RispError>;
+}
+
+/// Conversion of Rust values into risp values, can be derived with risp-derive.
+pub trait IntoRisp {
+    fn into_risp(self) -> RispType;
+}
+
+impl FromRisp for RispType {
+    fn from_risp(risp: RispType) -> Result<RispType, RispError> {
+        Ok(risp)
     }
 }
 
-impl Into<Result<bool, RispError>> for RispType {
-    fn into(self) -> Result<bool, RispError> {
-        match self {
+impl FromRisp for bool {
+    fn from_risp(risp: RispType) -> Result<bool, RispError> {
+        match risp {
             Bool(b) => Ok(b),
-            _ => Err(error(format!("Expected Bool but got {:?}", self))),
+            _ => Err(error(format!("Expected Bool but got {:?}", risp))),
         }
     }
 }
 
-
-impl Into<Result<i64, RispError>> for RispType {
-    fn into(self) -> Result<i64, RispError> {
-        match self {
+impl FromRisp for i64 {
+    fn from_risp(risp: RispType) -> Result<i64, RispError> {
+        match risp {
             Int(int) => Ok(int),
-            _ => Err(error(format!("Expected Int but got {:?}", self))),
+            _ => Err(error(format!("Expected Int but got {:?}", risp))),
         }
     }
 }
 
-impl Into<Result<String, RispError>> for RispType {
-    fn into(self) -> Result<String, RispError> {
-        match self {
+impl FromRisp for String {
+    fn from_risp(risp: RispType) -> Result<String, RispError> {
+        match risp {
             Str(s) => Ok(s),
-            _ => Err(error(format!("Expected String but got {:?}", self))),
+            _ => Err(error(format!("Expected String but got {:?}", risp))),
         }
     }
 }
 
-impl Into<Result<HashMap<String, RispType>, RispError>> for RispType {
-    fn into(self) -> Result<HashMap<String, RispType>, RispError> {
-        match self {
+impl FromRisp for IndexMap<RispType, RispType> {
+    fn from_risp(risp: RispType) -> Result<IndexMap<RispType, RispType>, RispError> {
+        match risp {
             Map(map) => Ok(map),
-            _ => Err(error(format!("Expected Map but got {:?}", self)))
+            _ => Err(error(format!("Expected Map but got {:?}", risp)))
+        }
+    }
+}
+
+/// Keyword and string keys are converted into strings, so :a and "a" in the same map are an error.
+impl<T: FromRisp> FromRisp for HashMap<String, T> {
+    fn from_risp(risp: RispType) -> Result<HashMap<String, T>, RispError> {
+        let mut result = HashMap::new();
+        for (key, value) in IndexMap::<RispType, RispType>::from_risp(risp)? {
+            match key {
+                Keyword(key_string) | Str(key_string) => {
+                    if result.contains_key(&key_string) {
+                        return Err(error(format!("Duplicate key {} as Keyword and String", key_string)));
+                    }
+                    let value = T::from_risp(value).map_err(|e| e.at_key(key_string.as_str()))?;
+                    result.insert(key_string, value);
+                }
+                _ => return Err(error(format!("Expected Keyword or String as key but got {:?}", key)))
+            }
+        }
+        Ok(result)
+    }
+}
+
+/// Nil becomes None.
+impl<T: FromRisp> FromRisp for Option<T> {
+    fn from_risp(risp: RispType) -> Result<Option<T>, RispError> {
+        match risp {
+            Nil => Ok(None),
+            _ => T::from_risp(risp).map(Some)
+        }
+    }
+}
+
+/// Strings with a single character.
+impl FromRisp for char {
+    fn from_risp(risp: RispType) -> Result<char, RispError> {
+        if let Str(ref s) = risp {
+            let mut chars = s.chars();
+            if let (Some(c), None) = (chars.next(), chars.next()) {
+                return Ok(c);
+            }
         }
+        Err(error(format!("Expected String with a single character but got {:?}", risp)))
+    }
+}
+
+macro_rules! impl_int_conversions {
+    ($($int_type:ident)*) => ($(
+        /// Ints outside of the range of the type are an error.
+        impl FromRisp for $int_type {
+            fn from_risp(risp: RispType) -> Result<$int_type, RispError> {
+                let int = i64::from_risp(risp)?;
+                $int_type::try_from(int).map_err(|_| error(format!(
+                    "Expected {} between {} and {} but got {}",
+                    stringify!($int_type), $int_type::MIN, $int_type::MAX, int)))
+            }
+        }
+    )*)
+}
+
+impl_int_conversions!(u8 u16 u32 u64 usize i8 i16 i32);
+
+macro_rules! impl_tuple_conversions {
+    ($(($len:expr, $($name:ident)+))*) => ($(
+        /// Vectors with exactly as many elements as the tuple.
+        impl<$($name: FromRisp),+> FromRisp for ($($name,)+) {
+            fn from_risp(risp: RispType) -> Result<($($name,)+), RispError> {
+                match risp {
+                    Vector(vector) if vector.len() == $len => {
+                        let mut elements = vector.into_iter().enumerate();
+                        Ok(($({
+                            let (index, element) = elements.next().unwrap();
+                            $name::from_risp(element).map_err(|e| e.at_index(index))?
+                        },)+))
+                    }
+                    _ => Err(error(format!("Expected Vector with {} elements but got {:?}", $len, risp)))
+                }
+            }
+        }
+
+        #[allow(non_snake_case)]
+        impl<$($name: IntoRisp),+> IntoRisp for ($($name,)+) {
+            fn into_risp(self) -> RispType {
+                let ($($name,)+) = self;
+                Vector(vec![$($name.into_risp()),+])
+            }
+        }
+    )*)
+}
+
+impl_tuple_conversions!((1, A) (2, A B) (3, A B C) (4, A B C D) (5, A B C D E) (6, A B C D E F));
+
+/// Converts a keyword into one of the given values, e.g. `from_keyword(risp, &[("kick", Drum::Kick)])`.
+pub fn from_keyword<T: Clone>(risp: RispType, variants: &[(&str, T)]) -> Result<T, RispError> {
+    if let Keyword(ref name) = risp {
+        if let Some((_, value)) = variants.iter().find(|&&(variant_name, _)| variant_name == name) {
+            return Ok(value.clone());
+        }
+    }
+    let names: Vec<String> = variants.iter().map(|&(name, _)| format!(":{}", name)).collect();
+    Err(error(format!("Expected one of {} but got {:?}", names.join(" "), risp)))
+}
+
+/// Converts a value into the keyword of the matching variant, nil if there is none.
+pub fn to_keyword<T: PartialEq>(value: &T, variants: &[(&str, T)]) -> RispType {
+    variants.iter()
+        .find(|&(_, variant_value)| variant_value == value)
+        .map_or(Nil, |&(name, _)| keyword(name))
+}
+
+impl<T: FromRisp> FromRisp for Vec<T> {
+    fn from_risp(risp: RispType) -> Result<Vec<T>, RispError> {
+        match risp {
+            Vector(vector) => convert_elements(vector),
+            _ => Err(error(format!("Expected Vector but got {:?}", risp)))
+        }
+    }
+}
+
+impl<T: FromRisp + Ord> FromRisp for BTreeSet<T> {
+    fn from_risp(risp: RispType) -> Result<BTreeSet<T>, RispError> {
+        convert_set(risp)
+    }
+}
+
+impl<T: FromRisp + Eq + Hash> FromRisp for HashSet<T> {
+    fn from_risp(risp: RispType) -> Result<HashSet<T>, RispError> {
+        convert_set(risp)
+    }
+}
+
+/// Sets can be converted from risp sets and vectors.
+fn convert_set<T: FromRisp, S: FromIterator<T>>(risp: RispType) -> Result<S, RispError> {
+    let elements: Vec<RispType> = match risp {
+        Set(set_value) => set_value.into_iter().collect(),
+        Vector(vector) => vector,
+        _ => return Err(error(format!("Expected Set but got {:?}", risp)))
+    };
+    convert_elements(elements)
+}
+
+/// Converts each element, errors contain the index of the failing element.
+fn convert_elements<T: FromRisp, C: FromIterator<T>>(elements: Vec<RispType>) -> Result<C, RispError> {
+    elements.into_iter()
+        .enumerate()
+        .map(|(index, element)| T::from_risp(element).map_err(|e| e.at_index(index)))
+        .collect()
+}
+
+
+impl IntoRisp for RispType {
+    fn into_risp(self) -> RispType {
+        self
+    }
+}
+
+impl IntoRisp for bool {
+    fn into_risp(self) -> RispType {
+        Bool(self)
+    }
+}
+
+impl IntoRisp for i64 {
+    fn into_risp(self) -> RispType {
+        Int(self)
     }
 }
 
-impl Into<Result<Vec<RispType>, RispError>> for RispType {
-    fn into(self) -> Result<Vec<RispType>, RispError> {
-        match self {
-            Vector(vector) => Ok(vector),
-            _ => Err(error(format!("Expected Vector but got {:?}", self)))
+macro_rules! impl_int_into_risp {
+    ($($int_type:ident)*) => ($(
+        impl IntoRisp for $int_type {
+            fn into_risp(self) -> RispType {
+                Int(i64::from(self))
+            }
         }
+    )*)
+}
+
+impl_int_into_risp!(u8 u16 u32 i8 i16 i32);
+
+impl IntoRisp for char {
+    fn into_risp(self) -> RispType {
+        Str(self.to_string())
     }
 }
 
-impl Into<Result<Vec<i64>, RispError>> for RispType {
-    fn into(self) -> Result<Vec<i64>, RispError> {
-        let vec_of_risp: Result<Vec<RispType>, _> = self.into();
-        vec_of_risp?.iter().cloned()
-            .map(|el| el.into())
-            .collect::<Result<Vec<i64>, _>>()
+impl IntoRisp for String {
+    fn into_risp(self) -> RispType {
+        Str(self)
+    }
+}
+
+impl IntoRisp for &str {
+    fn into_risp(self) -> RispType {
+        string(self)
+    }
+}
+
+impl IntoRisp for IndexMap<RispType, RispType> {
+    fn into_risp(self) -> RispType {
+        Map(self)
+    }
+}
+
+/// Keys become keywords.
+impl<T: IntoRisp> IntoRisp for HashMap<String, T> {
+    fn into_risp(self) -> RispType {
+        let mut entries: Vec<(String, T)> = self.into_iter().collect();
+        entries.sort_by(|e1, e2| e1.0.cmp(&e2.0));
+        Map(entries.into_iter().map(|(key, value)| (keyword(key), value.into_risp())).collect())
+    }
+}
+
+impl<T: IntoRisp> IntoRisp for Vec<T> {
+    fn into_risp(self) -> RispType {
+        Vector(self.into_iter().map(T::into_risp).collect())
+    }
+}
+
+impl<T: IntoRisp> IntoRisp for BTreeSet<T> {
+    fn into_risp(self) -> RispType {
+        Set(self.into_iter().map(T::into_risp).collect())
+    }
+}
+
+impl<T: IntoRisp> IntoRisp for HashSet<T> {
+    fn into_risp(self) -> RispType {
+        Set(self.into_iter().map(T::into_risp).collect())
+    }
+}
+
+/// None becomes nil.
+impl<T: IntoRisp> IntoRisp for Option<T> {
+    fn into_risp(self) -> RispType {
+        self.map_or(Nil, T::into_risp)
     }
 }
 
 
 impl RispType {
-    pub fn get<T>(&self, key: &str) -> Result<Option<T>, RispError> where RispType: Into<Result<T, RispError>> {
+    /// Returns the value for the keyword key (without the colon).
+    pub fn get<T: FromRisp>(&self, key: &str) -> Result<Option<T>, RispError> {
+        self.get_by_key(&keyword(key))
+    }
+
+    /// Returns the value for a key of any type.
+    pub fn get_by_key<T: FromRisp>(&self, key: &RispType) -> Result<Option<T>, RispError> {
         match *self {
             Map(ref map) => {
                 match map.get(key).cloned() {
                     Some(risp_value) => {
-                        Ok(Some(risp_value.into()?))
+                        Ok(Some(T::from_risp(risp_value).map_err(|e| e.at_key(path_key(key)))?))
                     }
                     None => Ok(None)
                 }
@@ -81,22 +316,107 @@ impl RispType {
             _ => Err(error(format!("Expected Map but got {:?}", self)))
         }
     }
+
+    /// Returns the value for the keyword key or the default, if the key is missing.
+    pub fn get_or<T: FromRisp>(&self, key: &str, default: T) -> Result<T, RispError> {
+        Ok(self.get(key)?.unwrap_or(default))
+    }
+
+    /// Returns the value for the keyword key, a missing key is an error.
+    pub fn require<T: FromRisp>(&self, key: &str) -> Result<T, RispError> {
+        self.get(key)?.ok_or_else(|| error(format!("Missing required key :{}", key)))
+    }
+
+    /// Returns the nested value at the path of map keys and vector indices, e.g. `&["effects", "0", "trigger"]`.
+    /// Map keys are looked up as keywords, strings and ints.
+    pub fn get_in<T: FromRisp>(&self, path: &[&str]) -> Result<Option<T>, RispError> {
+        let mut current = self;
+        let mut visited: Vec<PathSegment> = vec![];
+        for &segment in path {
+            let next = match *current {
+                Map(ref map) => {
+                    visited.push(PathSegment::Key(segment));
+                    lookup_key(map, segment)
+                }
+                Vector(ref elements) | List(ref elements) => {
+                    let index = segment.parse::<usize>()
+                        .map_err(|_| with_path(error(format!("Expected index but got {}", segment)), &visited))?;
+                    visited.push(PathSegment::Index(index));
+                    elements.get(index)
+                }
+                _ => return Err(with_path(error(format!("Expected Map or Vector but got {:?}", current)), &visited))
+            };
+            match next {
+                Some(value) => current = value,
+                None => return Ok(None)
+            }
+        }
+        T::from_risp(current.clone())
+            .map(Some)
+            .map_err(|e| with_path(e, &visited))
+    }
+
+    /// Like `get_in` with a path like `effects[0].trigger` or `effects.0.trigger`.
+    pub fn get_path<T: FromRisp>(&self, path: &str) -> Result<Option<T>, RispError> {
+        let segments: Vec<&str> = path.split(['.', '[', ']'])
+            .filter(|segment| !segment.is_empty())
+            .collect();
+        self.get_in(&segments)
+    }
+}
+
+enum PathSegment<'a> {
+    Key(&'a str),
+    Index(usize),
+}
+
+fn with_path(mut err: RispError, path: &[PathSegment]) -> RispError {
+    for segment in path.iter().rev() {
+        err = match *segment {
+            PathSegment::Key(key) => err.at_key(key),
+            PathSegment::Index(index) => err.at_index(index)
+        };
+    }
+    err
+}
+
+fn lookup_key<'a>(map: &'a IndexMap<RispType, RispType>, key: &str) -> Option<&'a RispType> {
+    map.get(&keyword(key))
+        .or_else(|| map.get(&string(key)))
+        .or_else(|| key.parse().ok().and_then(|int| map.get(&Int(int))))
+}
+
+/// Keywords are shown without colon in error paths.
+pub fn path_key(key: &RispType) -> String {
+    match *key {
+        Keyword(ref name) => name.clone(),
+        _ => key.to_string()
+    }
 }
 
 
-pub fn flatten_into<T>(risp_vec_input: RispType) -> Result<Vec<T>, RispError>
-    where RispType: Into<Result<T, RispError>> {
+pub fn flatten_into<T: FromRisp>(risp_vec_input: RispType) -> Result<Vec<T>, RispError> {
     match risp_vec_input {
         Vector(vector) => {
-            let flat = flatten_vec(vector);
-            flat.iter().cloned()
-                .map(|el| el.into())
-                .collect()
+            let mut result = vec![];
+            flatten_elements_into(vector, &mut result)?;
+            Ok(result)
         }
         _ => Err(error(format!("Expected Vector but got {:?}", risp_vec_input)))
     }
 }
 
+/// Converts while flattening, so that errors have the path of the nested element, e.g. [1][0].
+fn flatten_elements_into<T: FromRisp>(vector: Vec<RispType>, result: &mut Vec<T>) -> Result<(), RispError> {
+    for (index, element) in vector.into_iter().enumerate() {
+        match element {
+            Vector(children) => flatten_elements_into(children, result),
+            _ => T::from_risp(element).map(|converted| result.push(converted))
+        }.map_err(|e| e.at_index(index))?;
+    }
+    Ok(())
+}
+
 pub fn flatten_vec(risp_vec: Vec<RispType>) -> Vec<RispType> {
     let mut result = vec![];
     for el in risp_vec {
@@ -117,44 +437,44 @@ pub fn flatten_vec(risp_vec: Vec<RispType>) -> Vec<RispType> {
 
 #[test]
 fn test_convert_int() {
-    let result: Result<i64, _> = Int(3).into();
+    let result = i64::from_risp(Int(3));
     assert_eq!(result, Ok(3));
 }
 
 #[test]
 fn test_convert_int_error() {
-    let result: Result<i64, RispError> = List(vec![]).into();
+    let result = i64::from_risp(List(vec![]));
     assert!(result.is_err());
 }
 
 
 #[test]
 fn test_convert_string() {
-    let result: Result<String, _> = string("string").into();
+    let result = String::from_risp(string("string"));
     assert_eq!(result, Ok("string".to_string()));
 }
 
 #[test]
 fn test_convert_string_error() {
-    let result: Result<String, RispError> = List(vec![]).into();
+    let result = String::from_risp(List(vec![]));
     assert!(result.is_err());
 }
 
 #[test]
 fn test_convert_vector() {
-    let result: Result<Vec<_>, RispError> = Vector(vec![Int(23)]).into();
+    let result = <Vec<_>>::from_risp(Vector(vec![Int(23)]));
     assert_eq!(result, Ok(vec![Int(23)]));
 }
 
 #[test]
 fn test_convert_vector_error() {
-    let result: Result<Vec<RispType>, RispError> = Int(1).into();
+    let result = <Vec<RispType>>::from_risp(Int(1));
     assert!(result.is_err());
 }
 
 #[test]
 fn test_convert_vector_int() {
-    let result: Result<Vec<i64>, RispError> = Vector(vec![Int(23)]).into();
+    let result = <Vec<i64>>::from_risp(Vector(vec![Int(23)]));
     assert_eq!(result, Ok(vec![23]));
 }
 
@@ -163,14 +483,14 @@ fn test_convert_map() {
     let input_map = map(vec![
         ("key", Int(23))
     ]);
-    let result: Result<HashMap<String, RispType>, RispError> = input_map.into();
+    let result = <HashMap<String, RispType>>::from_risp(input_map);
     assert_eq!(result.unwrap().get("key").unwrap().clone(), Int(23));
 }
 
 #[test]
 fn test_convert_map_error() {
-    let result: Result<HashMap<String, RispType>, RispError> = List(vec![]).into();
-    assert_eq!(result, Err(error(format!("Expected Map but got List([])"))));
+    let result = <HashMap<String, RispType>>::from_risp(List(vec![]));
+    assert_eq!(result, Err(error("Expected Map but got List([])")));
 }
 
 #[test]
@@ -206,14 +526,14 @@ fn test_get_error_expected_int() {
         ("key", string("string"))
     ]);
     let int_result: Result<Option<i64>, _> = input_map.get("key");
-    assert_eq!(int_result, Err(error(format!("Expected Int but got {:?}", string("string")))));
+    assert_eq!(int_result, Err(error(format!("Expected Int but got {:?}", string("string"))).at_key("key")));
 }
 
 #[test]
 fn test_get_error_expected_map() {
     let input = Int(123);
     let int_result: Result<Option<i64>, _> = input.get("key");
-    assert_eq!(int_result, Err(error(format!("Expected Map but got Int(123)"))));
+    assert_eq!(int_result, Err(error("Expected Map but got Int(123)")));
 }
 
 
@@ -250,5 +570,194 @@ fn test_flatten_error_inner() {
         Vector(vec![string("string"), Int(3)])
     ]);
     let flat_result: Result<Vec<i64>, _> = flatten_into(input);
-    assert_eq!(flat_result, Err(error("Expected Int but got Str(\"string\")")));
+    assert_eq!(flat_result, Err(error("Expected Int but got Str(\"string\")").at_index(0).at_index(1)));
+}
+
+#[test]
+fn test_flatten_error_has_nested_path() {
+    let input = Vector(vec![
+        Vector(vec![Int(1), Int(2)]),
+        Vector(vec![Int(3), string("x")])
+    ]);
+    let flat_result: Result<Vec<i64>, _> = flatten_into(input);
+    assert_eq!(flat_result.unwrap_err().to_string(), "Expected Int but got Str(\"x\") at [1][1]");
+}
+
+#[test]
+fn test_convert_hash_set() {
+    let result = <HashSet<i64>>::from_risp(set(vec![Int(1), Int(2)]));
+    assert_eq!(result, Ok(vec![1, 2].into_iter().collect()));
+}
+
+#[test]
+fn test_convert_btree_set() {
+    let result = <BTreeSet<String>>::from_risp(set(vec![string("b"), string("a")]));
+    assert_eq!(result, Ok(vec!["a".to_string(), "b".to_string()].into_iter().collect()));
+}
+
+#[test]
+fn test_convert_set_from_vector() {
+    let result = <BTreeSet<i64>>::from_risp(Vector(vec![Int(2), Int(1), Int(2)]));
+    assert_eq!(result, Ok(vec![1, 2].into_iter().collect()));
+}
+
+#[test]
+fn test_convert_set_errors() {
+    let result = <HashSet<i64>>::from_risp(Int(1));
+    assert_eq!(result, Err(error("Expected Set but got Int(1)")));
+    let result = <HashSet<i64>>::from_risp(set(vec![string("a")]));
+    assert_eq!(result, Err(error("Expected Int but got Str(\"a\")").at_index(0)));
+}
+
+#[test]
+fn test_convert_map_string_keys() {
+    let input_map = map_from(vec![
+        (keyword("keyword"), Int(1)),
+        (string("string"), Int(2))
+    ]);
+    let result: HashMap<String, RispType> = HashMap::from_risp(input_map).unwrap();
+    assert_eq!(result.get("keyword"), Some(&Int(1)));
+    assert_eq!(result.get("string"), Some(&Int(2)));
+}
+
+#[test]
+fn test_convert_map_string_keys_error() {
+    let result = <HashMap<String, RispType>>::from_risp(map_from(vec![(Int(1), Int(2))]));
+    assert_eq!(result, Err(error("Expected Keyword or String as key but got Int(1)")));
+}
+
+#[test]
+fn test_convert_map_string_keys_duplicate() {
+    let input_map = map_from(vec![(keyword("a"), Int(1)), (string("a"), Int(2))]);
+    let result = <HashMap<String, RispType>>::from_risp(input_map);
+    assert_eq!(result, Err(error("Duplicate key a as Keyword and String")));
+}
+
+#[test]
+fn test_get_by_key() {
+    let input_map = map_from(vec![
+        (Int(36), string("kick")),
+        (string("key"), Int(1))
+    ]);
+    assert_eq!(input_map.get_by_key(&Int(36)).unwrap(), Some("kick".to_string()));
+    assert_eq!(input_map.get_by_key::<i64>(&string("key")).unwrap(), Some(1));
+    assert_eq!(input_map.get::<i64>("key").unwrap(), None);
+}
+
+#[test]
+fn test_convert_nested_generics() {
+    assert_eq!(<Vec<String>>::from_risp(Vector(vec![string("a"), string("b")])), Ok(vec!["a".to_string(), "b".to_string()]));
+    assert_eq!(<Vec<Vec<i64>>>::from_risp(Vector(vec![Vector(vec![Int(1)]), Vector(vec![])])), Ok(vec![vec![1], vec![]]));
+    assert_eq!(<Vec<bool>>::from_risp(Vector(vec![Bool(true), Bool(false)])), Ok(vec![true, false]));
+    assert_eq!(<Vec<i64>>::from_risp(Vector(vec![Int(1), string("2")])), Err(error("Expected Int but got Str(\"2\")").at_index(1)));
+
+    let result = <HashMap<String, i64>>::from_risp(map(vec![("kick", Int(36)), ("snare", Int(38))])).unwrap();
+    assert_eq!(result.get("snare"), Some(&38));
+    assert_eq!(<HashMap<String, i64>>::from_risp(map(vec![("kick", Nil)])), Err(error("Expected Int but got Nil").at_key("kick")));
+}
+
+#[test]
+fn test_convert_option() {
+    assert_eq!(<Option<i64>>::from_risp(Nil), Ok(None));
+    assert_eq!(<Option<i64>>::from_risp(Int(1)), Ok(Some(1)));
+    assert_eq!(<Vec<Option<i64>>>::from_risp(Vector(vec![Int(1), Nil])), Ok(vec![Some(1), None]));
+    assert_eq!(map(vec![("offset", Nil)]).get::<Option<i64>>("offset"), Ok(Some(None)));
+}
+
+#[test]
+fn test_convert_tuples() {
+    assert_eq!(<(i64, String)>::from_risp(Vector(vec![Int(36), string("kick")])), Ok((36, "kick".to_string())));
+    assert_eq!(<Vec<(u8, u8)>>::from_risp(Vector(vec![Vector(vec![Int(36), Int(100)])])), Ok(vec![(36, 100)]));
+    assert_eq!(<(i64, i64)>::from_risp(Vector(vec![Int(1)])), Err(error("Expected Vector with 2 elements but got Vector([Int(1)])")));
+    assert_eq!((36, "kick", true).into_risp(), Vector(vec![Int(36), string("kick"), Bool(true)]));
+}
+
+#[test]
+fn test_convert_ranged_ints() {
+    assert_eq!(u8::from_risp(Int(127)), Ok(127));
+    assert_eq!(u8::from_risp(Int(256)), Err(error("Expected u8 between 0 and 255 but got 256")));
+    assert_eq!(u32::from_risp(Int(-1)), Err(error("Expected u32 between 0 and 4294967295 but got -1")));
+    assert_eq!(i32::from_risp(Int(-5)), Ok(-5));
+    assert_eq!(usize::from_risp(Int(3)), Ok(3));
+    assert_eq!(usize::from_risp(string("3")), Err(error("Expected Int but got Str(\"3\")")));
+    assert_eq!(vec![1u8, 2].into_risp(), Vector(vec![Int(1), Int(2)]));
+}
+
+#[test]
+fn test_convert_char() {
+    assert_eq!(char::from_risp(string("x")), Ok('x'));
+    assert_eq!(char::from_risp(string("xy")), Err(error("Expected String with a single character but got Str(\"xy\")")));
+    assert_eq!('x'.into_risp(), string("x"));
+}
+
+#[cfg(test)]
+#[derive(Clone, Debug, PartialEq)]
+enum Drum {
+    Kick,
+    Snare,
+}
+
+#[test]
+fn test_from_keyword() {
+    let drums = [("kick", Drum::Kick), ("snare", Drum::Snare)];
+    assert_eq!(from_keyword(keyword("snare"), &drums), Ok(Drum::Snare));
+    assert_eq!(from_keyword(keyword("hat"), &drums), Err(error("Expected one of :kick :snare but got Keyword(\"hat\")")));
+    assert_eq!(to_keyword(&Drum::Kick, &drums), keyword("kick"));
+}
+
+#[test]
+fn test_get_or_and_require() {
+    let input_map = map(vec![("speed", Int(220))]);
+    assert_eq!(input_map.get_or("speed", 120), Ok(220));
+    assert_eq!(input_map.get_or("volume", 100), Ok(100));
+    assert_eq!(input_map.require::<i64>("speed"), Ok(220));
+    assert_eq!(input_map.require::<i64>("volume"), Err(error("Missing required key :volume")));
+    assert_eq!(input_map.require::<String>("speed"), Err(error("Expected String but got Int(220)").at_key("speed")));
+}
+
+#[cfg(test)]
+fn song_map() -> RispType {
+    map(vec![
+        ("effects", Vector(vec![
+            map(vec![("trigger", Int(43)), ("notes", Vector(vec![Int(45), string("57")]))]),
+        ])),
+        ("drums", map_from(vec![(Int(36), string("kick"))])),
+    ])
+}
+
+#[test]
+fn test_get_in() {
+    let song = song_map();
+    assert_eq!(song.get_in(&["effects", "0", "trigger"]), Ok(Some(43)));
+    assert_eq!(song.get_in::<i64>(&["effects", "1", "trigger"]), Ok(None));
+    assert_eq!(song.get_in::<i64>(&["effects", "0", "speed"]), Ok(None));
+    assert_eq!(song.get_in(&["drums", "36"]), Ok(Some("kick".to_string())));
+    assert_eq!(song.get_in::<RispType>(&[]), Ok(Some(song.clone())));
+}
+
+#[test]
+fn test_get_in_errors() {
+    let song = song_map();
+    assert_eq!(song.get_in::<Vec<i64>>(&["effects", "0", "notes"]),
+               Err(error("Expected Int but got Str(\"57\")").at_index(1).at_key("notes").at_index(0).at_key("effects")));
+    assert_eq!(song.get_in::<i64>(&["effects", "first"]), Err(error("Expected index but got first").at_key("effects")));
+    assert_eq!(song.get_in::<i64>(&["effects", "0", "trigger", "x"]),
+               Err(error("Expected Map or Vector but got Int(43)").at_key("trigger").at_index(0).at_key("effects")));
+}
+
+#[test]
+fn test_get_path() {
+    let song = song_map();
+    assert_eq!(song.get_path("effects[0].trigger"), Ok(Some(43)));
+    assert_eq!(song.get_path("effects.0.notes.0"), Ok(Some(45)));
+    assert_eq!(song.get_path::<i64>("effects[0].notes[1]"), Err(error("Expected Int but got Str(\"57\")").at_index(1).at_key("notes").at_index(0).at_key("effects")));
+}
+
+#[test]
+fn test_error_path() {
+    let err = error("Expected Int").at_key("trigger").at_index(2).at_key("effects");
+    assert_eq!(err.message(), "Expected Int");
+    assert_eq!(err.path(), Some("effects[2].trigger".to_string()));
+    assert_eq!(err.to_string(), "Expected Int at effects[2].trigger");
+    assert_eq!(error("Expected Int").path(), None);
 }
@@ -1,27 +1,36 @@
-#![cfg_attr(feature = "cargo-clippy", allow(needless_pass_by_value))]
+#![allow(clippy::needless_pass_by_value)]
 
+use std::convert::TryFrom;
 use std::iter;
 use environment::*;
+use convert::FromRisp;
+use json::{from_json, to_json};
+use music::add_music_functions;
+use random::add_random_functions;
+use rhythm::add_rhythm_functions;
+use limits::{check_collection_size, check_string_length};
+use schema::Schema;
 use types::*;
 use types::RispType::*;
 use std::cmp;
-use std::ops::{Add, Div, Mul, Sub};
+use std::collections::BTreeSet;
+use std::sync::atomic::{AtomicUsize, Ordering};
 
-type IntOperation = fn(i64, i64) -> i64;
+type IntOperation = fn(i64, i64) -> Option<i64>;
 
 fn sum(args: Vec<RispType>) -> RispResult {
-    apply_to_vector(i64::add, &args)
+    apply_to_vector(i64::checked_add, &args)
 }
 
 fn apply_to_vector(op: IntOperation, vec: &[RispType]) -> RispResult {
-    let s: RispResult = vec.get(0).ok_or_else(|| error("Missing first argument")).map(|x| x.clone());
+    let s: RispResult = vec.first().cloned().ok_or_else(|| error("Missing first argument"));
     vec.iter().skip(1).fold(s, |acc, x| apply_to(op, &acc?, x))
 }
 
 fn apply_to(op: IntOperation, x1: &RispType, x2: &RispType) -> RispResult {
     match *x1 {
         Int(x1_int) => match *x2 {
-            Int(x2_int) => Ok(Int(op(x1_int, x2_int))),
+            Int(x2_int) => apply_to_numbers(op, x1_int, x2_int),
             Vector(ref x2_vec) => apply_to_number_and_vector(op, x1_int, x2_vec),
             _ => error_result(format!("Operation wants an Int or a Vector as second argument but got {:?}", x2))
         },
@@ -34,6 +43,15 @@ fn apply_to(op: IntOperation, x1: &RispType, x2: &RispType) -> RispResult {
     }
 }
 
+/// Fails instead of panicking on division by zero and overflow.
+fn apply_to_numbers(op: IntOperation, x1: i64, x2: i64) -> RispResult {
+    match op(x1, x2) {
+        Some(result) => Ok(Int(result)),
+        None if x2 == 0 => error_result(format!("Division of {} by zero", x1)),
+        None => error_result(format!("Operation on {} and {} overflows", x1, x2))
+    }
+}
+
 fn apply_to_number_and_vector(op: IntOperation, x_int: i64, xs: &[RispType]) -> RispResult {
     let x = Int(x_int);
     xs.iter().map(|x2| apply_to(op, &x, x2))
@@ -49,6 +67,9 @@ fn apply_to_vector_and_number(op: IntOperation, xs: &[RispType], x_int: i64) ->
 }
 
 fn apply_to_vector_and_vector(op: IntOperation, xs1: &[RispType], xs2: &[RispType]) -> RispResult {
+    if xs1.is_empty() || xs2.is_empty() {
+        return error_result(format!("Operation wants non-empty Vectors but got {:?} and {:?}", Vector(xs1.to_vec()), Vector(xs2.to_vec())));
+    }
     let result_len = cmp::max(xs1.len(), xs2.len());
     (0..result_len)
         .map(|i| apply_to(op, &xs1[i % xs1.len()], &xs2[i % xs2.len()]))
@@ -57,22 +78,24 @@ fn apply_to_vector_and_vector(op: IntOperation, xs1: &[RispType], xs2: &[RispTyp
 }
 
 fn mul(vec: Vec<RispType>) -> RispResult {
-    apply_to_vector(i64::mul, &vec)
+    apply_to_vector(i64::checked_mul, &vec)
 }
 
 fn div(vec: Vec<RispType>) -> RispResult {
-    apply_to_vector(i64::div, &vec)
+    apply_to_vector(i64::checked_div, &vec)
 }
 
 fn sub(vec: Vec<RispType>) -> RispResult {
-    apply_to_vector(i64::sub, &vec)
+    apply_to_vector(i64::checked_sub, &vec)
 }
 
 fn rep(args: Vec<RispType>) -> RispResult {
     if let Some((n, elements)) = args.split_first() {
         match *n {
             Int(n2) => {
-                Ok(Vector(repeated(elements, n2 as usize)))
+                let times = usize::try_from(n2).map_err(|_| error(format!("rep needs a non-negative count but got {}", n2)))?;
+                check_collection_size("rep", times.checked_mul(elements.len()))?;
+                Ok(Vector(repeated(elements, times)))
             }
             _ => error_result("rep needs an int as first argument")
         }
@@ -81,12 +104,190 @@ fn rep(args: Vec<RispType>) -> RispResult {
     }
 }
 
+fn collection_len(coll: &RispType) -> usize {
+    match *coll {
+        List(ref elements) | Vector(ref elements) => elements.len(),
+        Set(ref set_value) => set_value.len(),
+        Map(ref map_value) => map_value.len(),
+        _ => 0
+    }
+}
+
 fn repeated<T: Clone>(pattern: &[T], times: usize) -> Vec<T> {
-    concat(iter::repeat(pattern.to_vec()).take(times).collect())
+    concat(iter::repeat_n(pattern.to_vec(), times).collect())
 }
 
 fn concat<T: Clone>(input: Vec<Vec<T>>) -> Vec<T> {
-    input.into_iter().flat_map(|x| x).collect()
+    input.into_iter().flatten().collect()
+}
+
+fn create_set(args: Vec<RispType>) -> RispResult {
+    match args.first() {
+        Some(List(elements)) | Some(Vector(elements)) => Ok(set(elements.clone())),
+        Some(Set(elements)) => Ok(Set(elements.clone())),
+        Some(Nil) => Ok(set(vec![])),
+        Some(other) => error_result(format!("set wants a List, Vector or Set but got {:?}", other)),
+        None => error_result("set needs 1 argument but got 0")
+    }
+}
+
+fn conj(args: Vec<RispType>) -> RispResult {
+    if let Some((coll, elements)) = args.split_first() {
+        check_collection_size("conj", collection_len(coll).checked_add(elements.len()))?;
+        match *coll {
+            Vector(ref vector) => Ok(Vector(vector.iter().chain(elements).cloned().collect())),
+            List(ref list) => Ok(List(elements.iter().rev().chain(list).cloned().collect())),
+            Set(ref set_value) => Ok(Set(set_value.iter().chain(elements).cloned().collect())),
+            Nil => Ok(List(elements.iter().rev().cloned().collect())),
+            _ => error_result(format!("conj wants a collection as first argument but got {:?}", coll))
+        }
+    } else {
+        error_result("conj needs at least 1 argument but got 0")
+    }
+}
+
+fn disj(args: Vec<RispType>) -> RispResult {
+    if let Some((set_arg, elements)) = args.split_first() {
+        match *set_arg {
+            Set(ref set_value) => Ok(Set(set_value.iter().filter(|el| !elements.contains(el)).cloned().collect())),
+            Nil => Ok(Nil),
+            _ => error_result(format!("disj wants a Set as first argument but got {:?}", set_arg))
+        }
+    } else {
+        error_result("disj needs at least 1 argument but got 0")
+    }
+}
+
+fn contains(args: Vec<RispType>) -> RispResult {
+    match (args.first(), args.get(1)) {
+        (Some(Set(set_value)), Some(element)) => Ok(Bool(set_value.contains(element))),
+        (Some(Map(map_value)), Some(key)) => Ok(Bool(map_value.contains_key(key))),
+        (Some(Nil), Some(_)) => Ok(Bool(false)),
+        (Some(other), Some(_)) => error_result(format!("contains? wants a Set or Map as first argument but got {:?}", other)),
+        _ => error_result(format!("contains? needs 2 arguments but got {}", args.len()))
+    }
+}
+
+fn set_args<'a>(name: &str, args: &'a [RispType]) -> Result<Vec<&'a BTreeSet<RispType>>, RispError> {
+    args.iter()
+        .map(|arg| match *arg {
+            Set(ref set_value) => Ok(set_value),
+            _ => Err(error(format!("{} wants only Sets as arguments but got {:?}", name, arg)))
+        })
+        .collect()
+}
+
+fn union(args: Vec<RispType>) -> RispResult {
+    let sets = set_args("union", &args)?;
+    check_collection_size("union", sets.iter().try_fold(0usize, |size, set_value| size.checked_add(set_value.len())))?;
+    Ok(Set(sets.into_iter().flat_map(|set_value| set_value.iter().cloned()).collect()))
+}
+
+fn intersection(args: Vec<RispType>) -> RispResult {
+    let sets = set_args("intersection", &args)?;
+    if let Some((first, others)) = sets.split_first() {
+        Ok(Set(first.iter().filter(|el| others.iter().all(|other| other.contains(el))).cloned().collect()))
+    } else {
+        error_result("intersection needs at least 1 argument but got 0")
+    }
+}
+
+fn difference(args: Vec<RispType>) -> RispResult {
+    let sets = set_args("difference", &args)?;
+    if let Some((first, others)) = sets.split_first() {
+        Ok(Set(first.iter().filter(|el| !others.iter().any(|other| other.contains(el))).cloned().collect()))
+    } else {
+        error_result("difference needs at least 1 argument but got 0")
+    }
+}
+
+/// Readable representation of all arguments separated by spaces, strings are quoted.
+fn pr_str(args: Vec<RispType>) -> RispResult {
+    checked_str("pr-str", join_args(&args, RispType::pr_str))
+}
+
+/// Concatenation of all arguments, strings are not quoted and nil is empty.
+fn str_concat(args: Vec<RispType>) -> RispResult {
+    let parts: Vec<String> = args.iter().map(to_plain_string).collect();
+    check_string_length("str", parts.iter().try_fold(0usize, |length, part| length.checked_add(part.len())))?;
+    Ok(Str(parts.concat()))
+}
+
+fn checked_str(name: &str, s: String) -> RispResult {
+    check_string_length(name, Some(s.len()))?;
+    Ok(Str(s))
+}
+
+fn print(args: Vec<RispType>) -> RispResult {
+    print!("{}", join_args(&args, to_plain_string));
+    Ok(Nil)
+}
+
+fn println(args: Vec<RispType>) -> RispResult {
+    println!("{}", join_args(&args, to_plain_string));
+    Ok(Nil)
+}
+
+fn to_plain_string(value: &RispType) -> String {
+    match *value {
+        Str(ref s) => s.clone(),
+        Nil => String::new(),
+        _ => value.to_string()
+    }
+}
+
+fn join_args(args: &[RispType], to_string: fn(&RispType) -> String) -> String {
+    args.iter().map(to_string).collect::<Vec<_>>().join(" ")
+}
+
+fn json_encode(args: Vec<RispType>) -> RispResult {
+    match args.len() {
+        1 => to_json(&args[0]).and_then(|json| checked_str("json/encode", json)),
+        len => error_result(format!("json/encode needs 1 argument but got {}", len))
+    }
+}
+
+fn json_decode(args: Vec<RispType>) -> RispResult {
+    match (args.first(), args.len()) {
+        (Some(Str(json)), 1) => from_json(json),
+        (Some(other), 1) => error_result(format!("json/decode wants a String but got {:?}", other)),
+        (_, len) => error_result(format!("json/decode needs 1 argument but got {}", len))
+    }
+}
+
+fn valid(args: Vec<RispType>) -> RispResult {
+    match args.len() {
+        2 => Ok(Bool(Schema::from_risp(args[0].clone())?.is_valid(&args[1]))),
+        len => error_result(format!("valid? needs 2 arguments but got {}", len))
+    }
+}
+
+/// Returns nil for valid values, otherwise a vector of {:path "effects[0].trigger" :message "..."} maps.
+fn explain(args: Vec<RispType>) -> RispResult {
+    match args.len() {
+        2 => match Schema::from_risp(args[0].clone())?.validate(&args[1]) {
+            Ok(()) => Ok(Nil),
+            Err(errors) => Ok(Vector(errors.iter()
+                .map(|e| map(vec![("path", e.path().map_or(Nil, Str)), ("message", string(e.message()))]))
+                .collect()))
+        },
+        len => error_result(format!("explain needs 2 arguments but got {}", len))
+    }
+}
+
+static GENSYM_COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+/// Returns a new unique symbol name starting with prefix.
+pub fn gensym_name(prefix: &str) -> String {
+    format!("{}__{}", prefix, GENSYM_COUNTER.fetch_add(1, Ordering::SeqCst) + 1)
+}
+
+fn gensym(args: Vec<RispType>) -> RispResult {
+    match args.first() {
+        None => Ok(symbol(gensym_name("G"))),
+        Some(Str(prefix)) => Ok(symbol(gensym_name(prefix))),
+        Some(other) => error_result(format!("gensym wants a string as prefix but got {:?}", other))
+    }
 }
 
 
@@ -97,15 +298,37 @@ pub fn create_core_environment() -> Environment {
     env.set("/", Function(div));
     env.set("-", Function(sub));
     env.set("rep", Function(rep));
+    env.set("gensym", Function(gensym));
+    env.set("set", Function(create_set));
+    env.set("conj", Function(conj));
+    env.set("disj", Function(disj));
+    env.set("contains?", Function(contains));
+    env.set("union", Function(union));
+    env.set("intersection", Function(intersection));
+    env.set("difference", Function(difference));
+    env.set("pr-str", Function(pr_str));
+    env.set("str", Function(str_concat));
+    env.set("print", Function(print));
+    env.set("println", Function(println));
+    env.set("json/encode", Function(json_encode));
+    env.set("json/decode", Function(json_decode));
+    env.set("valid?", Function(valid));
+    env.set("explain", Function(explain));
+    add_rhythm_functions(&mut env);
+    add_music_functions(&mut env);
+    add_random_functions(&mut env);
     env
 }
 
 
 /* ------------------------------ Tests ----------------------------------------------- */
 
+#[cfg(test)]
+use limits::{with_limits, EvalLimits};
+
 #[allow(dead_code)]
 fn sum2(x1: &RispType, x2: &RispType) -> RispResult {
-    apply_to(i64::add, x1, x2)
+    apply_to(i64::checked_add, x1, x2)
 }
 
 
@@ -169,7 +392,7 @@ fn test_sum2_errors() {
 
 #[test]
 fn test_add_number_to_vector() {
-    assert_eq!(apply_to_number_and_vector(i64::add, 20, &vec![Int(1), Int(2)]), Ok(Vector(vec![Int(21), Int(22)])));
+    assert_eq!(apply_to_number_and_vector(i64::checked_add, 20, &[Int(1), Int(2)]), Ok(Vector(vec![Int(21), Int(22)])));
 }
 
 
@@ -188,6 +411,16 @@ fn test_div() {
     assert_eq!(div(vec![Int(10), Int(2)]), Ok(Int(5)));
 }
 
+#[test]
+fn test_arithmetic_errors_instead_of_panics() {
+    assert_eq!(div(vec![Int(1), Int(0)]), error_result("Division of 1 by zero"));
+    assert_eq!(div(vec![Vector(vec![Int(4), Int(2)]), Int(0)]), error_result("Division of 4 by zero"));
+    assert_eq!(mul(vec![Int(i64::MAX), Int(2)]), error_result("Operation on 9223372036854775807 and 2 overflows"));
+    assert_eq!(sub(vec![Int(i64::MIN), Int(1)]), error_result("Operation on -9223372036854775808 and 1 overflows"));
+    assert_eq!(div(vec![Int(i64::MIN), Int(-1)]), error_result("Operation on -9223372036854775808 and -1 overflows"));
+    assert_eq!(sum(vec![Vector(vec![]), Vector(vec![Int(1)])]), error_result("Operation wants non-empty Vectors but got Vector([]) and Vector([Int(1)])"));
+}
+
 #[test]
 fn test_sub() {
     assert_eq!(sub(vec![Int(10), Int(2), Int(1)]), Ok(Int(7)));
@@ -218,4 +451,142 @@ fn test_rep_missing_arguments() {
 #[test]
 fn test_rep_needs_int_as_first_argument() {
     assert_eq!(rep(vec![string("23")]), error_result("rep needs an int as first argument"));
-}
\ No newline at end of file
+}
+
+#[test]
+fn test_rep_checks_size_before_allocating() {
+    assert_eq!(rep(vec![Int(-1), Int(3)]), error_result("rep needs a non-negative count but got -1"));
+    assert_eq!(rep(vec![Int(i64::MAX), Int(3), Int(4), Int(5)]), error_result("rep would create too many elements"));
+    let limits = EvalLimits { max_collection_size: Some(100), ..EvalLimits::default() };
+    with_limits(&limits, || {
+        assert_eq!(rep(vec![Int(50), Int(3), Int(4)]).map(|v| collection_len(&v)), Ok(100));
+        let err = rep(vec![Int(1_000_000_000), Int(3)]).unwrap_err();
+        assert_eq!(err, error("rep would create 1000000000 elements but the limit is 100").with_kind(ErrorKind::LimitExceeded));
+        assert_eq!(err.kind(), ErrorKind::LimitExceeded);
+    });
+}
+
+#[test]
+fn test_gensym() {
+    let generated1 = gensym(vec![]).unwrap();
+    let generated2 = gensym(vec![]).unwrap();
+    assert_ne!(generated1, generated2);
+
+    if let Symbol(name) = gensym(vec![string("note")]).unwrap() {
+        assert!(name.starts_with("note__"));
+    } else {
+        panic!("gensym should return a symbol");
+    }
+}
+
+#[test]
+fn test_gensym_error() {
+    assert_eq!(gensym(vec![Int(1)]), error_result("gensym wants a string as prefix but got Int(1)"));
+}
+
+#[test]
+fn test_create_set() {
+    assert_eq!(create_set(vec![Vector(vec![Int(2), Int(1), Int(2)])]), Ok(set(vec![Int(1), Int(2)])));
+    assert_eq!(create_set(vec![List(vec![Int(1)])]), Ok(set(vec![Int(1)])));
+    assert_eq!(create_set(vec![Nil]), Ok(set(vec![])));
+    assert_eq!(create_set(vec![]), error_result("set needs 1 argument but got 0"));
+    assert_eq!(create_set(vec![Int(1)]), error_result("set wants a List, Vector or Set but got Int(1)"));
+}
+
+#[test]
+fn test_conj() {
+    assert_eq!(conj(vec![set(vec![Int(1)]), Int(2), Int(1)]), Ok(set(vec![Int(1), Int(2)])));
+    assert_eq!(conj(vec![Vector(vec![Int(1)]), Int(2), Int(3)]), Ok(Vector(vec![Int(1), Int(2), Int(3)])));
+    assert_eq!(conj(vec![List(vec![Int(1)]), Int(2), Int(3)]), Ok(List(vec![Int(3), Int(2), Int(1)])));
+    assert_eq!(conj(vec![Int(1), Int(2)]), error_result("conj wants a collection as first argument but got Int(1)"));
+    assert_eq!(conj(vec![]), error_result("conj needs at least 1 argument but got 0"));
+}
+
+#[test]
+fn test_disj() {
+    assert_eq!(disj(vec![set(vec![Int(1), Int(2), Int(3)]), Int(2), Int(4)]), Ok(set(vec![Int(1), Int(3)])));
+    assert_eq!(disj(vec![Vector(vec![])]), error_result("disj wants a Set as first argument but got Vector([])"));
+}
+
+#[test]
+fn test_contains() {
+    assert_eq!(contains(vec![set(vec![Int(1)]), Int(1)]), Ok(Bool(true)));
+    assert_eq!(contains(vec![set(vec![Int(1)]), Int(2)]), Ok(Bool(false)));
+    assert_eq!(contains(vec![map(vec![("key", Int(1))]), keyword("key")]), Ok(Bool(true)));
+    assert_eq!(contains(vec![map(vec![("key", Int(1))]), keyword("other")]), Ok(Bool(false)));
+    assert_eq!(contains(vec![map(vec![("key", Int(1))]), string("key")]), Ok(Bool(false)));
+    assert_eq!(contains(vec![map_from(vec![(Int(36), string("kick"))]), Int(36)]), Ok(Bool(true)));
+    assert_eq!(contains(vec![set(vec![])]), error_result("contains? needs 2 arguments but got 1"));
+    assert_eq!(contains(vec![Int(1), Int(1)]), error_result("contains? wants a Set or Map as first argument but got Int(1)"));
+}
+
+#[test]
+fn test_set_operations() {
+    let set12 = set(vec![Int(1), Int(2)]);
+    let set23 = set(vec![Int(2), Int(3)]);
+    assert_eq!(union(vec![set12.clone(), set23.clone()]), Ok(set(vec![Int(1), Int(2), Int(3)])));
+    assert_eq!(union(vec![]), Ok(set(vec![])));
+    assert_eq!(intersection(vec![set12.clone(), set23.clone()]), Ok(set(vec![Int(2)])));
+    assert_eq!(difference(vec![set12.clone(), set23.clone()]), Ok(set(vec![Int(1)])));
+    assert_eq!(difference(vec![set12.clone()]), Ok(set12.clone()));
+}
+
+#[test]
+fn test_set_operation_errors() {
+    assert_eq!(union(vec![set(vec![]), Int(1)]), error_result("union wants only Sets as arguments but got Int(1)"));
+    assert_eq!(intersection(vec![]), error_result("intersection needs at least 1 argument but got 0"));
+    assert_eq!(difference(vec![]), error_result("difference needs at least 1 argument but got 0"));
+}
+
+#[test]
+fn test_pr_str() {
+    assert_eq!(pr_str(vec![string("a\"b"), keyword("key"), Nil, Vector(vec![Int(1)])]), Ok(string(r#""a\"b" :key nil [1]"#)));
+    assert_eq!(pr_str(vec![]), Ok(string("")));
+}
+
+#[test]
+fn test_str_concat() {
+    assert_eq!(str_concat(vec![string("note"), Int(42), Nil, keyword("key")]), Ok(string("note42:key")));
+    assert_eq!(str_concat(vec![]), Ok(string("")));
+}
+
+#[test]
+fn test_print_text() {
+    assert_eq!(join_args(&[string("kick"), Int(36), Nil, keyword("drums")], to_plain_string), "kick 36  :drums");
+    assert_eq!(join_args(&[string("kick")], RispType::pr_str), "\"kick\"");
+}
+
+#[test]
+fn test_json_encode_and_decode() {
+    let value = map(vec![("notes", Vector(vec![Int(1), Int(2)]))]);
+    assert_eq!(json_encode(vec![value.clone()]), Ok(string(r#"{"notes":[1,2]}"#)));
+    assert_eq!(json_decode(vec![string(r#"{"notes":[1,2]}"#)]), Ok(value));
+    assert_eq!(json_encode(vec![]), error_result("json/encode needs 1 argument but got 0"));
+    assert_eq!(json_decode(vec![Int(1)]), error_result("json/decode wants a String but got Int(1)"));
+}
+
+#[test]
+fn test_builtins_check_limits() {
+    let limits = EvalLimits { max_collection_size: Some(3), max_string_length: Some(4), ..EvalLimits::default() };
+    with_limits(&limits, || {
+        assert_eq!(conj(vec![Vector(vec![Int(1), Int(2)]), Int(3), Int(4)]), Err(error("conj would create 4 elements but the limit is 3").with_kind(ErrorKind::LimitExceeded)));
+        assert_eq!(union(vec![set(vec![Int(1), Int(2)]), set(vec![Int(3), Int(4)])]), Err(error("union would create 4 elements but the limit is 3").with_kind(ErrorKind::LimitExceeded)));
+        assert_eq!(str_concat(vec![string("abc"), string("de")]), Err(error("str would create 5 bytes but the limit is 4").with_kind(ErrorKind::LimitExceeded)));
+        assert_eq!(pr_str(vec![string("abc")]), Err(error("pr-str would create 5 bytes but the limit is 4").with_kind(ErrorKind::LimitExceeded)));
+        assert_eq!(json_encode(vec![string("abc")]), Err(error("json/encode would create 5 bytes but the limit is 4").with_kind(ErrorKind::LimitExceeded)));
+    });
+}
+
+#[test]
+fn test_valid_and_explain() {
+    let schema = map(vec![("speed", Vector(vec![keyword("int"), map(vec![("min", Int(1))])]))]);
+    assert_eq!(valid(vec![schema.clone(), map(vec![("speed", Int(220))])]), Ok(Bool(true)));
+    assert_eq!(valid(vec![schema.clone(), map(vec![("speed", Int(0))])]), Ok(Bool(false)));
+    assert_eq!(explain(vec![schema.clone(), map(vec![("speed", Int(220))])]), Ok(Nil));
+    assert_eq!(explain(vec![schema.clone(), map::<&str>(vec![])]),
+               Ok(Vector(vec![map(vec![("path", Nil), ("message", string("Missing required key :speed"))])])));
+    assert_eq!(explain(vec![schema, map(vec![("speed", Int(0))])]),
+               Ok(Vector(vec![map(vec![("path", string("speed")), ("message", string("Expected Int >= 1 but got 0"))])])));
+    assert_eq!(valid(vec![keyword("float"), Int(1)]), error_result("Unknown schema type :float"));
+    assert_eq!(explain(vec![]), error_result("explain needs 2 arguments but got 0"));
+}
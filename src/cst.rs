@@ -0,0 +1,136 @@
+use parse::describe_position;
+use tokenize::*;
+use types::*;
+
+/// A lossless concrete syntax tree node, which keeps whitespace and comments.
+#[derive(Debug, PartialEq, Clone)]
+pub enum Cst {
+    /// Numbers, strings, symbols and keywords.
+    Atom(Token),
+    Whitespace(String),
+    Comment(String),
+    /// Lists, vectors, maps and sets with everything between their brackets.
+    Collection(Token, Vec<Cst>, Token),
+    /// Reader macros like 'x or #_x with the whitespace and comments before their form.
+    Prefixed(Token, Vec<Cst>),
+}
+
+impl Cst {
+    /// Returns the exact source text of the node.
+    pub fn to_source(&self) -> String {
+        match *self {
+            Cst::Atom((_, ref text)) | Cst::Whitespace(ref text) | Cst::Comment(ref text) => text.clone(),
+            Cst::Collection((_, ref open), ref children, (_, ref close)) => {
+                format!("{}{}{}", open, to_source(children), close)
+            }
+            Cst::Prefixed((_, ref prefix), ref children) => format!("{}{}", prefix, to_source(children))
+        }
+    }
+
+    pub fn is_trivia(&self) -> bool {
+        matches!(*self, Cst::Whitespace(_) | Cst::Comment(_))
+    }
+}
+
+/// Returns the exact source text of the nodes.
+pub fn to_source(nodes: &[Cst]) -> String {
+    nodes.iter().map(Cst::to_source).collect()
+}
+
+/// Parses the input into nodes, which add up to the complete input.
+pub fn parse_cst(input: &str) -> Result<Vec<Cst>, RispError> {
+    let mut tokenizer = Tokenizer::lossless(input);
+    let tokens: Vec<Token> = tokenizer.by_ref().collect();
+    if tokenizer.position() < input.len() {
+        return Err(error(format!("Unexpected character at {}", describe_position(input, tokenizer.position()))));
+    }
+
+    let mut tokens = tokens.into_iter();
+    let mut nodes = vec![];
+    while let Some(token) = tokens.next() {
+        nodes.push(parse_node(token, &mut tokens)?);
+    }
+    Ok(nodes)
+}
+
+fn parse_node<I: Iterator<Item=Token>>(token: Token, tokens: &mut I) -> Result<Cst, RispError> {
+    match token.0 {
+        TokenType::Whitespace => Ok(Cst::Whitespace(token.1)),
+        TokenType::Comment => Ok(Cst::Comment(token.1)),
+        TokenType::ListStart => parse_collection(token, TokenType::ListEnd, "Unexpected end of list", tokens),
+        TokenType::VectorStart => parse_collection(token, TokenType::VectorEnd, "Vector should end with ] but just ends", tokens),
+        TokenType::HashMapStart => parse_collection(token, TokenType::HashMapEnd, "HashMap should end with } but just ends", tokens),
+        TokenType::SetStart => parse_collection(token, TokenType::HashMapEnd, "Set should end with } but just ends", tokens),
+        TokenType::ListEnd | TokenType::VectorEnd | TokenType::HashMapEnd => {
+            Err(error(format!("Unexpected {}", token.1)))
+        }
+        TokenType::Quote | TokenType::SyntaxQuote | TokenType::Unquote | TokenType::UnquoteSplicing |
+        TokenType::Discard | TokenType::Deref => {
+            let mut children = vec![];
+            loop {
+                match tokens.next() {
+                    Some(next_token) => {
+                        let node = parse_node(next_token, tokens)?;
+                        let is_form = !node.is_trivia();
+                        children.push(node);
+                        if is_form {
+                            break;
+                        }
+                    }
+                    None => return Err(error(format!("Missing form after {}", token.1)))
+                }
+            }
+            Ok(Cst::Prefixed(token, children))
+        }
+        TokenType::Number | TokenType::Symbol | TokenType::Keyword | TokenType::Str => Ok(Cst::Atom(token))
+    }
+}
+
+fn parse_collection<I: Iterator<Item=Token>>(open: Token, end: TokenType, missing_end_message: &str, tokens: &mut I) -> Result<Cst, RispError> {
+    let mut children = vec![];
+    loop {
+        match tokens.next() {
+            Some(token) => {
+                if token.0 == end {
+                    return Ok(Cst::Collection(open, children, token));
+                }
+                children.push(parse_node(token, tokens)?);
+            }
+            None => return Err(error(missing_end_message))
+        }
+    }
+}
+
+
+/* ------------------------------ Tests ----------------------------------------------- */
+
+#[test]
+fn test_parse_cst_is_lossless() {
+    let input = "; header\n(def x   [1 #_2 ' 3]) ; trailing\n{:a #{1} :b \"s\"}\n";
+    assert_eq!(to_source(&parse_cst(input).unwrap()), input);
+}
+
+#[test]
+fn test_parse_cst_structure() {
+    let nodes = parse_cst("(a ;c\n 'b)").unwrap();
+    assert_eq!(nodes, vec![Cst::Collection(
+        (TokenType::ListStart, "(".to_string()),
+        vec![
+            Cst::Atom((TokenType::Symbol, "a".to_string())),
+            Cst::Whitespace(" ".to_string()),
+            Cst::Comment(";c".to_string()),
+            Cst::Whitespace("\n ".to_string()),
+            Cst::Prefixed((TokenType::Quote, "'".to_string()), vec![Cst::Atom((TokenType::Symbol, "b".to_string()))])
+        ],
+        (TokenType::ListEnd, ")".to_string())
+    )]);
+}
+
+#[test]
+fn test_parse_cst_errors() {
+    assert_eq!(parse_cst("(1"), Err(error("Unexpected end of list")));
+    assert_eq!(parse_cst("[1"), Err(error("Vector should end with ] but just ends")));
+    assert_eq!(parse_cst("1]"), Err(error("Unexpected ]")));
+    assert_eq!(parse_cst("'"), Err(error("Missing form after '")));
+    assert_eq!(parse_cst("(1 \"open"), Err(error("Unexpected character at line 1, column 4")));
+}
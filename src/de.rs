@@ -0,0 +1,366 @@
+use std::fmt;
+use serde::de::{self, DeserializeOwned, DeserializeSeed, EnumAccess, IntoDeserializer, MapAccess, SeqAccess,
+                VariantAccess, Visitor};
+use core::create_core_environment;
+use types::*;
+use types::RispType::*;
+use eval_risp_script;
+
+/// Deserializes an evaluated risp value.
+/// Structs are read from maps, enums from keywords or maps with a single entry and Option from nil or missing keys.
+pub fn from_value<T: DeserializeOwned>(value: RispType) -> Result<T, RispError> {
+    T::deserialize(ValueDeserializer::new(value, vec![])).map_err(RispError::from)
+}
+
+/// Evaluates the risp script in the core environment and deserializes its result.
+pub fn from_str<T: DeserializeOwned>(risp_code: &str) -> Result<T, RispError> {
+    from_value(eval_risp_script(risp_code, &mut create_core_environment())?)
+}
+
+/// An error, which knows the path of the value that failed to deserialize.
+#[derive(Debug)]
+struct Error {
+    message: String,
+    path: Option<Vec<String>>,
+}
+
+impl Error {
+    /// Only the innermost path is kept, because it points to the failing value.
+    fn at(mut self, path: &[String]) -> Error {
+        if self.path.is_none() && !path.is_empty() {
+            self.path = Some(path.to_vec());
+        }
+        self
+    }
+
+    fn to_risp_error(&self) -> RispError {
+        // The segments are already formatted like the ones of RispError, e.g. [2] for an index.
+        self.path.iter().flatten().rev()
+            .fold(error(self.message.as_str()), |risp_error, segment| risp_error.at_key(segment.as_str()))
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(&self.to_risp_error(), f)
+    }
+}
+
+impl ::std::error::Error for Error {}
+
+impl de::Error for Error {
+    fn custom<T: fmt::Display>(msg: T) -> Self {
+        Error { message: msg.to_string(), path: None }
+    }
+}
+
+impl From<Error> for RispError {
+    fn from(e: Error) -> RispError {
+        e.to_risp_error()
+    }
+}
+
+fn custom_error(message: String) -> Error {
+    de::Error::custom(message)
+}
+
+struct ValueDeserializer {
+    value: RispType,
+    path: Vec<String>,
+}
+
+impl ValueDeserializer {
+    fn new(value: RispType, path: Vec<String>) -> ValueDeserializer {
+        ValueDeserializer { value, path }
+    }
+}
+
+/// Returns the path of a map entry like effects[2].noteSequencer.
+fn key_path(path: &[String], key: &RispType) -> Vec<String> {
+    let key_name = match *key {
+        Keyword(ref name) | Str(ref name) | Symbol(ref name) => name.clone(),
+        _ => key.to_string()
+    };
+    let mut result = path.to_vec();
+    result.push(key_name);
+    result
+}
+
+fn index_path(path: &[String], index: usize) -> Vec<String> {
+    let mut result = path.to_vec();
+    result.push(format!("[{}]", index));
+    result
+}
+
+impl<'de> de::Deserializer<'de> for ValueDeserializer {
+    type Error = Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        let path = self.path;
+        let result = match self.value {
+            Nil => visitor.visit_unit(),
+            Bool(b) => visitor.visit_bool(b),
+            Int(i) => visitor.visit_i64(i),
+            Str(s) | Keyword(s) | Symbol(s) => visitor.visit_string(s),
+            List(elements) | Vector(elements) => visitor.visit_seq(SeqDeserializer::new(elements, path.clone())),
+            Set(elements) => visitor.visit_seq(SeqDeserializer::new(elements.into_iter().collect(), path.clone())),
+            Map(map) => visitor.visit_map(MapDeserializer::new(map.into_iter().collect(), path.clone())),
+            value @ Function(_) | value @ RispFunction(_) | value @ Macro(_) => {
+                Err(custom_error(format!("Can't deserialize {}", value)))
+            }
+        };
+        result.map_err(|e| e.at(&path))
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        match self.value {
+            Nil => visitor.visit_none(),
+            _ => visitor.visit_some(self)
+        }
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(self, _name: &'static str, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(self, name: &'static str, _variants: &'static [&'static str],
+                                         visitor: V) -> Result<V::Value, Error> {
+        let path = self.path;
+        let result = match self.value {
+            Keyword(variant) | Str(variant) => {
+                visitor.visit_enum(IntoDeserializer::<Error>::into_deserializer(variant))
+            }
+            Map(map) => {
+                if map.len() != 1 {
+                    return Err(custom_error(format!("Expected a map with a single entry for enum {} but got {} entries",
+                                                    name, map.len())).at(&path));
+                }
+                let (variant, value) = map.into_iter().next().unwrap();
+                visitor.visit_enum(EnumDeserializer { variant, value, path: path.clone() })
+            }
+            other => Err(custom_error(format!("Expected a keyword or map for enum {} but got {}", name, other)))
+        };
+        result.map_err(|e| e.at(&path))
+    }
+
+    fn deserialize_ignored_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_unit()
+    }
+
+    forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        bytes byte_buf unit unit_struct seq tuple tuple_struct map struct identifier
+    }
+}
+
+struct SeqDeserializer {
+    elements: ::std::vec::IntoIter<RispType>,
+    index: usize,
+    path: Vec<String>,
+}
+
+impl SeqDeserializer {
+    fn new(elements: Vec<RispType>, path: Vec<String>) -> SeqDeserializer {
+        SeqDeserializer { elements: elements.into_iter(), index: 0, path }
+    }
+}
+
+impl<'de> SeqAccess<'de> for SeqDeserializer {
+    type Error = Error;
+
+    fn next_element_seed<T: DeserializeSeed<'de>>(&mut self, seed: T) -> Result<Option<T::Value>, Error> {
+        match self.elements.next() {
+            Some(element) => {
+                let path = index_path(&self.path, self.index);
+                self.index += 1;
+                seed.deserialize(ValueDeserializer::new(element, path.clone()))
+                    .map(Some)
+                    .map_err(|e| e.at(&path))
+            }
+            None => Ok(None)
+        }
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.elements.len())
+    }
+}
+
+struct MapDeserializer {
+    entries: ::std::vec::IntoIter<(RispType, RispType)>,
+    value: Option<(RispType, Vec<String>)>,
+    path: Vec<String>,
+}
+
+impl MapDeserializer {
+    fn new(entries: Vec<(RispType, RispType)>, path: Vec<String>) -> MapDeserializer {
+        MapDeserializer { entries: entries.into_iter(), value: None, path }
+    }
+}
+
+impl<'de> MapAccess<'de> for MapDeserializer {
+    type Error = Error;
+
+    fn next_key_seed<K: DeserializeSeed<'de>>(&mut self, seed: K) -> Result<Option<K::Value>, Error> {
+        match self.entries.next() {
+            Some((key, value)) => {
+                let path = key_path(&self.path, &key);
+                self.value = Some((value, path.clone()));
+                seed.deserialize(ValueDeserializer::new(key, self.path.clone()))
+                    .map(Some)
+                    .map_err(|e| e.at(&path))
+            }
+            None => Ok(None)
+        }
+    }
+
+    fn next_value_seed<V: DeserializeSeed<'de>>(&mut self, seed: V) -> Result<V::Value, Error> {
+        let (value, path) = self.value.take().ok_or_else(|| custom_error("Value is missing".to_string()))?;
+        seed.deserialize(ValueDeserializer::new(value, path.clone())).map_err(|e| e.at(&path))
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.entries.len())
+    }
+}
+
+struct EnumDeserializer {
+    variant: RispType,
+    value: RispType,
+    path: Vec<String>,
+}
+
+impl<'de> EnumAccess<'de> for EnumDeserializer {
+    type Error = Error;
+    type Variant = ValueDeserializer;
+
+    fn variant_seed<V: DeserializeSeed<'de>>(self, seed: V) -> Result<(V::Value, ValueDeserializer), Error> {
+        let path = key_path(&self.path, &self.variant);
+        let variant = seed.deserialize(ValueDeserializer::new(self.variant, self.path))?;
+        Ok((variant, ValueDeserializer::new(self.value, path)))
+    }
+}
+
+impl<'de> VariantAccess<'de> for ValueDeserializer {
+    type Error = Error;
+
+    fn unit_variant(self) -> Result<(), Error> {
+        match self.value {
+            Nil => Ok(()),
+            other => Err(custom_error(format!("Expected nil for unit variant but got {}", other)).at(&self.path))
+        }
+    }
+
+    fn newtype_variant_seed<T: DeserializeSeed<'de>>(self, seed: T) -> Result<T::Value, Error> {
+        seed.deserialize(self)
+    }
+
+    fn tuple_variant<V: Visitor<'de>>(self, _len: usize, visitor: V) -> Result<V::Value, Error> {
+        de::Deserializer::deserialize_seq(self, visitor)
+    }
+
+    fn struct_variant<V: Visitor<'de>>(self, _fields: &'static [&'static str], visitor: V) -> Result<V::Value, Error> {
+        de::Deserializer::deserialize_map(self, visitor)
+    }
+}
+
+
+/* ------------------------------ Tests ----------------------------------------------- */
+
+#[cfg(test)]
+use std::collections::HashMap;
+
+#[cfg(test)]
+#[derive(Debug, PartialEq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Song {
+    name: String,
+    speed: Option<u32>,
+    effects: Vec<Effect>,
+}
+
+#[cfg(test)]
+#[derive(Debug, PartialEq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Effect {
+    trigger: u8,
+    note_sequencer: Option<NoteSequencer>,
+    kind: Option<Kind>,
+}
+
+#[cfg(test)]
+#[derive(Debug, PartialEq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct NoteSequencer {
+    notes: Vec<i64>,
+}
+
+#[cfg(test)]
+#[derive(Debug, PartialEq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+enum Kind {
+    Drum,
+    Sweep { from: i64, to: i64 },
+    Transpose(i64),
+}
+
+#[test]
+fn test_from_str_struct() {
+    let song: Song = from_str(r#"
+        {:name    "Amazon"
+         :effects [{:trigger 43 :noteSequencer {:notes [45 57]}}
+                   {:trigger 36 :speed nil :kind :drum}]}"#).unwrap();
+    assert_eq!(song, Song {
+        name: "Amazon".to_string(),
+        speed: None,
+        effects: vec![
+            Effect { trigger: 43, note_sequencer: Some(NoteSequencer { notes: vec![45, 57] }), kind: None },
+            Effect { trigger: 36, note_sequencer: None, kind: Some(Kind::Drum) },
+        ],
+    });
+}
+
+#[test]
+fn test_from_str_enums() {
+    assert_eq!(from_str::<Kind>(":drum").unwrap(), Kind::Drum);
+    assert_eq!(from_str::<Kind>("\"drum\"").unwrap(), Kind::Drum);
+    assert_eq!(from_str::<Kind>("{:sweep {:from 1 :to 2}}").unwrap(), Kind::Sweep { from: 1, to: 2 });
+    assert_eq!(from_str::<Kind>("{:transpose -12}").unwrap(), Kind::Transpose(-12));
+    assert_eq!(from_str::<Kind>(":unknown").unwrap_err(),
+               error("unknown variant `unknown`, expected one of `drum`, `sweep`, `transpose`"));
+}
+
+#[test]
+fn test_from_value_collections() {
+    let map: HashMap<i64, String> = from_str("{36 \"kick\" 38 \"snare\"}").unwrap();
+    assert_eq!(map.get(&36), Some(&"kick".to_string()));
+    let tuple: (String, Vec<Option<bool>>) = from_str("[:key [true nil]]").unwrap();
+    assert_eq!(tuple, ("key".to_string(), vec![Some(true), None]));
+    let set: Vec<i64> = from_str("#{2 1}").unwrap();
+    assert_eq!(set, vec![1, 2]);
+}
+
+#[test]
+fn test_error_paths() {
+    let result = from_str::<Song>(r#"
+        {:name "Amazon"
+         :effects [{:trigger 43}
+                   {:trigger 45}
+                   {:trigger 36 :noteSequencer {:notes ["kick"]}}]}"#);
+    assert_eq!(result.unwrap_err(),
+               error("invalid type: string \"kick\", expected i64").at_index(0).at_key("notes").at_key("noteSequencer").at_index(2).at_key("effects"));
+
+    assert_eq!(from_str::<Song>("{:name \"Amazon\" :effects [{:trigger 300}]}").unwrap_err(),
+               error("invalid value: integer `300`, expected u8").at_key("trigger").at_index(0).at_key("effects"));
+    assert_eq!(from_str::<Song>("{:name \"Amazon\" :effects [{}]}").unwrap_err(),
+               error("missing field `trigger`").at_index(0).at_key("effects"));
+    assert_eq!(from_str::<Song>("{:effects []}").unwrap_err(), error("missing field `name`"));
+    assert_eq!(from_str::<Song>("42").unwrap_err(), error("invalid type: integer `42`, expected struct Song"));
+    assert_eq!(from_str::<Song>("{:name \"A\" :effects [{:trigger 1 :kind {:sweep {:from 1}}}]}").unwrap_err(),
+               error("missing field `to`").at_key("sweep").at_key("kind").at_index(0).at_key("effects"));
+}
+
+#[test]
+fn test_from_str_eval_error() {
+    assert_eq!(from_str::<i64>("(+ 1"), Err(error("Unexpected end of list").with_kind(ErrorKind::IncompleteInput)));
+}
@@ -1,23 +1,210 @@
-use std::collections::HashMap;
+use std::cmp::Ordering;
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::rc::Rc;
+use capabilities::{Capability, ALL_CAPABILITIES};
+use modules::{ModuleResolver, Modules};
+use random::RandomSource;
+use tokenize::split_qualified_symbol;
 use types::*;
 
-#[derive(Default, Debug, PartialEq, Clone)]
+#[derive(Default, Clone)]
 pub struct Environment {
     data: HashMap<String, RispType>,
+    modules: Option<Rc<Modules>>,
+    /// The namespace of new definitions, set by `(ns name)`.
+    namespace: Option<String>,
+    /// Namespace by alias, e.g. d -> drums.
+    aliases: HashMap<String, String>,
+    /// Qualified names of definitions, which can't be used from other namespaces.
+    private: HashSet<String>,
+    /// Capabilities granted by the embedder, modules get the same ones.
+    capabilities: HashSet<Capability>,
+    /// The default source of the random forms, shared by all copies of this environment.
+    random_source: Option<Rc<RandomSource>>,
 }
 
 impl Environment {
     pub fn new() -> Self {
-        Environment { data: HashMap::new() }
+        Environment::default()
+    }
+
+    /// Enables load and require, all copies of this environment share the resolver and the required modules.
+    pub fn set_module_resolver<R: ModuleResolver + 'static>(&mut self, resolver: R) {
+        self.modules = Some(Rc::new(Modules::new(resolver)));
+    }
+
+    /// Installs the builtins of the capability, e.g. env/get for Capability::Env.
+    pub fn grant(&mut self, capability: Capability) {
+        for (name, function) in capability.functions() {
+            self.set(name, RispType::Function(function));
+        }
+        self.capabilities.insert(capability);
+    }
+
+    pub fn is_granted(&self, capability: Capability) -> bool {
+        self.capabilities.contains(&capability)
+    }
+
+    pub fn granted_capabilities(&self) -> Vec<Capability> {
+        ALL_CAPABILITIES.iter().filter(|capability| self.is_granted(**capability)).cloned().collect()
+    }
+
+    pub fn modules(&self) -> Option<Rc<Modules>> {
+        self.modules.clone()
+    }
+
+    pub fn set_modules(&mut self, modules: Option<Rc<Modules>>) {
+        self.modules = modules;
+    }
+
+    /// Makes the random forms like rand-int deterministic without (with-seed ...).
+    pub fn set_random_seed(&mut self, seed: u64) {
+        self.random_source = Some(Rc::new(RandomSource::new(seed)));
+    }
+
+    pub fn random_source(&self) -> Option<Rc<RandomSource>> {
+        self.random_source.clone()
+    }
+
+    pub fn set_random_source(&mut self, random_source: Option<Rc<RandomSource>>) {
+        self.random_source = random_source;
     }
 
     pub fn set(&mut self, key: &str, value: RispType) {
         self.data.insert(key.to_string(), value);
     }
 
+    /// Looks up names in the current namespace first, qualified names like `d/kick` may use an alias.
     pub fn get(&self, key: &str) -> Option<RispType> {
-        self.data.get(key).cloned()
+        self.data.get(&self.qualify(key)).or_else(|| self.data.get(key)).cloned()
+    }
+
+    /// Like get, but fails for private definitions of other namespaces.
+    pub fn resolve(&self, key: &str) -> Result<Option<RispType>, RispError> {
+        if let Some((namespace, name)) = split_qualified_symbol(key) {
+            let namespace = self.aliases.get(namespace).map_or(namespace, |namespace| namespace.as_str());
+            let qualified_name = format!("{}/{}", namespace, name);
+            if self.private.contains(&qualified_name) && self.namespace.as_ref().is_none_or(|current| current != namespace) {
+                return Err(error(format!("{} is private", qualified_name)));
+            }
+        }
+        Ok(self.get(key))
+    }
+
+    /// Sets the name in the current namespace.
+    pub fn define(&mut self, name: &str, value: RispType) {
+        let qualified_name = self.qualify(name);
+        self.private.remove(&qualified_name);
+        self.data.insert(qualified_name, value);
+    }
+
+    /// Sets the name in the current namespace, other namespaces can't use it.
+    pub fn define_private(&mut self, name: &str, value: RispType) {
+        let qualified_name = self.qualify(name);
+        self.data.insert(qualified_name.clone(), value);
+        self.private.insert(qualified_name);
+    }
+
+    /// Sets a function argument, which hides definitions of the current namespace with the same name.
+    pub fn set_local(&mut self, name: &str, value: RispType) {
+        if self.namespace.is_some() {
+            self.data.insert(self.qualify(name), value.clone());
+        }
+        self.data.insert(name.to_string(), value);
+    }
+
+    /// Returns the name with the current namespace or with the namespace of its alias.
+    fn qualify(&self, name: &str) -> String {
+        match split_qualified_symbol(name) {
+            Some((alias, local_name)) => match self.aliases.get(alias) {
+                Some(namespace) => format!("{}/{}", namespace, local_name),
+                None => name.to_string()
+            },
+            None => match self.namespace {
+                Some(ref namespace) => format!("{}/{}", namespace, name),
+                None => name.to_string()
+            }
+        }
+    }
+
+    pub fn current_namespace(&self) -> Option<&str> {
+        self.namespace.as_deref()
+    }
+
+    pub fn set_namespace(&mut self, namespace: &str) {
+        self.namespace = Some(namespace.to_string());
+    }
+
+    /// Makes `alias/name` refer to `namespace/name`.
+    pub fn add_alias(&mut self, alias: &str, namespace: &str) {
+        self.aliases.insert(alias.to_string(), namespace.to_string());
+    }
+
+    /// Returns all namespaces with definitions, sorted alphabetically.
+    pub fn namespaces(&self) -> Vec<String> {
+        let namespaces: BTreeSet<String> = self.data.keys()
+            .filter_map(|key| split_qualified_symbol(key).map(|(namespace, _)| namespace.to_string()))
+            .collect();
+        namespaces.into_iter().collect()
+    }
+
+    /// Returns the names of the public definitions of the namespace without the namespace, sorted alphabetically.
+    pub fn public_names(&self, namespace: &str) -> Vec<String> {
+        let mut names: Vec<String> = self.data.keys()
+            .filter(|key| !self.private.contains(*key))
+            .filter_map(|key| match split_qualified_symbol(key) {
+                Some((key_namespace, name)) if key_namespace == namespace => Some(name.to_string()),
+                _ => None
+            })
+            .collect();
+        names.sort();
+        names
+    }
+
+    pub fn is_private(&self, qualified_name: &str) -> bool {
+        self.private.contains(qualified_name)
+    }
+
+    /// Returns the names of all variables sorted alphabetically.
+    pub fn names(&self) -> Vec<String> {
+        let mut names: Vec<String> = self.data.keys().cloned().collect();
+        names.sort();
+        names
     }
 }
 
+/// Environments are equal if they contain the same variables.
+impl PartialEq for Environment {
+    fn eq(&self, other: &Environment) -> bool {
+        self.data == other.data
+    }
+}
+
+impl Eq for Environment {}
+
+impl PartialOrd for Environment {
+    fn partial_cmp(&self, other: &Environment) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Environment {
+    fn cmp(&self, other: &Environment) -> Ordering {
+        sorted_entries(&self.data).cmp(&sorted_entries(&other.data))
+    }
+}
 
+impl Hash for Environment {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        sorted_entries(&self.data).hash(state);
+    }
+}
+
+/// Lists the variables sorted by name, so that the output is stable.
+impl fmt::Debug for Environment {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_map().entries(sorted_entries(&self.data)).finish()
+    }
+}
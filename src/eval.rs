@@ -1,17 +1,37 @@
-use std::collections::HashMap;
+use std::collections::{BTreeSet, HashMap};
 use types::*;
 use types::RispType::*;
 use environment::*;
-use core::create_core_environment;
-use parse;
+use core::{create_core_environment, gensym_name};
+use modules::{load, refer, require, symbol_names};
+use tokenize::split_qualified_symbol;
+use capabilities::{not_granted_error, required_capability};
+use random::{seed_of, with_random_source, with_seed};
+use limits::{check_cancelled, count_step, enter_call, with_limits, EvalLimits};
 use std::rc::Rc;
 
+/// Evaluates with the limits, which abort endless or too expensive code with an error of kind LimitExceeded
+/// or stale evaluations with an error of kind Cancelled.
+pub fn eval_with_limits(ast: RispType, env: &mut Environment, limits: &EvalLimits) -> RispResult {
+    with_limits(limits, || eval_top_level(ast, env))
+}
+
+/// Evaluates a whole script or input, random functions outside of with-seed use the seed of the environment.
+pub fn eval_top_level(ast: RispType, env: &mut Environment) -> RispResult {
+    with_random_source(env.random_source(), || eval(ast, env))
+}
+
 pub fn eval(ast: RispType, env: &mut Environment) -> RispResult {
+    count_step()?;
     match ast {
         List(list) => {
             let first_element = list.first().ok_or_else(|| error("Empty List"))?;
             match *first_element {
                 Symbol(ref symbol_ref) => {
+                    if let Some(Macro(risp_macro)) = env.get(symbol_ref) {
+                        let expanded = call_risp_function(&risp_macro, &list[1..])?;
+                        return eval(expanded, env);
+                    }
                     match symbol_ref.as_ref() {
                         "def" => {
                             let var = list.get(1).ok_or_else(|| error("Missing variable in def"))?;
@@ -19,7 +39,7 @@ pub fn eval(ast: RispType, env: &mut Environment) -> RispResult {
                                 Symbol(ref sym_var) => {
                                     let value_ast = list.get(2).ok_or_else(|| error("Missing value in def"))?;
                                     let value = eval(value_ast.clone(), env)?;
-                                    env.set(sym_var, value.clone());
+                                    env.define(sym_var, value.clone());
                                     Ok(value)
                                 }
                                 _ => error_result(format!("Expected symbol in def but got {:?}", var))
@@ -31,9 +51,72 @@ pub fn eval(ast: RispType, env: &mut Environment) -> RispResult {
                             let body = list.get(3).ok_or_else(|| error("Missing body in defn"))?;
                             eval(List(vec![symbol("def"), name.clone(), List(vec![symbol("fn"), args.clone(), body.clone()])]), env)
                         }
+                        "defn-" => {
+                            let name = list.get(1).ok_or_else(|| error("Missing function name in defn-"))?;
+                            let args = list.get(2).ok_or_else(|| error("Missing args in defn-"))?;
+                            let body = list.get(3).ok_or_else(|| error("Missing body in defn-"))?;
+                            match *name {
+                                Symbol(ref function_name) => {
+                                    let function = RispFunction(create_risp_func(&[symbol("fn"), args.clone(), body.clone()], env)?);
+                                    env.define_private(function_name, function.clone());
+                                    Ok(function)
+                                }
+                                _ => error_result(format!("Expected symbol in defn- but got {:?}", name))
+                            }
+                        }
+                        "ns" => {
+                            match list.get(1) {
+                                Some(Symbol(namespace)) if split_qualified_symbol(namespace).is_none() => {
+                                    env.set_namespace(namespace);
+                                    Ok(Nil)
+                                }
+                                Some(other) => error_result(format!("Expected namespace name in ns but got {}", other)),
+                                None => error_result("Missing name in ns")
+                            }
+                        }
+                        "refer" => {
+                            let namespace = match eval(list.get(1).cloned().ok_or_else(|| error("Missing namespace in refer"))?, env)? {
+                                Symbol(namespace) => namespace,
+                                other => return error_result(format!("Expected namespace symbol in refer but got {}", other))
+                            };
+                            let names = match list.get(2..) {
+                                Some([]) | None => None,
+                                Some([Keyword(only), names]) if only == "only" => Some(symbol_names(&eval(names.clone(), env)?)?),
+                                Some(_) => return error_result("Expected (refer 'namespace) or (refer 'namespace :only '[names])")
+                            };
+                            refer(&namespace, names, env)?;
+                            Ok(Nil)
+                        }
+                        "defmacro" => {
+                            let name = list.get(1).ok_or_else(|| error("Missing macro name in defmacro"))?;
+                            let args = list.get(2).ok_or_else(|| error("Missing args in defmacro"))?;
+                            let body = list.get(3).ok_or_else(|| error("Missing body in defmacro"))?;
+                            match *name {
+                                Symbol(ref macro_name) => {
+                                    let risp_macro = Macro(create_risp_func(&[symbol("fn"), args.clone(), body.clone()], env)?);
+                                    env.define(macro_name, risp_macro.clone());
+                                    Ok(risp_macro)
+                                }
+                                _ => error_result(format!("Expected symbol in defmacro but got {:?}", name))
+                            }
+                        }
+                        "macroexpand-1" => {
+                            let form_ast = list.get(1).ok_or_else(|| error("Missing form in macroexpand-1"))?;
+                            let form = eval(form_ast.clone(), env)?;
+                            Ok(macroexpand_1(&form, env)?.unwrap_or(form))
+                        }
+                        "macroexpand" => {
+                            let form_ast = list.get(1).ok_or_else(|| error("Missing form in macroexpand"))?;
+                            let mut form = eval(form_ast.clone(), env)?;
+                            while let Some(expanded) = macroexpand_1(&form, env)? {
+                                form = expanded;
+                            }
+                            Ok(form)
+                        }
                         "do" => {
-                            if let Some((last, elements)) = (&list[1..]).split_last() {
+                            if let Some((last, elements)) = list[1..].split_last() {
                                 for child_ast in elements.iter() {
+                                    check_cancelled()?;
                                     eval(child_ast.clone(), env)?;
                                 }
                                 eval(last.clone(), env)
@@ -44,46 +127,51 @@ pub fn eval(ast: RispType, env: &mut Environment) -> RispResult {
                         "comment" => {
                             Ok(Nil)
                         }
+                        "with-seed" => {
+                            let seed = seed_of(&eval(list.get(1).cloned().ok_or_else(|| error("Missing seed in with-seed"))?, env)?)?;
+                            let mut do_form = vec![symbol("do")];
+                            do_form.extend_from_slice(&list[2..]);
+                            with_seed(seed, || eval(List(do_form), env))
+                        }
+                        "quote" => {
+                            list.get(1).cloned().ok_or_else(|| error("Missing form in quote"))
+                        }
+                        "syntax-quote" => {
+                            let form = list.get(1).ok_or_else(|| error("Missing form in syntax-quote"))?;
+                            eval_syntax_quote(form, env)
+                        }
+                        "unquote" | "unquote-splicing" => {
+                            error_result(format!("{} is only allowed inside syntax-quote", symbol_ref))
+                        }
                         "fn" => {
-                            let args_risp = list.get(1).ok_or_else(|| error("Missing args in fn"))?;
-                            match *args_risp {
-                                Vector(ref args_vec) => {
-                                    let body = list.get(2).ok_or_else(|| error("Missing body in fn"))?;
-                                    if let Some(variadic_marker_pos) = args_vec.iter().position(is_variadic_marker) {
-                                        if let Some(&Symbol(ref variadic_arg)) = args_vec.get(variadic_marker_pos + 1) {
-                                            Ok(RispFunction(RispFunc {
-                                                args: args_vec[..variadic_marker_pos].to_vec(),
-                                                variadic_arg: Some(variadic_arg.to_string()),
-                                                body: Rc::new(body.clone()),
-                                                env: env.clone()
-                                            }))
-                                        } else {
-                                            error_result(format!("Missing variadic arg after & in {:?}", args_vec))
-                                        }
-                                    } else {
-                                        Ok(RispFunction(RispFunc {
-                                            args: args_vec.clone(),
-                                            variadic_arg: None,
-                                            body: Rc::new(body.clone()),
-                                            env: env.clone()
-                                        }))
-                                    }
-                                }
-                                _ => error_result(format!("Expected args vector in fn but got {:?}", args_risp))
+                            create_risp_func(&list, env).map(RispFunction)
+                        }
+                        "load" => {
+                            let path_ast = list.get(1).ok_or_else(|| error("Missing path in load"))?;
+                            let path = eval(path_ast.clone(), env)?;
+                            load(&path, env)
+                        }
+                        "require" => {
+                            if list.len() < 2 {
+                                return error_result("Missing module in require");
+                            }
+                            for spec_ast in &list[1..] {
+                                let spec = eval(spec_ast.clone(), env)?;
+                                require(&spec, env)?;
                             }
+                            Ok(Nil)
                         }
                         _ => {
                             let evaluated_tail = list[1..].iter()
                                 .map(|el| eval(el.clone(), env))
                                 .collect::<Result<Vec<_>, _>>()?;
-                            let env_value = env.get(symbol_ref).ok_or_else(|| error(format!("Undefined symbol{:?}", symbol_ref)))?;
+                            let env_value = env.resolve(symbol_ref)?.ok_or_else(|| undefined_error(symbol_ref, format!("Undefined symbol{:?}", symbol_ref)))?;
                             match env_value {
-                                Function(function) => function(evaluated_tail.to_vec()),
-                                RispFunction(risp_function) => {
-                                    let mut inner_env = risp_function.env.clone();
-                                    put_args_into_env(&risp_function, &evaluated_tail, &mut inner_env)?;
-                                    eval((*risp_function.body).clone(), &mut inner_env)
+                                Function(function) => {
+                                    check_cancelled()?;
+                                    function(evaluated_tail.to_vec())
                                 }
+                                RispFunction(risp_function) => call_risp_function(&risp_function, &evaluated_tail),
                                 _ => error_result(format!("Expected function but got {:?}", env_value))
                             }
                         }
@@ -99,22 +187,169 @@ pub fn eval(ast: RispType, env: &mut Environment) -> RispResult {
             Ok(Vector(evaluated_vector))
         }
 
+        Set(set_value) => {
+            let evaluated_elements = set_value.iter()
+                .map(|el| eval(el.clone(), env))
+                .collect::<Result<Vec<_>, _>>()?;
+            evaluated_set(evaluated_elements)
+        }
+
         Map(map_value) => {
-            let evaluated_map = map_value.iter()
-                .map(|(key, val)|
-                    eval(val.clone(), env).map(|evaluated_value|
-                        (key.to_string(), evaluated_value)))
-                .collect::<Result<HashMap<String, RispType>, _>>()?;
-            Ok(Map(evaluated_map))
+            let evaluated_entries = map_value.iter()
+                .map(|(key, val)| Ok((eval(key.clone(), env)?, eval(val.clone(), env)?)))
+                .collect::<Result<Vec<_>, RispError>>()?;
+            evaluated_map(evaluated_entries)
         }
 
         Symbol(symbol) => {
-            env.get(&symbol).ok_or_else(|| error(format!("symbol '{:?}' is undefined", symbol)))
+            env.resolve(&symbol)?.ok_or_else(|| undefined_error(&symbol, format!("symbol '{:?}' is undefined", symbol)))
         }
         other => Ok(other)
     }
 }
 
+/// Explains undefined builtins of capabilities, which are not granted.
+fn undefined_error(symbol: &str, message: String) -> RispError {
+    match required_capability(symbol) {
+        Some(capability) => not_granted_error(symbol, capability),
+        None => error(message)
+    }
+}
+
+/// Creates the function for a (fn [args] body) form.
+fn create_risp_func(list: &[RispType], env: &Environment) -> Result<RispFunc, RispError> {
+    let args_risp = list.get(1).ok_or_else(|| error("Missing args in fn"))?;
+    match *args_risp {
+        Vector(ref args_vec) => {
+            let body = list.get(2).ok_or_else(|| error("Missing body in fn"))?;
+            if let Some(variadic_marker_pos) = args_vec.iter().position(is_variadic_marker) {
+                if let Some(Symbol(variadic_arg)) = args_vec.get(variadic_marker_pos + 1) {
+                    Ok(RispFunc {
+                        args: args_vec[..variadic_marker_pos].to_vec(),
+                        variadic_arg: Some(variadic_arg.to_string()),
+                        body: Rc::new(body.clone()),
+                        env: env.clone()
+                    })
+                } else {
+                    Err(error(format!("Missing variadic arg after & in {:?}", args_vec)))
+                }
+            } else {
+                Ok(RispFunc {
+                    args: args_vec.clone(),
+                    variadic_arg: None,
+                    body: Rc::new(body.clone()),
+                    env: env.clone()
+                })
+            }
+        }
+        _ => Err(error(format!("Expected args vector in fn but got {:?}", args_risp)))
+    }
+}
+
+fn call_risp_function(risp_function: &RispFunc, args: &[RispType]) -> RispResult {
+    let _call = enter_call()?;
+    let mut inner_env = risp_function.env.clone();
+    put_args_into_env(risp_function, args, &mut inner_env)?;
+    eval((*risp_function.body).clone(), &mut inner_env)
+}
+
+/// Expands the form once, if it's a call of a macro, otherwise returns None.
+fn macroexpand_1(form: &RispType, env: &Environment) -> Result<Option<RispType>, RispError> {
+    if let List(ref list) = *form {
+        if let Some(Symbol(symbol_ref)) = list.first() {
+            if let Some(Macro(risp_macro)) = env.get(symbol_ref) {
+                return call_risp_function(&risp_macro, &list[1..]).map(Some);
+            }
+        }
+    }
+    Ok(None)
+}
+
+/// Returns the quoted template with all ~x and ~@x parts replaced by their evaluated values.
+/// Symbols ending with # are replaced by the same generated symbol within one template.
+fn eval_syntax_quote(form: &RispType, env: &mut Environment) -> RispResult {
+    eval_template(form, env, &mut HashMap::new())
+}
+
+/// Like the parser for set literals, rejects elements which are only equal after evaluation, e.g. #{1 (+ 0 1)}.
+fn evaluated_set(elements: Vec<RispType>) -> RispResult {
+    let mut result = BTreeSet::new();
+    for element in elements {
+        if result.contains(&element) {
+            return error_result(format!("Duplicate element {:?} in set after evaluation", element));
+        }
+        result.insert(element);
+    }
+    Ok(Set(result))
+}
+
+/// Like the parser for map literals, rejects keys which are only equal after evaluation, e.g. {1 :a (+ 0 1) :b}.
+fn evaluated_map(entries: Vec<(RispType, RispType)>) -> RispResult {
+    let mut result = IndexMap::new();
+    for (key, value) in entries {
+        if result.contains_key(&key) {
+            return error_result(format!("Duplicate key {:?} in map after evaluation", key));
+        }
+        result.insert(key, value);
+    }
+    Ok(Map(result))
+}
+
+fn eval_template(form: &RispType, env: &mut Environment, gensyms: &mut HashMap<String, String>) -> RispResult {
+    match *form {
+        List(ref list) => {
+            if let Some(unquoted) = unquoted_form(list, "unquote") {
+                return eval(unquoted.clone(), env);
+            }
+            eval_template_elements(list, env, gensyms).map(List)
+        }
+        Vector(ref vector) => eval_template_elements(vector, env, gensyms).map(Vector),
+        Set(ref set_value) => {
+            let elements: Vec<RispType> = set_value.iter().cloned().collect();
+            evaluated_set(eval_template_elements(&elements, env, gensyms)?)
+        }
+        Map(ref map_value) => {
+            let evaluated_entries = map_value.iter()
+                .map(|(key, val)| Ok((eval_template(key, env, gensyms)?, eval_template(val, env, gensyms)?)))
+                .collect::<Result<Vec<_>, RispError>>()?;
+            evaluated_map(evaluated_entries)
+        }
+        Symbol(ref name) if name.len() > 1 && name.ends_with('#') => {
+            let generated = gensyms.entry(name.clone())
+                .or_insert_with(|| gensym_name(&name[..name.len() - 1]));
+            Ok(symbol(generated.as_str()))
+        }
+        _ => Ok(form.clone())
+    }
+}
+
+fn eval_template_elements(elements: &[RispType], env: &mut Environment, gensyms: &mut HashMap<String, String>) -> Result<Vec<RispType>, RispError> {
+    let mut result = vec![];
+    for element in elements {
+        if let List(ref list) = *element {
+            if let Some(spliced) = unquoted_form(list, "unquote-splicing") {
+                match eval(spliced.clone(), env)? {
+                    List(spliced_elements) | Vector(spliced_elements) => {
+                        result.extend(spliced_elements);
+                    }
+                    Nil => {}
+                    other => return Err(error(format!("Expected list or vector in unquote-splicing but got {:?}", other)))
+                }
+                continue;
+            }
+        }
+        result.push(eval_template(element, env, gensyms)?);
+    }
+    Ok(result)
+}
+
+fn unquoted_form<'a>(list: &'a [RispType], unquote_symbol: &str) -> Option<&'a RispType> {
+    match list {
+        [Symbol(ref s), form] if s == unquote_symbol => Some(form),
+        _ => None
+    }
+}
+
 fn is_variadic_marker(risp: &RispType) -> bool {
     *risp == symbol("&")
 }
@@ -123,7 +358,7 @@ fn put_args_into_env(risp_func: &RispFunc, values: &[RispType], env: &mut Enviro
     for (arg, value) in risp_func.args.iter().zip(values.iter()) {
         match *arg {
             Symbol(ref arg_string) => {
-                env.set(arg_string, value.clone())
+                env.set_local(arg_string, value.clone())
             }
             _ => {
                 return Err(error(format!("Expected symbol in args list got {:?}", arg)));
@@ -132,7 +367,7 @@ fn put_args_into_env(risp_func: &RispFunc, values: &[RispType], env: &mut Enviro
     }
     if let Some(ref variadic_arg) = risp_func.variadic_arg {
         let variadic_args: Vec<RispType> = values[risp_func.args.len()..].to_vec();
-        env.set(variadic_arg, Vector(variadic_args));
+        env.set_local(variadic_arg, Vector(variadic_args));
     }
     Ok(())
 }
@@ -144,11 +379,10 @@ fn eval_test(ast: RispType) -> RispResult {
     eval(ast, &mut create_core_environment())
 }
 
-#[allow(dead_code)]
-fn eval_str(risp: &str) -> RispResult {
-    let ast = parse::parse(risp)?;
-    eval(ast, &mut create_core_environment())
-}
+#[cfg(test)]
+use eval_str;
+#[cfg(test)]
+use parse;
 
 #[test]
 fn test_eval_number() {
@@ -378,4 +612,237 @@ fn test_return_closures() {
 
     )
     "), Ok(Int(23)));
-}
\ No newline at end of file
+}
+
+#[test]
+fn test_quote() {
+    assert_eq!(eval_str("(quote a)"), Ok(symbol("a")));
+    assert_eq!(eval_str("'a"), Ok(symbol("a")));
+    assert_eq!(eval_str("'(+ 1 2)"), Ok(List(vec![symbol("+"), Int(1), Int(2)])));
+    assert_eq!(eval_str("'[a {:key b}]"), Ok(Vector(vec![symbol("a"), map(vec![("key", symbol("b"))])])));
+    assert_eq!(eval_str("(quote)"), error_result("Missing form in quote"));
+}
+
+#[test]
+fn test_syntax_quote() {
+    assert_eq!(eval_str("`(+ 1 ~(+ 1 1))"), Ok(List(vec![symbol("+"), Int(1), Int(2)])));
+    assert_eq!(eval_str("`[a ~(+ 1 1) {:key ~(* 2 3)}]"), Ok(Vector(vec![
+        symbol("a"),
+        Int(2),
+        map(vec![("key", Int(6))])
+    ])));
+    assert_eq!(eval_str("`~(+ 1 1)"), Ok(Int(2)));
+}
+
+#[test]
+fn test_syntax_quote_unquote_splicing() {
+    assert_eq!(eval_str("`(+ ~@[1 2] 3)"), Ok(List(vec![symbol("+"), Int(1), Int(2), Int(3)])));
+    assert_eq!(eval_str("`[0 ~@'(1 2)]"), Ok(Vector(vec![Int(0), Int(1), Int(2)])));
+    assert_eq!(eval_str("`(a ~@1)"), error_result("Expected list or vector in unquote-splicing but got Int(1)"));
+}
+
+#[test]
+fn test_unquote_outside_syntax_quote() {
+    assert_eq!(eval_str("~a"), error_result("unquote is only allowed inside syntax-quote"));
+    assert_eq!(eval_str("~@a"), error_result("unquote-splicing is only allowed inside syntax-quote"));
+}
+
+#[test]
+fn test_eval_quoted_code() {
+    assert_eq!(eval_str("(do (def code `(+ ~@[1 2])) code)"), Ok(List(vec![symbol("+"), Int(1), Int(2)])));
+}
+
+#[test]
+fn test_defmacro() {
+    assert_eq!(eval_str(r"
+    (do
+        (defmacro infix [a op b] `(~op ~a ~b))
+        (infix 20 + 22)
+    )
+    "), Ok(Int(42)));
+}
+
+#[test]
+fn test_defmacro_gets_unevaluated_args() {
+    assert_eq!(eval_str(r"
+    (do
+        (defmacro ignore_first [ignored value] value)
+        (ignore_first (undefined_function) 23)
+    )
+    "), Ok(Int(23)));
+}
+
+#[test]
+fn test_defmacro_variadic() {
+    assert_eq!(eval_str(r"
+    (do
+        (defmacro effect [trigger & notes] `{:trigger ~trigger :notes [~@notes]})
+        (effect 43 1 (+ 1 1))
+    )
+    "), Ok(map(vec![
+        ("trigger", Int(43)),
+        ("notes", Vector(vec![Int(1), Int(2)]))
+    ])));
+}
+
+#[test]
+fn test_defmacro_errors() {
+    assert_eq!(eval_str("(defmacro)"), error_result("Missing macro name in defmacro"));
+    assert_eq!(eval_str("(defmacro name)"), error_result("Missing args in defmacro"));
+    assert_eq!(eval_str("(defmacro name [])"), error_result("Missing body in defmacro"));
+    assert_eq!(eval_str("(defmacro 1 [] 2)"), error_result("Expected symbol in defmacro but got Int(1)"));
+}
+
+#[test]
+fn test_macroexpand() {
+    assert_eq!(eval_str(r"
+    (do
+        (defmacro plus [a b] `(+ ~a ~b))
+        (defmacro plus_twice [a] `(plus ~a ~a))
+        [(macroexpand-1 '(plus_twice 1)) (macroexpand '(plus_twice 1)) (macroexpand '(+ 1 2))]
+    )
+    "), Ok(Vector(vec![
+        List(vec![symbol("plus"), Int(1), Int(1)]),
+        List(vec![symbol("+"), Int(1), Int(1)]),
+        List(vec![symbol("+"), Int(1), Int(2)])
+    ])));
+    assert_eq!(eval_str("(macroexpand)"), error_result("Missing form in macroexpand"));
+}
+
+#[test]
+fn test_syntax_quote_auto_gensym() {
+    let result = eval_str("`[x# x# y#]").unwrap();
+    if let Vector(symbols) = result {
+        assert_eq!(symbols[0], symbols[1]);
+        assert_ne!(symbols[0], symbols[2]);
+        assert!(symbols[0] != symbol("x#"));
+    } else {
+        panic!("Expected vector but got {:?}", result);
+    }
+}
+
+#[test]
+fn test_hygienic_macro() {
+    assert_eq!(eval_str(r"
+    (do
+        (defmacro double [x] `(do (def value# ~x) (+ value# value#)))
+        (def value 20)
+        [(double (+ value 1)) value]
+    )
+    "), Ok(Vector(vec![Int(42), Int(20)])));
+}
+
+#[test]
+fn test_eval_map_rejects_keys_equal_after_evaluation() {
+    assert_eq!(eval_str("(def a :x) {a 1 :y 2}"), Ok(map(vec![("x", Int(1)), ("y", Int(2))])));
+    assert_eq!(eval_str("(def a :x) {a 1 :x 2}"), error_result("Duplicate key Keyword(\"x\") in map after evaluation"));
+    assert_eq!(eval_str("`{1 :a ~(+ 0 1) :b}"), error_result("Duplicate key Int(1) in map after evaluation"));
+}
+
+#[test]
+fn test_eval_set() {
+    assert_eq!(eval_str("#{1 (+ 1 1)}"), Ok(set(vec![Int(1), Int(2)])));
+    assert_eq!(eval_str("#{1 (+ 0 1)}"), error_result("Duplicate element Int(1) in set after evaluation"));
+    assert_eq!(eval_str("`#{1 ~(+ 0 1)}"), error_result("Duplicate element Int(1) in set after evaluation"));
+    assert_eq!(eval_str("`#{1 ~(+ 1 1)}"), Ok(set(vec![Int(1), Int(2)])));
+}
+
+#[test]
+fn test_eval_map_keys() {
+    assert_eq!(eval_str("{(+ 1 1) :two \"str\" :str :key :keyword}"), Ok(map_from(vec![
+        (Int(2), keyword("two")),
+        (string("str"), keyword("str")),
+        (keyword("key"), keyword("keyword"))
+    ])));
+}
+
+#[test]
+fn test_eval_map_in_source_order() {
+    assert_eq!(eval_str(r"
+    (do
+        {:z (def x 1) :a (def x 2) :m (def x 3)}
+        x
+    )
+    "), Ok(Int(3)));
+}
+
+#[test]
+fn test_eval_map_keeps_order() {
+    let result = eval_str("{:z (+ 1 1) :a 1 :m 3}").unwrap();
+    if let Map(map_value) = result {
+        let keys: Vec<RispType> = map_value.keys().cloned().collect();
+        assert_eq!(keys, vec![keyword("z"), keyword("a"), keyword("m")]);
+    } else {
+        panic!("Expected map but got {:?}", result);
+    }
+}
+
+#[test]
+fn test_ns_qualifies_definitions() {
+    let mut env = create_core_environment();
+    let result = ::eval_risp_script(r"
+        (def kick 1)
+        (ns drums)
+        (def kick 36)
+        (defn double [x] [x x])
+        (double kick)", &mut env);
+    assert_eq!(result, Ok(Vector(vec![Int(36), Int(36)])));
+    assert_eq!(env.current_namespace(), Some("drums"));
+    assert_eq!(env.get("drums/kick"), Some(Int(36)));
+    assert_eq!(env.get("kick"), Some(Int(36)));
+    assert_eq!(::eval_risp_script("(ns song) [kick drums/kick]", &mut env), Ok(Vector(vec![Int(1), Int(36)])));
+}
+
+#[test]
+fn test_ns_function_args_hide_definitions() {
+    assert_eq!(eval_str("(do (ns drums) (def x 1) (defn f [x] x) (f 2))"), Ok(Int(2)));
+}
+
+#[test]
+fn test_ns_errors() {
+    assert_eq!(eval_str("(ns)"), error_result("Missing name in ns"));
+    assert_eq!(eval_str("(ns \"drums\")"), error_result("Expected namespace name in ns but got \"drums\""));
+}
+
+#[test]
+fn test_private_definitions() {
+    let mut env = create_core_environment();
+    let result = ::eval_risp_script(r"
+        (ns drums)
+        (defn- helper [x] (+ x 1))
+        (defn kick [] (helper 35))
+        (ns song)
+        (drums/kick)", &mut env);
+    assert_eq!(result, Ok(Int(36)));
+    assert_eq!(::eval_risp_script("(drums/helper 1)", &mut env), error_result("drums/helper is private"));
+    assert_eq!(::eval_risp_script("drums/helper", &mut env), error_result("drums/helper is private"));
+    assert_eq!(env.public_names("drums"), vec!["kick".to_string()]);
+    assert_eq!(env.namespaces(), vec!["drums".to_string(), "json".to_string(), "music".to_string()]);
+}
+
+#[test]
+fn test_refer() {
+    let mut env = create_core_environment();
+    ::eval_risp_script("(ns drums) (def kick 36) (def snare 38) (defn- helper [] 1) (ns song)", &mut env).unwrap();
+    assert_eq!(::eval_risp_script("(refer 'drums :only '[kick]) kick", &mut env), Ok(Int(36)));
+    assert_eq!(::eval_risp_script("snare", &mut env), error_result("symbol '\"snare\"' is undefined"));
+    assert_eq!(::eval_risp_script("(refer 'drums) [kick snare]", &mut env), Ok(Vector(vec![Int(36), Int(38)])));
+    assert_eq!(env.get("song/snare"), Some(Int(38)));
+    assert_eq!(::eval_risp_script("(refer 'drums :only '[helper])", &mut env), error_result("Can't refer drums/helper, it's private"));
+    assert_eq!(::eval_risp_script("(refer 'drums :only '[hat])", &mut env), error_result("Can't refer drums/hat, it's not defined"));
+}
+
+#[test]
+fn test_eval_with_limits() {
+    let mut env = create_core_environment();
+    ::eval_risp_script("(defn self-apply [f] (f f))", &mut env).unwrap();
+    let limits = EvalLimits { max_call_depth: Some(20), ..EvalLimits::default() };
+    let endless = parse::parse("(self-apply self-apply)").unwrap();
+    let err = eval_with_limits(endless.clone(), &mut env, &limits).unwrap_err();
+    assert_eq!(err, error("Evaluation exceeded the call depth limit of 20").with_kind(ErrorKind::LimitExceeded));
+    assert_eq!(err.kind(), ErrorKind::LimitExceeded);
+
+    let limits = EvalLimits { max_steps: Some(30), max_call_depth: Some(20), ..EvalLimits::default() };
+    assert_eq!(eval_with_limits(endless, &mut env, &limits), Err(error("Evaluation exceeded the limit of 30 steps").with_kind(ErrorKind::LimitExceeded)));
+    assert_eq!(eval_with_limits(parse::parse("(+ 1 2)").unwrap(), &mut env, &limits), Ok(Int(3)));
+}
@@ -0,0 +1,251 @@
+use cst::*;
+use tokenize::{Token, TokenType};
+use types::*;
+
+/// Lists starting with these symbols indent their arguments by two spaces instead of aligning them.
+const BODY_FORMS: &[&str] = &["def", "defn", "defmacro", "fn", "do", "comment"];
+
+/// At most one blank line is kept between forms.
+const MAX_NEW_LINES: usize = 2;
+
+/// Reindents the source and aligns map values, while keeping comments and line breaks between forms.
+pub fn format_source(input: &str) -> Result<String, RispError> {
+    let nodes = parse_cst(input)?;
+    let elements = elements(&nodes);
+    let mut formatter = Formatter { output: String::new() };
+    formatter.format_elements(&elements, 0, false, None);
+    if !elements.is_empty() {
+        formatter.output.push('\n');
+    }
+    Ok(formatter.output)
+}
+
+/// A node which is not whitespace, together with the number of line breaks before it.
+struct Element<'a> {
+    new_lines: usize,
+    node: &'a Cst,
+}
+
+fn elements(nodes: &[Cst]) -> Vec<Element<'_>> {
+    let mut result = vec![];
+    let mut new_lines = 0;
+    for node in nodes {
+        match *node {
+            Cst::Whitespace(ref text) => new_lines += text.matches('\n').count(),
+            _ => {
+                result.push(Element { new_lines, node });
+                new_lines = 0;
+            }
+        }
+    }
+    result
+}
+
+fn is_comment(node: &Cst) -> bool {
+    matches!(*node, Cst::Comment(_))
+}
+
+struct Formatter {
+    output: String,
+}
+
+impl Formatter {
+    fn column(&self) -> usize {
+        self.output.chars().rev().take_while(|&c| c != '\n').count()
+    }
+
+    fn new_line(&mut self, new_lines: usize, indent: usize) {
+        for _ in 0..new_lines.clamp(1, MAX_NEW_LINES) {
+            self.output.push('\n');
+        }
+        self.output.push_str(&" ".repeat(indent));
+    }
+
+    fn format_node(&mut self, node: &Cst) {
+        match *node {
+            Cst::Atom((_, ref text)) => self.output.push_str(text),
+            Cst::Whitespace(_) => {}
+            Cst::Comment(ref text) => self.output.push_str(text.trim_end()),
+            Cst::Collection(ref open, ref children, ref close) => self.format_collection(open, children, close),
+            Cst::Prefixed((_, ref prefix), ref children) => {
+                self.output.push_str(prefix);
+                let indent = self.column();
+                let elements = elements(children);
+                for (i, element) in elements.iter().enumerate() {
+                    if i > 0 && is_comment(elements[i - 1].node) {
+                        self.new_line(1, indent);
+                    }
+                    self.format_node(element.node);
+                }
+            }
+        }
+    }
+
+    fn format_collection(&mut self, open: &Token, children: &[Cst], close: &Token) {
+        let open_column = self.column();
+        self.output.push_str(&open.1);
+        let elements = elements(children);
+
+        let mut indent = open_column + open.1.chars().count();
+        let mut align_with_first_argument = false;
+        if open.0 == TokenType::ListStart {
+            if let Some(&Cst::Atom((TokenType::Symbol, ref name))) = elements.first().map(|element| element.node) {
+                if BODY_FORMS.contains(&&name[..]) {
+                    indent = open_column + 2;
+                } else {
+                    align_with_first_argument = true;
+                }
+            }
+        }
+        let value_column = if open.0 == TokenType::HashMapStart {
+            map_value_column(&elements, indent)
+        } else {
+            None
+        };
+
+        let indent = self.format_elements(&elements, indent, align_with_first_argument, value_column);
+        if elements.last().is_some_and(|element| is_comment(element.node)) {
+            self.new_line(1, indent);
+        }
+        self.output.push_str(&close.1);
+    }
+
+    /// Puts elements on new lines where the source had line breaks and returns the final indent.
+    fn format_elements(&mut self, elements: &[Element], mut indent: usize, align_with_first_argument: bool,
+                       value_column: Option<usize>) -> usize {
+        let mut form_index = 0;
+        for (i, element) in elements.iter().enumerate() {
+            if i > 0 {
+                if element.new_lines > 0 || is_comment(elements[i - 1].node) {
+                    self.new_line(element.new_lines, indent);
+                } else {
+                    let padding = match value_column {
+                        Some(column) if form_index % 2 == 1 => column.saturating_sub(self.column()).max(1),
+                        _ => 1
+                    };
+                    self.output.push_str(&" ".repeat(padding));
+                    if i == 1 && align_with_first_argument && !is_comment(element.node) {
+                        indent = self.column();
+                    }
+                }
+            }
+            self.format_node(element.node);
+            if !is_comment(element.node) {
+                form_index += 1;
+            }
+        }
+        indent
+    }
+}
+
+/// Maps with one entry per line align their values after the longest key.
+fn map_value_column(elements: &[Element], key_column: usize) -> Option<usize> {
+    let mut forms = vec![];
+    for (i, element) in elements.iter().enumerate() {
+        if !is_comment(element.node) {
+            let starts_line = i == 0 || element.new_lines > 0 || is_comment(elements[i - 1].node);
+            forms.push((starts_line, element.node));
+        }
+    }
+    if forms.len() < 4 || forms.len() % 2 != 0 {
+        return None;
+    }
+
+    let mut key_width = 0;
+    for (index, &(starts_line, node)) in forms.iter().enumerate() {
+        if index % 2 == 0 {
+            let key = formatted(node, key_column);
+            if !starts_line || key.contains('\n') {
+                return None;
+            }
+            key_width = key_width.max(key.chars().count());
+        } else if starts_line {
+            return None;
+        }
+    }
+    Some(key_column + key_width + 1)
+}
+
+fn formatted(node: &Cst, column: usize) -> String {
+    let mut formatter = Formatter { output: " ".repeat(column) };
+    formatter.format_node(node);
+    formatter.output.split_off(column)
+}
+
+
+/* ------------------------------ Tests ----------------------------------------------- */
+
+#[cfg(test)]
+use std::fs::File;
+#[cfg(test)]
+use std::io::prelude::*;
+
+#[cfg(test)]
+fn assert_formatted(input: &str, expected: &str) {
+    assert_eq!(format_source(input).unwrap(), expected);
+    assert_eq!(format_source(expected).unwrap(), expected);
+}
+
+#[test]
+fn test_format_whitespace() {
+    assert_formatted("  ( +   1\t2 )  ", "(+ 1 2)\n");
+    assert_formatted("(def a 1)\n\n\n\n(def b 2) (def c 3)", "(def a 1)\n\n(def b 2) (def c 3)\n");
+    assert_formatted("", "");
+}
+
+#[test]
+fn test_format_indent_arguments() {
+    assert_formatted("(rep 6\n(rep 4 45)\n   (rep 4 48))", "(rep 6\n     (rep 4 45)\n     (rep 4 48))\n");
+    assert_formatted("(rep\n6\n7)", "(rep\n 6\n 7)\n");
+    assert_formatted("(1\n2)", "(1\n 2)\n");
+}
+
+#[test]
+fn test_format_indent_body() {
+    assert_formatted("(defn f [x]\n(+ x 1))", "(defn f [x]\n  (+ x 1))\n");
+    assert_formatted("[1 (do\n(def a 1)\n    a)]", "[1 (do\n     (def a 1)\n     a)]\n");
+}
+
+#[test]
+fn test_format_indent_collections() {
+    assert_formatted("[1\n2 #{3\n4}]", "[1\n 2 #{3\n     4}]\n");
+}
+
+#[test]
+fn test_format_align_map_values() {
+    assert_formatted("{:name \"Amazon\"\n:time_per_note 220\n  :notes [1\n2]}",
+                     "{:name          \"Amazon\"\n :time_per_note 220\n :notes         [1\n                 2]}\n");
+    assert_formatted("{:a 1 :long_key 2}", "{:a 1 :long_key 2}\n");
+    assert_formatted("{:a 1 :b 2\n:long_key 3}", "{:a 1 :b 2\n :long_key 3}\n");
+}
+
+#[test]
+fn test_format_keeps_comments() {
+    assert_formatted(";; header   \n(def a ; the answer\n42)", ";; header\n(def a ; the answer\n  42)\n");
+    assert_formatted("[1 ; one\n]", "[1 ; one\n ]\n");
+    assert_formatted("{:a 1\n ; :b 2\n :c 3}", "{:a 1\n ; :b 2\n :c 3}\n");
+}
+
+#[test]
+fn test_format_reader_macros() {
+    assert_formatted("'( a  b)\n`[~ x ~@ y]\n#_ (ignored)", "'(a b)\n`[~x ~@y]\n#_(ignored)\n");
+}
+
+#[test]
+fn test_format_keeps_multi_line_strings() {
+    assert_formatted("(str \"a\n  b\"   1)", "(str \"a\n  b\" 1)\n");
+}
+
+#[test]
+fn test_format_error() {
+    assert_eq!(format_source("(1"), Err(error("Unexpected end of list")));
+}
+
+#[test]
+fn test_examples_are_formatted() {
+    for path in &["examples/kitchen_sink.risp", "examples/song.risp", "examples/simple_song.risp"] {
+        let mut source = String::new();
+        File::open(path).unwrap().read_to_string(&mut source).unwrap();
+        assert_eq!(format_source(&source).unwrap(), source, "{} is not formatted", path);
+    }
+}
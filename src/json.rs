@@ -0,0 +1,320 @@
+use std::collections::HashSet;
+use std::iter::Peekable;
+use std::num::IntErrorKind;
+use std::str::Chars;
+use ser::is_keyword_name;
+use types::*;
+use types::RispType::*;
+
+/// Encodes the value as compact JSON.
+/// Maps become objects, keywords and symbols strings, lists, vectors and sets arrays and nil null.
+pub fn to_json(value: &RispType) -> Result<String, RispError> {
+    let mut output = String::new();
+    write_json(value, &mut output)?;
+    Ok(output)
+}
+
+fn write_json(value: &RispType, output: &mut String) -> Result<(), RispError> {
+    match *value {
+        Nil => output.push_str("null"),
+        Bool(b) => output.push_str(&b.to_string()),
+        Int(i) => output.push_str(&i.to_string()),
+        Str(ref s) | Keyword(ref s) | Symbol(ref s) => write_json_string(s, output),
+        List(ref elements) | Vector(ref elements) => write_json_array(elements.iter(), output)?,
+        Set(ref elements) => write_json_array(elements.iter(), output)?,
+        Map(ref map) => {
+            output.push('{');
+            let mut json_keys = HashSet::new();
+            for (i, (key, value)) in map.iter().enumerate() {
+                if i > 0 {
+                    output.push(',');
+                }
+                let json_key = json_key(key)?;
+                if !json_keys.insert(json_key.clone()) {
+                    return Err(error(format!("Duplicate JSON object key {} for {}", json_key, key)));
+                }
+                write_json_string(&json_key, output);
+                output.push(':');
+                write_json(value, output)?;
+            }
+            output.push('}');
+        }
+        Function(_) | RispFunction(_) | Macro(_) => {
+            return Err(error(format!("Can't convert {} to JSON", value)));
+        }
+    }
+    Ok(())
+}
+
+fn write_json_array<'a, I: Iterator<Item=&'a RispType>>(elements: I, output: &mut String) -> Result<(), RispError> {
+    output.push('[');
+    for (i, element) in elements.enumerate() {
+        if i > 0 {
+            output.push(',');
+        }
+        write_json(element, output)?;
+    }
+    output.push(']');
+    Ok(())
+}
+
+/// JSON object keys are strings, so keywords lose their colon and numbers are written as text.
+/// Keys like :a and "a" become the same JSON key, which to_json reports as duplicate.
+fn json_key(key: &RispType) -> Result<String, RispError> {
+    match *key {
+        Str(ref s) | Keyword(ref s) | Symbol(ref s) => Ok(s.clone()),
+        Int(i) => Ok(i.to_string()),
+        _ => Err(error(format!("Can't use {} as JSON object key", key)))
+    }
+}
+
+fn write_json_string(s: &str, output: &mut String) {
+    output.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => output.push_str("\\\""),
+            '\\' => output.push_str("\\\\"),
+            '\n' => output.push_str("\\n"),
+            '\t' => output.push_str("\\t"),
+            '\r' => output.push_str("\\r"),
+            c if (c as u32) < 0x20 => output.push_str(&format!("\\u{:04x}", c as u32)),
+            _ => output.push(c)
+        }
+    }
+    output.push('"');
+}
+
+/// Decodes JSON, objects become maps with keyword keys, arrays vectors and null nil.
+/// Object keys, which are no valid keyword names like "hi hat", stay strings.
+/// Only integer numbers are supported.
+pub fn from_json(json: &str) -> RispResult {
+    let mut chars = json.chars().peekable();
+    let value = read_value(&mut chars)?;
+    skip_json_whitespace(&mut chars);
+    match chars.next() {
+        None => Ok(value),
+        Some(c) => error_result(format!("Unexpected {} after JSON value", c))
+    }
+}
+
+fn skip_json_whitespace(chars: &mut Peekable<Chars>) {
+    while chars.peek().is_some_and(|c| c.is_whitespace()) {
+        chars.next();
+    }
+}
+
+fn read_value(chars: &mut Peekable<Chars>) -> RispResult {
+    skip_json_whitespace(chars);
+    match chars.peek().cloned() {
+        Some('{') => {
+            chars.next();
+            let mut map = IndexMap::new();
+            if read_end_of_empty(chars, '}') {
+                return Ok(Map(map));
+            }
+            loop {
+                skip_json_whitespace(chars);
+                if chars.peek() != Some(&'"') {
+                    return error_result("Expected string as JSON object key");
+                }
+                let key = read_string(chars)?;
+                skip_json_whitespace(chars);
+                expect(chars, ':')?;
+                let value = read_value(chars)?;
+                let key = if is_keyword_name(&key) { Keyword(key) } else { Str(key) };
+                map.insert(key, value);
+                if read_separator(chars, '}')? {
+                    return Ok(Map(map));
+                }
+            }
+        }
+        Some('[') => {
+            chars.next();
+            let mut vector = vec![];
+            if read_end_of_empty(chars, ']') {
+                return Ok(Vector(vector));
+            }
+            loop {
+                vector.push(read_value(chars)?);
+                if read_separator(chars, ']')? {
+                    return Ok(Vector(vector));
+                }
+            }
+        }
+        Some('"') => read_string(chars).map(Str),
+        Some(c) if c == '-' || c.is_ascii_digit() => read_number(chars),
+        Some(c) if c.is_alphabetic() => {
+            let mut word = String::new();
+            while let Some(c) = chars.peek().cloned().filter(|c| c.is_alphabetic()) {
+                word.push(c);
+                chars.next();
+            }
+            match word.as_str() {
+                "null" => Ok(Nil),
+                "true" => Ok(Bool(true)),
+                "false" => Ok(Bool(false)),
+                _ => error_result(format!("Unexpected {} in JSON", word))
+            }
+        }
+        Some(c) => error_result(format!("Unexpected {} in JSON", c)),
+        None => error_result("Unexpected end of JSON")
+    }
+}
+
+fn read_end_of_empty(chars: &mut Peekable<Chars>, end: char) -> bool {
+    skip_json_whitespace(chars);
+    if chars.peek() == Some(&end) {
+        chars.next();
+        true
+    } else {
+        false
+    }
+}
+
+/// Returns true at the end of the array or object.
+fn read_separator(chars: &mut Peekable<Chars>, end: char) -> Result<bool, RispError> {
+    skip_json_whitespace(chars);
+    match chars.next() {
+        Some(',') => Ok(false),
+        Some(c) if c == end => Ok(true),
+        Some(c) => Err(error(format!("Expected , or {} in JSON but got {}", end, c))),
+        None => Err(error(format!("Expected , or {} in JSON but it just ends", end)))
+    }
+}
+
+fn expect(chars: &mut Peekable<Chars>, expected: char) -> Result<(), RispError> {
+    match chars.next() {
+        Some(c) if c == expected => Ok(()),
+        Some(c) => Err(error(format!("Expected {} in JSON but got {}", expected, c))),
+        None => Err(error(format!("Expected {} in JSON but it just ends", expected)))
+    }
+}
+
+fn read_number(chars: &mut Peekable<Chars>) -> RispResult {
+    let mut number = String::new();
+    while let Some(c) = chars.peek().cloned().filter(|&c| c == '-' || c == '+' || c == '.' || c == 'e' || c == 'E' || c.is_ascii_digit()) {
+        number.push(c);
+        chars.next();
+    }
+    number.parse().map(Int).map_err(|e| match *e.kind() {
+        IntErrorKind::PosOverflow | IntErrorKind::NegOverflow => error(format!("JSON number {} is out of range", number)),
+        _ => error(format!("Unsupported JSON number {}, only integers are supported", number))
+    })
+}
+
+fn read_string(chars: &mut Peekable<Chars>) -> Result<String, RispError> {
+    expect(chars, '"')?;
+    let mut result = String::new();
+    loop {
+        match chars.next() {
+            Some('"') => return Ok(result),
+            Some('\\') => match chars.next() {
+                Some('"') => result.push('"'),
+                Some('\\') => result.push('\\'),
+                Some('/') => result.push('/'),
+                Some('b') => result.push('\u{8}'),
+                Some('f') => result.push('\u{c}'),
+                Some('n') => result.push('\n'),
+                Some('r') => result.push('\r'),
+                Some('t') => result.push('\t'),
+                Some('u') => result.push(read_unicode_escape(chars)?),
+                Some(c) => return Err(error(format!("Unsupported escape sequence \\{} in JSON string", c))),
+                None => return Err(error("Unterminated JSON string"))
+            },
+            Some(c) => result.push(c),
+            None => return Err(error("Unterminated JSON string"))
+        }
+    }
+}
+
+/// Reads the hex digits after \u including a following low surrogate.
+fn read_unicode_escape(chars: &mut Peekable<Chars>) -> Result<char, RispError> {
+    let high = read_hex4(chars)?;
+    let code = if (0xD800..0xDC00).contains(&high) {
+        expect(chars, '\\')?;
+        expect(chars, 'u')?;
+        let low = read_hex4(chars)?;
+        if !(0xDC00..0xE000).contains(&low) {
+            return Err(error(format!("Invalid unicode surrogate pair {:x} {:x} in JSON string", high, low)));
+        }
+        0x10000 + ((high - 0xD800) << 10) + (low - 0xDC00)
+    } else {
+        high
+    };
+    ::std::char::from_u32(code).ok_or_else(|| error(format!("Invalid unicode escape {:x} in JSON string", code)))
+}
+
+fn read_hex4(chars: &mut Peekable<Chars>) -> Result<u32, RispError> {
+    let hex: String = chars.by_ref().take(4).collect();
+    // from_str_radix alone would also accept a sign like in \u+041.
+    match u32::from_str_radix(&hex, 16) {
+        Ok(code) if hex.len() == 4 && hex.chars().all(|c| c.is_ascii_hexdigit()) => Ok(code),
+        _ => Err(error(format!("Invalid unicode escape \\u{} in JSON string", hex)))
+    }
+}
+
+
+/* ------------------------------ Tests ----------------------------------------------- */
+
+#[cfg(test)]
+use parse::parse;
+
+#[cfg(test)]
+fn json_of(risp_code: &str) -> Result<String, RispError> {
+    to_json(&parse(risp_code).unwrap())
+}
+
+#[test]
+fn test_to_json_scalars() {
+    assert_eq!(json_of("nil"), Ok("null".to_string()));
+    assert_eq!(json_of("true"), Ok("true".to_string()));
+    assert_eq!(json_of("-42"), Ok("-42".to_string()));
+    assert_eq!(json_of(":kick"), Ok("\"kick\"".to_string()));
+    assert_eq!(json_of(r#""say \"hi\"\n""#), Ok(r#""say \"hi\"\n""#.to_string()));
+}
+
+#[test]
+fn test_to_json_collections() {
+    assert_eq!(json_of("[1 (2 3) #{4}]"), Ok("[1,[2,3],[4]]".to_string()));
+    assert_eq!(json_of("{:name \"Amazon\" 36 :kick \"notes\" []}"),
+               Ok(r#"{"name":"Amazon","36":"kick","notes":[]}"#.to_string()));
+}
+
+#[test]
+fn test_to_json_errors() {
+    assert_eq!(json_of("{[1] 2}"), Err(error("Can't use [1] as JSON object key")));
+    assert_eq!(json_of("{:a 1 \"a\" 2}"), Err(error("Duplicate JSON object key a for \"a\"")));
+    assert_eq!(json_of("{1 :x \"1\" :y}"), Err(error("Duplicate JSON object key 1 for \"1\"")));
+    assert_eq!(to_json(&Vector(vec![Function(|_| Ok(Nil))])), Err(error("Can't convert #<function> to JSON")));
+}
+
+#[test]
+fn test_from_json() {
+    assert_eq!(from_json(" null "), Ok(Nil));
+    assert_eq!(from_json("[true, false, -42, \"a\\\"\\n\\u00e4\\ud83c\\udfb5\"]"),
+               Ok(Vector(vec![Bool(true), Bool(false), Int(-42), string("a\"\n\u{e4}\u{1f3b5}")])));
+    assert_eq!(from_json(r#"{"name": "Amazon", "notes": [], "effects": {}}"#),
+               Ok(map(vec![("name", string("Amazon")), ("notes", Vector(vec![])), ("effects", map::<&str>(vec![]))])));
+    assert_eq!(from_json(r#"{"hi hat": 1, "kick": 2}"#), Ok(map_from(vec![(string("hi hat"), Int(1)), (keyword("kick"), Int(2))])));
+}
+
+#[test]
+fn test_from_json_errors() {
+    assert_eq!(from_json("1.5"), error_result("Unsupported JSON number 1.5, only integers are supported"));
+    assert_eq!(from_json("9223372036854775808"), error_result("JSON number 9223372036854775808 is out of range"));
+    assert_eq!(from_json("-9223372036854775809"), error_result("JSON number -9223372036854775809 is out of range"));
+    assert_eq!(from_json(r#""\u+041""#), error_result("Invalid unicode escape \\u+041 in JSON string"));
+    assert_eq!(from_json(r#""\u04""#), error_result("Invalid unicode escape \\u04\" in JSON string"));
+    assert_eq!(from_json("[1 2]"), error_result("Expected , or ] in JSON but got 2"));
+    assert_eq!(from_json("{1: 2}"), error_result("Expected string as JSON object key"));
+    assert_eq!(from_json("\"open"), error_result("Unterminated JSON string"));
+    assert_eq!(from_json("nope"), error_result("Unexpected nope in JSON"));
+    assert_eq!(from_json("1 2"), error_result("Unexpected 2 after JSON value"));
+    assert_eq!(from_json(""), error_result("Unexpected end of JSON"));
+}
+
+#[test]
+fn test_json_round_trip() {
+    let value = parse("{:name \"Amazon\" :effects [{:trigger 43 :enabled true :note nil}]}").unwrap();
+    assert_eq!(from_json(&to_json(&value).unwrap()), Ok(value));
+}
@@ -1,30 +1,85 @@
 #[macro_use] extern crate lazy_static;
+extern crate indexmap;
 extern crate regex;
+#[macro_use] extern crate serde;
+#[cfg(test)] #[macro_use] extern crate serde_derive;
 
+pub mod capabilities;
 pub mod convert;
 pub mod core;
+pub mod cst;
+pub mod de;
 pub mod environment;
 pub mod eval;
+pub mod formatter;
+pub mod json;
+pub mod limits;
+pub mod modules;
+pub mod music;
 pub mod parse;
+pub mod printer;
+pub mod random;
+pub mod repl;
+pub mod rhythm;
+pub mod schema;
+pub mod ser;
 pub mod tokenize;
 pub mod types;
 
-use types::RispResult;
-use parse::parse;
-use eval::eval;
+use types::{RispError, RispResult, symbol};
+use types::RispType::List;
+use parse::{parse, parse_all_with_options, ParseOptions};
+use eval::{eval_top_level, eval_with_limits};
+use limits::EvalLimits;
 use environment::Environment;
 use core::create_core_environment;
 
+pub use de::{from_str, from_value};
+pub use ser::{to_string, to_string_pretty, to_value};
+
 pub fn eval_risp(risp_code: &str) -> RispResult {
     eval_risp_for_env(risp_code, &mut create_core_environment())
 }
 
 pub fn eval_risp_for_env(risp_code: &str, env: &mut Environment) -> RispResult {
     let ast = parse(risp_code)?;
-    eval(ast, env)
+    eval_top_level(ast, env)
 }
 
 pub fn eval_risp_script(risp_code: &str, env: &mut Environment) -> RispResult {
-    let ast = parse(&("(do ".to_string() + risp_code + ")"))?;
-    eval(ast, env)
-}
\ No newline at end of file
+    eval_risp_script_with_options(risp_code, env, &ParseOptions::default()).map(|(result, _warnings)| result)
+}
+
+/// Evaluates all forms of the script and returns the result of the last one together with the parse warnings.
+pub fn eval_risp_script_with_options(risp_code: &str, env: &mut Environment, options: &ParseOptions) -> Result<(types::RispType, Vec<String>), RispError> {
+    let (script, warnings) = parse_script(risp_code, options)?;
+    let result = eval_top_level(script, env)?;
+    Ok((result, warnings))
+}
+
+/// Evaluates all forms of the script like eval_risp_script, but aborts when one of the limits is exceeded.
+pub fn eval_risp_script_with_limits(risp_code: &str, env: &mut Environment, limits: &EvalLimits) -> RispResult {
+    let (script, _warnings) = parse_script(risp_code, &ParseOptions::default())?;
+    eval_with_limits(script, env, limits)
+}
+
+/// Parses all forms of the script into a single do form.
+fn parse_script(risp_code: &str, options: &ParseOptions) -> Result<(types::RispType, Vec<String>), RispError> {
+    let (forms, warnings) = parse_all_with_options(risp_code, options)?;
+    let mut do_form = vec![symbol("do")];
+    do_form.extend(forms);
+    Ok((List(do_form), warnings))
+}
+
+/* ------------------------------ Test helpers ----------------------------------------- */
+
+/// Evaluates the script in a fresh core environment.
+#[cfg(test)]
+fn eval_str(risp_code: &str) -> RispResult {
+    eval_risp_script(risp_code, &mut create_core_environment())
+}
+
+#[cfg(test)]
+fn ints(numbers: &[i64]) -> types::RispType {
+    types::RispType::Vector(numbers.iter().map(|n| types::RispType::Int(*n)).collect())
+}
@@ -0,0 +1,264 @@
+use std::cell::RefCell;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Instant;
+use types::*;
+
+/// Cancels a running evaluation from another thread, clones share the same state.
+#[derive(Debug, Default, Clone)]
+pub struct CancellationToken {
+    cancelled: Arc<AtomicBool>,
+}
+
+impl CancellationToken {
+    pub fn new() -> CancellationToken {
+        CancellationToken::default()
+    }
+
+    /// The evaluation stops with an error of kind Cancelled at its next function call or iteration.
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::SeqCst)
+    }
+}
+
+/// Tokens are equal if they cancel the same evaluations.
+impl PartialEq for CancellationToken {
+    fn eq(&self, other: &CancellationToken) -> bool {
+        Arc::ptr_eq(&self.cancelled, &other.cancelled)
+    }
+}
+
+/// Limits for evaluating untrusted code, the default is unlimited.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct EvalLimits {
+    /// Maximal number of evaluated forms.
+    pub max_steps: Option<u64>,
+    /// Maximal nesting of function and macro calls.
+    /// Each call needs native stack, so untrusted code should always get a limit that fits the thread's stack.
+    pub max_call_depth: Option<usize>,
+    /// Maximal number of elements of a collection created by a builtin.
+    pub max_collection_size: Option<usize>,
+    /// Maximal length in bytes of a string created by a builtin.
+    pub max_string_length: Option<usize>,
+    /// The evaluation is aborted after this point in time.
+    pub deadline: Option<Instant>,
+    /// The evaluation is aborted when the token is cancelled.
+    pub cancellation: Option<CancellationToken>,
+}
+
+struct LimitState {
+    limits: EvalLimits,
+    steps: u64,
+    call_depth: usize,
+}
+
+thread_local! {
+    static ACTIVE_LIMITS: RefCell<Option<LimitState>> = const { RefCell::new(None) };
+}
+
+/// Runs f with the limits active for the current thread, nested runs restore the outer limits afterwards.
+pub fn with_limits<T, F: FnOnce() -> T>(limits: &EvalLimits, f: F) -> T {
+    let state = LimitState { limits: limits.clone(), steps: 0, call_depth: 0 };
+    let outer = ACTIVE_LIMITS.with(|active| active.replace(Some(state)));
+    let _restore = Restore(outer);
+    f()
+}
+
+/// Puts the outer limits back, even if f panics.
+struct Restore(Option<LimitState>);
+
+impl Drop for Restore {
+    fn drop(&mut self) {
+        let outer = self.0.take();
+        ACTIVE_LIMITS.with(|active| *active.borrow_mut() = outer);
+    }
+}
+
+fn limit_error<S: Into<String>>(message: S) -> RispError {
+    error(message).with_kind(ErrorKind::LimitExceeded)
+}
+
+/// Counts one evaluation step and checks the step budget and the deadline.
+pub fn count_step() -> Result<(), RispError> {
+    ACTIVE_LIMITS.with(|active| {
+        if let Some(ref mut state) = *active.borrow_mut() {
+            state.steps += 1;
+            if let Some(max_steps) = state.limits.max_steps {
+                if state.steps > max_steps {
+                    return Err(limit_error(format!("Evaluation exceeded the limit of {} steps", max_steps)));
+                }
+            }
+            if let Some(deadline) = state.limits.deadline {
+                if Instant::now() >= deadline {
+                    return Err(limit_error("Evaluation exceeded its deadline"));
+                }
+            }
+        }
+        Ok(())
+    })
+}
+
+/// Fails if the token of the active limits is cancelled, called at function calls and iterations.
+pub fn check_cancelled() -> Result<(), RispError> {
+    ACTIVE_LIMITS.with(|active| {
+        let cancelled = active.borrow().as_ref()
+            .and_then(|state| state.limits.cancellation.as_ref())
+            .is_some_and(CancellationToken::is_cancelled);
+        if cancelled {
+            Err(error("Evaluation was cancelled").with_kind(ErrorKind::Cancelled))
+        } else {
+            Ok(())
+        }
+    })
+}
+
+/// Enters a function call, the depth is decreased again when the returned guard is dropped.
+pub fn enter_call() -> Result<CallGuard, RispError> {
+    check_cancelled()?;
+    ACTIVE_LIMITS.with(|active| {
+        if let Some(ref mut state) = *active.borrow_mut() {
+            if let Some(max_call_depth) = state.limits.max_call_depth {
+                if state.call_depth >= max_call_depth {
+                    return Err(limit_error(format!("Evaluation exceeded the call depth limit of {}", max_call_depth)));
+                }
+            }
+            state.call_depth += 1;
+        }
+        Ok(CallGuard)
+    })
+}
+
+pub struct CallGuard;
+
+impl Drop for CallGuard {
+    fn drop(&mut self) {
+        ACTIVE_LIMITS.with(|active| {
+            if let Some(ref mut state) = *active.borrow_mut() {
+                state.call_depth = state.call_depth.saturating_sub(1);
+            }
+        })
+    }
+}
+
+/// Checks the size of a collection before the builtin with the name allocates it.
+pub fn check_collection_size(name: &str, size: Option<usize>) -> Result<(), RispError> {
+    check_size(name, size, "elements", |limits| limits.max_collection_size)
+}
+
+/// Checks the length of a string before the builtin with the name allocates it.
+pub fn check_string_length(name: &str, length: Option<usize>) -> Result<(), RispError> {
+    check_size(name, length, "bytes", |limits| limits.max_string_length)
+}
+
+/// None as size means that it doesn't even fit into memory.
+fn check_size<F: Fn(&EvalLimits) -> Option<usize>>(name: &str, size: Option<usize>, unit: &str, max: F) -> Result<(), RispError> {
+    ACTIVE_LIMITS.with(|active| {
+        let max_size = active.borrow().as_ref().and_then(|state| max(&state.limits));
+        match (size, max_size) {
+            (Some(size), Some(max_size)) if size > max_size =>
+                Err(limit_error(format!("{} would create {} {} but the limit is {}", name, size, unit, max_size))),
+            (None, Some(max_size)) =>
+                Err(limit_error(format!("{} would create more than {} {}", name, max_size, unit))),
+            (None, None) => Err(error(format!("{} would create too many {}", name, unit))),
+            _ => Ok(())
+        }
+    })
+}
+
+/* ------------------------------ Tests ----------------------------------------------- */
+
+#[cfg(test)]
+use std::time::Duration;
+#[cfg(test)]
+use std::thread;
+#[cfg(test)]
+use core::create_core_environment;
+
+#[test]
+fn test_no_limits_outside_of_with_limits() {
+    for _ in 0..1000 {
+        assert_eq!(count_step(), Ok(()));
+    }
+    assert_eq!(check_collection_size("rep", Some(usize::MAX)), Ok(()));
+}
+
+#[test]
+fn test_step_limit() {
+    let limits = EvalLimits { max_steps: Some(2), ..EvalLimits::default() };
+    with_limits(&limits, || {
+        assert_eq!(count_step(), Ok(()));
+        assert_eq!(count_step(), Ok(()));
+        let err = count_step().unwrap_err();
+        assert_eq!(err, limit_error("Evaluation exceeded the limit of 2 steps"));
+        assert_eq!(err.kind(), ErrorKind::LimitExceeded);
+    });
+    assert_eq!(count_step(), Ok(()));
+}
+
+#[test]
+fn test_call_depth_limit() {
+    let limits = EvalLimits { max_call_depth: Some(1), ..EvalLimits::default() };
+    with_limits(&limits, || {
+        {
+            let _guard = enter_call().unwrap();
+            assert_eq!(enter_call().err(), Some(limit_error("Evaluation exceeded the call depth limit of 1")));
+        }
+        assert!(enter_call().is_ok());
+    });
+}
+
+#[test]
+fn test_deadline() {
+    let limits = EvalLimits { deadline: Some(Instant::now() - Duration::from_millis(1)), ..EvalLimits::default() };
+    with_limits(&limits, || {
+        assert_eq!(count_step(), Err(limit_error("Evaluation exceeded its deadline")));
+    });
+}
+
+#[test]
+fn test_size_limits() {
+    let limits = EvalLimits { max_collection_size: Some(10), max_string_length: Some(5), ..EvalLimits::default() };
+    with_limits(&limits, || {
+        assert_eq!(check_collection_size("rep", Some(10)), Ok(()));
+        assert_eq!(check_collection_size("rep", Some(11)), Err(limit_error("rep would create 11 elements but the limit is 10")));
+        assert_eq!(check_collection_size("rep", None), Err(limit_error("rep would create more than 10 elements")));
+        assert_eq!(check_string_length("str", Some(6)), Err(limit_error("str would create 6 bytes but the limit is 5")));
+    });
+}
+
+#[test]
+fn test_cancelled_token() {
+    let token = CancellationToken::new();
+    let limits = EvalLimits { cancellation: Some(token.clone()), ..EvalLimits::default() };
+    with_limits(&limits, || {
+        assert_eq!(check_cancelled(), Ok(()));
+        token.cancel();
+        let err = enter_call().err().unwrap();
+        assert_eq!(err, error("Evaluation was cancelled").with_kind(ErrorKind::Cancelled));
+        assert_eq!(err.kind(), ErrorKind::Cancelled);
+    });
+    assert_eq!(check_cancelled(), Ok(()));
+}
+
+#[test]
+fn test_cancel_from_another_thread() {
+    let token = CancellationToken::new();
+    let limits = EvalLimits { cancellation: Some(token.clone()), ..EvalLimits::default() };
+    // Calls inc 2^30 times with a call depth of only 30.
+    let mut script = "(defn twice [f] (fn [x] (f (f x)))) (defn f0 [x] (+ x 1))".to_string();
+    for i in 1..31 {
+        script.push_str(&format!(" (def f{} (twice f{}))", i, i - 1));
+    }
+    script.push_str(" (f30 0)");
+    let evaluation = thread::Builder::new().stack_size(64 * 1024 * 1024).spawn(move || {
+        ::eval_risp_script_with_limits(&script, &mut create_core_environment(), &limits).map(|_| ())
+    }).unwrap();
+    thread::sleep(Duration::from_millis(50));
+    token.cancel();
+    let err = evaluation.join().unwrap().unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::Cancelled);
+}
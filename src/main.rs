@@ -1,27 +1,343 @@
-#[macro_use] extern crate lazy_static;
-extern crate regex;
-
-mod core;
-mod environment;
-mod eval;
-mod parse;
-mod types;
-mod tokenize;
+extern crate risp;
 
+use std::env;
 use std::fs::File;
+use std::io;
 use std::io::prelude::*;
-use parse::parse;
-use eval::eval;
-use core::create_core_environment;
+use std::process;
+use risp::eval_risp_script;
+use risp::capabilities::{not_granted_error, Capability};
+use risp::core::create_core_environment;
+use risp::environment::Environment;
+use risp::eval::eval_top_level;
+use risp::formatter::format_source;
+use risp::json::to_json;
+use risp::modules::FileResolver;
+use risp::parse::{parse_all_with_options, ParseOptions};
+use risp::printer::{pretty_print, DEFAULT_WIDTH};
+use risp::repl::{Repl, ReplResponse};
+use risp::types::RispType;
+use risp::types::RispType::*;
+
+const USAGE: &str = "Usage: risp [OPTIONS] [FILE | - | -e EXPRESSION]...
+       risp fmt [--check] FILES...
+       risp repl [--allow CAPS] [--seed SEED] [-D NAME=VALUE]...
+
+Evaluates the inputs in order in one environment and prints the result of the last one.
+With --allow fs-read load and require read files below the current directory, (require 'lib.drums) reads lib/drums.risp.
+
+Options:
+  -e EXPRESSION      Evaluates the expression
+  -                  Reads risp code from stdin
+  -D NAME=VALUE      Defines NAME before evaluating the inputs, VALUE is risp (bare words are strings)
+  --allow CAPS       Grants capabilities separated by commas: env, time, random, fs-read
+  --seed SEED        Seeds rand-int, shuffle ... outside of (with-seed SEED ...)
+  -f, --format FMT   Output format: pretty (default), risp or json
+  -h, --help         Prints this help";
+
+/// Exit code for evaluation errors.
+const EXIT_ERROR: i32 = 1;
+/// Exit code for invalid arguments and unreadable inputs.
+const EXIT_USAGE: i32 = 2;
 
+enum Input {
+    File(String),
+    Expression(String),
+    Stdin,
+}
+
+enum OutputFormat {
+    Pretty,
+    Risp,
+    Json,
+}
+
+struct Options {
+    inputs: Vec<Input>,
+    definitions: Vec<(String, String)>,
+    capabilities: Vec<Capability>,
+    seed: Option<u64>,
+    format: OutputFormat,
+}
 
 fn main() {
-    let mut file = File::open("examples/kitchen_sink.risp").unwrap();
-    let mut risp_code = String::new();
-    file.read_to_string(&mut risp_code).unwrap();
+    let args: Vec<String> = env::args().skip(1).collect();
+    match args.first().map(String::as_str) {
+        Some("fmt") => process::exit(format_files(&args[1..])),
+        Some("repl") => process::exit(repl(&args[1..])),
+        _ => {}
+    }
+    process::exit(run(&args));
+}
+
+fn run(args: &[String]) -> i32 {
+    let options = match parse_args(args) {
+        Ok(Some(ref options)) if options.inputs.is_empty() => {
+            eprintln!("risp: Missing input\n\n{}", USAGE);
+            return EXIT_USAGE;
+        }
+        Ok(Some(options)) => options,
+        Ok(None) => {
+            println!("{}", USAGE);
+            return 0;
+        }
+        Err(message) => {
+            eprintln!("risp: {}\n\n{}", message, USAGE);
+            return EXIT_USAGE;
+        }
+    };
+
+    let mut env = match create_environment(&options) {
+        Ok(env) => env,
+        Err(message) => {
+            eprintln!("risp: {}", message);
+            return EXIT_ERROR;
+        }
+    };
 
-    let ast = parse(&("(do ".to_string() + &risp_code + ")")).unwrap();
-    let result = eval(ast, &mut create_core_environment());
-    println!("{:?}", result);
+    let mut result = Nil;
+    for input in &options.inputs {
+        let (source_name, risp_code) = match read_input(input) {
+            Ok(source) => source,
+            Err(message) => {
+                eprintln!("risp: {}", message);
+                return EXIT_USAGE;
+            }
+        };
+        match eval_risp_script(&risp_code, &mut env) {
+            Ok(value) => result = value,
+            Err(error) => {
+                eprintln!("risp: {}: {}", source_name, error);
+                return EXIT_ERROR;
+            }
+        }
+    }
+
+    match format_output(&result, &options.format) {
+        Ok(output) => {
+            println!("{}", output);
+            0
+        }
+        Err(message) => {
+            eprintln!("risp: {}", message);
+            EXIT_ERROR
+        }
+    }
 }
 
+/// Returns None if only the help was requested.
+fn parse_args(args: &[String]) -> Result<Option<Options>, String> {
+    let mut options = Options { inputs: vec![], definitions: vec![], capabilities: vec![], seed: None, format: OutputFormat::Pretty };
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-h" | "--help" => return Ok(None),
+            "-" => options.inputs.push(Input::Stdin),
+            "-e" => {
+                let expression = args.next().ok_or("Missing expression after -e")?;
+                options.inputs.push(Input::Expression(expression.clone()));
+            }
+            "-D" => {
+                let definition = args.next().ok_or("Missing NAME=VALUE after -D")?;
+                options.definitions.push(parse_definition(definition)?);
+            }
+            "--allow" => {
+                let names = args.next().ok_or("Missing capabilities after --allow")?;
+                for name in names.split(',') {
+                    let capability = Capability::from_name(name).ok_or_else(|| format!("Unknown capability {}", name))?;
+                    options.capabilities.push(capability);
+                }
+            }
+            "--seed" => {
+                let seed = args.next().ok_or("Missing seed after --seed")?;
+                options.seed = Some(seed.parse().map_err(|_| format!("Expected a non-negative number as seed but got {}", seed))?);
+            }
+            "-f" | "--format" => {
+                options.format = match args.next().map(String::as_str) {
+                    Some("pretty") => OutputFormat::Pretty,
+                    Some("risp") => OutputFormat::Risp,
+                    Some("json") => OutputFormat::Json,
+                    Some(other) => return Err(format!("Unknown format {}", other)),
+                    None => return Err("Missing format after --format".to_string())
+                };
+            }
+            _ if arg.starts_with("-D") => options.definitions.push(parse_definition(&arg[2..])?),
+            _ if arg.starts_with('-') => return Err(format!("Unknown option {}", arg)),
+            _ => options.inputs.push(Input::File(arg.clone()))
+        }
+    }
+    Ok(Some(options))
+}
+
+/// Creates the environment with the capabilities, seed and definitions of the options.
+/// load and require only read files if fs-read is granted.
+fn create_environment(options: &Options) -> Result<Environment, String> {
+    let mut env = create_core_environment();
+    if options.capabilities.contains(&Capability::FsRead) {
+        env.set_module_resolver(FileResolver::new("."));
+    } else {
+        env.set_module_resolver(|_name: &str| Err(not_granted_error("load and require", Capability::FsRead)));
+    }
+    for capability in &options.capabilities {
+        env.grant(*capability);
+    }
+    if let Some(seed) = options.seed {
+        env.set_random_seed(seed);
+    }
+    for (name, value) in &options.definitions {
+        define(&mut env, name, value).map_err(|message| format!("-D {}: {}", name, message))?;
+    }
+    Ok(env)
+}
+
+fn parse_definition(definition: &str) -> Result<(String, String), String> {
+    match definition.find('=') {
+        Some(index) if index > 0 => Ok((definition[..index].to_string(), definition[index + 1..].to_string())),
+        _ => Err(format!("Expected NAME=VALUE but got {}", definition))
+    }
+}
+
+/// Evaluates the value as single risp form, but takes bare words and everything else unparsable as string.
+fn define(env: &mut Environment, name: &str, value: &str) -> Result<(), String> {
+    let risp_value = match parse_all_with_options(value, &ParseOptions::default()) {
+        Ok((ref forms, _)) if forms.len() == 1 && !is_symbol(&forms[0]) => {
+            eval_top_level(forms[0].clone(), env).map_err(|error| error.to_string())?
+        }
+        _ => Str(value.to_string())
+    };
+    env.set(name, risp_value);
+    Ok(())
+}
+
+fn is_symbol(form: &RispType) -> bool {
+    matches!(*form, Symbol(_))
+}
+
+/// Returns the name of the input for error messages and its risp code.
+fn read_input(input: &Input) -> Result<(String, String), String> {
+    match *input {
+        Input::File(ref path) => read_file(path).map(|code| (path.clone(), code)),
+        Input::Expression(ref expression) => Ok(("-e".to_string(), expression.clone())),
+        Input::Stdin => {
+            let mut code = String::new();
+            io::stdin().read_to_string(&mut code).map_err(|error| format!("stdin: {}", error))?;
+            Ok(("stdin".to_string(), code))
+        }
+    }
+}
+
+fn format_output(result: &RispType, format: &OutputFormat) -> Result<String, String> {
+    match *format {
+        OutputFormat::Pretty => Ok(pretty_print(result, DEFAULT_WIDTH)),
+        OutputFormat::Risp => Ok(result.to_string()),
+        OutputFormat::Json => to_json(result).map_err(|error| error.to_string())
+    }
+}
+
+/// Reads lines from stdin until :quit or the end of the input and keeps the history in ~/.risp_history.
+fn repl(args: &[String]) -> i32 {
+    let options = match parse_args(args) {
+        Ok(Some(ref options)) if !options.inputs.is_empty() => {
+            eprintln!("risp: risp repl takes no inputs\n\n{}", USAGE);
+            return EXIT_USAGE;
+        }
+        Ok(Some(options)) => options,
+        Ok(None) => {
+            println!("{}", USAGE);
+            return 0;
+        }
+        Err(message) => {
+            eprintln!("risp: {}\n\n{}", message, USAGE);
+            return EXIT_USAGE;
+        }
+    };
+    let mut repl = match create_environment(&options) {
+        Ok(env) => Repl::with_environment(env),
+        Err(message) => {
+            eprintln!("risp: {}", message);
+            return EXIT_ERROR;
+        }
+    };
+    let history_path = env::var("HOME").ok().map(|home| format!("{}/.risp_history", home));
+    if let Some(content) = history_path.as_ref().and_then(|path| read_file(path).ok()) {
+        repl.load_history(&content);
+    }
+    println!("risp repl, :help lists the commands");
+
+    let stdin = io::stdin();
+    loop {
+        print!("{}", repl.prompt());
+        io::stdout().flush().unwrap();
+        let mut line = String::new();
+        match stdin.lock().read_line(&mut line) {
+            Ok(0) => {
+                println!();
+                break;
+            }
+            Ok(_) => {}
+            Err(error) => {
+                eprintln!("risp: stdin: {}", error);
+                return EXIT_USAGE;
+            }
+        }
+        let response = repl.handle_line(line.trim_end_matches(&['\n', '\r'][..]));
+        if let Some(ref path) = history_path {
+            save_history(&repl, path);
+        }
+        match response {
+            ReplResponse::Incomplete | ReplResponse::Nothing => {}
+            ReplResponse::Output(output) => println!("{}", output),
+            ReplResponse::Error(message) => println!("Error: {}", message),
+            ReplResponse::Quit => break
+        }
+    }
+    0
+}
+
+/// Saves the history after every entry, so that it survives a killed session.
+fn save_history(repl: &Repl, path: &str) {
+    if let Err(error) = File::create(path).and_then(|mut file| file.write_all(repl.history_file_content().as_bytes())) {
+        eprintln!("risp: {}: {}", path, error);
+    }
+}
+
+/// risp fmt [--check] FILES... rewrites the files formatted, --check only reports unformatted files.
+fn format_files(args: &[String]) -> i32 {
+    let check = args.iter().any(|arg| arg == "--check");
+    let paths: Vec<&String> = args.iter().filter(|arg| *arg != "--check").collect();
+    if paths.is_empty() {
+        eprintln!("Usage: risp fmt [--check] FILES...");
+        return EXIT_USAGE;
+    }
+
+    let mut exit_code = 0;
+    for path in paths {
+        let result = read_file(path).and_then(|source| {
+            let formatted = format_source(&source).map_err(|error| format!("{}: {}", path, error))?;
+            if formatted == source {
+                Ok(())
+            } else if check {
+                println!("{} is not formatted", path);
+                exit_code = EXIT_ERROR;
+                Ok(())
+            } else {
+                File::create(path)
+                    .and_then(|mut file| file.write_all(formatted.as_bytes()))
+                    .map_err(|error| format!("{}: {}", path, error))
+            }
+        });
+        if let Err(message) = result {
+            eprintln!("risp: {}", message);
+            exit_code = EXIT_ERROR;
+        }
+    }
+    exit_code
+}
+
+fn read_file(path: &str) -> Result<String, String> {
+    let mut source = String::new();
+    File::open(path)
+        .and_then(|mut file| file.read_to_string(&mut source))
+        .map_err(|error| format!("{}: {}", path, error))?;
+    Ok(source)
+}
@@ -0,0 +1,352 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+use std::rc::Rc;
+use core::create_core_environment;
+use environment::Environment;
+use eval::eval;
+use parse::{parse_all_with_options, ParseOptions};
+use tokenize::split_qualified_symbol;
+use types::*;
+use types::RispType::*;
+
+/// Finds the source code of modules for `load` and `require`.
+pub trait ModuleResolver {
+    /// Returns the source for a module name like `drums` or a path like `lib/drums.risp`.
+    fn resolve(&self, name: &str) -> Result<String, RispError>;
+}
+
+/// Reads modules from files below the root directory, `drums` and `lib.drums` become `drums.risp` and `lib/drums.risp`.
+pub struct FileResolver {
+    root: PathBuf,
+}
+
+impl FileResolver {
+    pub fn new<P: Into<PathBuf>>(root: P) -> FileResolver {
+        FileResolver { root: root.into() }
+    }
+}
+
+impl ModuleResolver for FileResolver {
+    fn resolve(&self, name: &str) -> Result<String, RispError> {
+        let relative_path = if name.ends_with(".risp") {
+            name.to_string()
+        } else {
+            format!("{}.risp", name.replace('.', "/"))
+        };
+        // Absolute paths and .. would read files outside of the root.
+        if !Path::new(&relative_path).components().all(|component| matches!(component, Component::Normal(_))) {
+            return Err(error(format!("Module {} must be a relative path below the module root", name)));
+        }
+        let path = self.root.join(relative_path);
+        fs::read_to_string(&path).map_err(|e| error(format!("Can't read module {} from {}: {}", name, path.display(), e)))
+    }
+}
+
+/// Modules held in memory, e.g. for tests or embedders which store scripts elsewhere.
+#[derive(Default)]
+pub struct MemoryResolver {
+    modules: HashMap<String, String>,
+}
+
+impl MemoryResolver {
+    pub fn new() -> MemoryResolver {
+        MemoryResolver::default()
+    }
+
+    pub fn add(mut self, name: &str, source: &str) -> MemoryResolver {
+        self.modules.insert(name.to_string(), source.to_string());
+        self
+    }
+}
+
+impl ModuleResolver for MemoryResolver {
+    fn resolve(&self, name: &str) -> Result<String, RispError> {
+        self.modules.get(name).cloned().ok_or_else(|| error(format!("Unknown module {}", name)))
+    }
+}
+
+/// Any function can be used as custom resolver.
+impl<F: Fn(&str) -> Result<String, RispError>> ModuleResolver for F {
+    fn resolve(&self, name: &str) -> Result<String, RispError> {
+        self(name)
+    }
+}
+
+/// The resolver together with the already required modules, shared by all copies of an environment.
+pub struct Modules {
+    resolver: Box<dyn ModuleResolver>,
+    /// The definitions of each required module by module name.
+    required: RefCell<HashMap<String, Vec<(String, RispType)>>>,
+    /// Modules and files which are currently loading, to detect cycles.
+    loading: RefCell<Vec<String>>,
+}
+
+impl Modules {
+    pub fn new<R: ModuleResolver + 'static>(resolver: R) -> Modules {
+        Modules { resolver: Box::new(resolver), required: RefCell::new(HashMap::new()), loading: RefCell::new(vec![]) }
+    }
+
+    /// Evaluates the source in the environment, fails if the name is already loading.
+    fn eval_source(&self, name: &str, env: &mut Environment) -> RispResult {
+        if self.loading.borrow().iter().any(|loading_name| loading_name == name) {
+            let mut cycle = self.loading.borrow().clone();
+            cycle.push(name.to_string());
+            return error_result(format!("Cyclic load of {}: {}", name, cycle.join(" -> ")));
+        }
+        let source = self.resolver.resolve(name)?;
+        self.loading.borrow_mut().push(name.to_string());
+        let result = parse_all_with_options(&source, &ParseOptions::default()).and_then(|(forms, _warnings)| {
+            let mut result = Nil;
+            for form in forms {
+                result = eval(form, env)?;
+            }
+            Ok(result)
+        });
+        self.loading.borrow_mut().pop();
+        result
+    }
+}
+
+fn modules_of(env: &Environment, form_name: &str) -> Result<Rc<Modules>, RispError> {
+    env.modules().ok_or_else(|| error(format!("{} needs a module resolver, see Environment::set_module_resolver", form_name)))
+}
+
+/// Evaluates the file in the current environment and returns the last result.
+pub fn load(path: &RispType, env: &mut Environment) -> RispResult {
+    match *path {
+        Str(ref path) => modules_of(env, "load")?.eval_source(path, env),
+        _ => error_result(format!("load wants a String but got {:?}", path))
+    }
+}
+
+/// Makes the public definitions of the module available as `drums/name` for specs like `drums` or
+/// `[drums :as d :refer [kick]]`. Each module is evaluated only once in its own environment.
+pub fn require(spec: &RispType, env: &mut Environment) -> RispResult {
+    let spec = parse_require_spec(spec)?;
+    let modules = modules_of(env, "require")?;
+    let cached = modules.required.borrow().get(&spec.module_name).cloned();
+    let definitions = match cached {
+        Some(definitions) => definitions,
+        None => {
+            let definitions = eval_module(&modules, &spec.module_name, env)?;
+            modules.required.borrow_mut().insert(spec.module_name.clone(), definitions.clone());
+            definitions
+        }
+    };
+    for (name, value) in definitions {
+        env.set(&format!("{}/{}", spec.module_name, name), value);
+    }
+    if let Some(alias) = spec.alias {
+        env.add_alias(&alias, &spec.module_name);
+    }
+    if let Some(names) = spec.refer {
+        refer(&spec.module_name, names, env)?;
+    }
+    Ok(Nil)
+}
+
+/// Makes the names (or all public names of the namespace) available without namespace in the current namespace.
+pub fn refer(namespace: &str, names: Option<Vec<String>>, env: &mut Environment) -> Result<(), RispError> {
+    let names = names.unwrap_or_else(|| env.public_names(namespace));
+    for name in names {
+        let qualified_name = format!("{}/{}", namespace, name);
+        if env.is_private(&qualified_name) {
+            return Err(error(format!("Can't refer {}, it's private", qualified_name)));
+        }
+        let value = env.get(&qualified_name).ok_or_else(|| error(format!("Can't refer {}, it's not defined", qualified_name)))?;
+        env.define(&name, value);
+    }
+    Ok(())
+}
+
+struct RequireSpec {
+    module_name: String,
+    alias: Option<String>,
+    /// None means nothing is referred, `:refer :all` is Some of all public names.
+    refer: Option<Option<Vec<String>>>,
+}
+
+fn parse_require_spec(spec: &RispType) -> Result<RequireSpec, RispError> {
+    match *spec {
+        Symbol(ref name) => Ok(RequireSpec { module_name: name.clone(), alias: None, refer: None }),
+        Vector(ref elements) => match elements.split_first() {
+            Some((Symbol(name), options)) if options.len() % 2 == 0 => {
+                let mut require_spec = RequireSpec { module_name: name.clone(), alias: None, refer: None };
+                for option in options.chunks(2) {
+                    match (&option[0], &option[1]) {
+                        (Keyword(key), Symbol(alias)) if key == "as" => require_spec.alias = Some(alias.clone()),
+                        (Keyword(key), Keyword(all)) if key == "refer" && all == "all" => require_spec.refer = Some(None),
+                        (Keyword(key), names) if key == "refer" => require_spec.refer = Some(Some(symbol_names(names)?)),
+                        _ => return Err(error(format!("Expected :as alias or :refer [names] in require spec but got {} {}", option[0], option[1])))
+                    }
+                }
+                Ok(require_spec)
+            }
+            _ => Err(error(format!("Expected require spec like [drums :as d] but got {}", spec)))
+        },
+        _ => Err(error(format!("Expected require spec like 'drums or '[drums :as d] but got {}", spec)))
+    }
+}
+
+/// Returns the names of a vector of symbols like [kick snare].
+pub fn symbol_names(names: &RispType) -> Result<Vec<String>, RispError> {
+    match *names {
+        Vector(ref elements) => elements.iter()
+            .map(|element| match *element {
+                Symbol(ref name) => Ok(name.clone()),
+                _ => Err(error(format!("Expected symbol but got {}", element)))
+            })
+            .collect(),
+        _ => Err(error(format!("Expected vector of names like [kick snare] but got {}", names)))
+    }
+}
+
+/// Evaluates the module in a new core environment and returns its public definitions.
+/// These are the definitions of the namespace declared with `(ns name)` and all definitions without namespace.
+fn eval_module(modules: &Rc<Modules>, module_name: &str, env: &Environment) -> Result<Vec<(String, RispType)>, RispError> {
+    let mut core_env = create_core_environment();
+    for capability in env.granted_capabilities() {
+        core_env.grant(capability);
+    }
+    let mut module_env = core_env.clone();
+    module_env.set_modules(env.modules());
+    module_env.set_random_source(env.random_source());
+    modules.eval_source(module_name, &mut module_env)?;
+    let mut definitions: Vec<(String, RispType)> = module_env.names().into_iter()
+        .filter(|name| split_qualified_symbol(name).is_none() && !module_env.is_private(name))
+        .filter_map(|name| {
+            let value = module_env.get(&name)?;
+            if core_env.get(&name) == Some(value.clone()) {
+                None
+            } else {
+                Some((name, value))
+            }
+        })
+        .collect();
+    if let Some(namespace) = module_env.current_namespace() {
+        for name in module_env.public_names(namespace) {
+            if let Some(value) = module_env.get(&format!("{}/{}", namespace, name)) {
+                definitions.push((name, value));
+            }
+        }
+    }
+    Ok(definitions)
+}
+
+
+/* ------------------------------ Tests ----------------------------------------------- */
+
+#[cfg(test)]
+use eval_risp_script;
+#[cfg(test)]
+use capabilities::Capability;
+
+#[cfg(test)]
+fn eval_with_modules(risp_code: &str, resolver: MemoryResolver) -> RispResult {
+    let mut env = create_core_environment();
+    env.set_module_resolver(resolver);
+    eval_risp_script(risp_code, &mut env)
+}
+
+#[cfg(test)]
+fn drums() -> MemoryResolver {
+    MemoryResolver::new()
+        .add("drums", "(def kick 36) (def snare 38) (defn double-kick [] [kick kick])")
+        .add("patterns.risp", "(def pattern [kick snare])")
+}
+
+#[test]
+fn test_load() {
+    assert_eq!(eval_with_modules("(def kick 1) (def snare 2) (load \"patterns.risp\") pattern", drums()),
+               Ok(Vector(vec![Int(1), Int(2)])));
+    assert_eq!(eval_with_modules("(load 'patterns)", drums()), error_result("load wants a String but got Symbol(\"patterns\")"));
+    assert_eq!(eval_with_modules("(load \"missing.risp\")", drums()), error_result("Unknown module missing.risp"));
+}
+
+#[test]
+fn test_require() {
+    assert_eq!(eval_with_modules("(require '[drums :as d]) [d/kick d/snare (d/double-kick)]", drums()),
+               Ok(Vector(vec![Int(36), Int(38), Vector(vec![Int(36), Int(36)])])));
+    assert_eq!(eval_with_modules("(require 'drums) drums/snare", drums()), Ok(Int(38)));
+    assert_eq!(eval_with_modules("(require '[drums :as d]) (+ d/kick)", drums()), Ok(Int(36)));
+    assert_eq!(eval_with_modules("(require '[drums :as d]) kick", drums()), error_result("symbol '\"kick\"' is undefined"));
+}
+
+#[test]
+fn test_require_errors() {
+    assert_eq!(eval_with_modules("(require '[drums d])", drums()),
+               error_result("Expected require spec like [drums :as d] but got [drums d]"));
+    assert_eq!(eval_with_modules("(require 'bass)", drums()), error_result("Unknown module bass"));
+    assert_eq!(eval_risp_script("(require 'drums)", &mut create_core_environment()),
+               error_result("require needs a module resolver, see Environment::set_module_resolver"));
+}
+
+#[test]
+fn test_require_evaluates_module_once() {
+    let resolver = MemoryResolver::new()
+        .add("counter", "(def id (gensym))")
+        .add("user", "(require 'counter) (def id counter/id)");
+    let ids = eval_with_modules("(require 'counter) (require 'user) [counter/id user/id]", resolver).unwrap();
+    assert_eq!(ids.get_in::<RispType>(&["0"]), ids.get_in(&["1"]));
+}
+
+#[test]
+fn test_cyclic_require() {
+    let resolver = MemoryResolver::new()
+        .add("a", "(require 'b)")
+        .add("b", "(require 'a)");
+    assert_eq!(eval_with_modules("(require 'a)", resolver), error_result("Cyclic load of a: a -> b -> a"));
+    let resolver = MemoryResolver::new().add("self.risp", "(load \"self.risp\")");
+    assert_eq!(eval_with_modules("(load \"self.risp\")", resolver), error_result("Cyclic load of self.risp: self.risp -> self.risp"));
+}
+
+#[test]
+fn test_custom_resolver() {
+    let mut env = create_core_environment();
+    env.set_module_resolver(|name: &str| Ok(format!("(def name \"{}\")", name)));
+    assert_eq!(eval_risp_script("(require '[bass :as b]) b/name", &mut env), Ok(string("bass")));
+}
+
+#[test]
+fn test_require_namespaced_module() {
+    let resolver = MemoryResolver::new()
+        .add("drums", "(ns drums) (defn- velocity [] 100) (def kick [36 (velocity)]) (def snare 38)");
+    assert_eq!(eval_with_modules("(require '[drums :as d :refer [snare]]) [d/kick drums/kick snare]", resolver),
+               Ok(Vector(vec![Vector(vec![Int(36), Int(100)]), Vector(vec![Int(36), Int(100)]), Int(38)])));
+
+    let resolver = MemoryResolver::new().add("drums", "(ns drums) (defn- velocity [] 100)");
+    assert_eq!(eval_with_modules("(require '[drums :as d]) (d/velocity)", resolver), error_result("Undefined symbol\"d/velocity\""));
+}
+
+#[test]
+fn test_require_refer_all() {
+    let resolver = MemoryResolver::new().add("drums", "(ns drums) (def kick 36) (def snare 38)");
+    assert_eq!(eval_with_modules("(ns song) (require '[drums :refer :all]) [kick snare]", resolver),
+               Ok(Vector(vec![Int(36), Int(38)])));
+    assert_eq!(eval_with_modules("(require '[drums :refer kick])", drums()),
+               error_result("Expected vector of names like [kick snare] but got kick"));
+}
+
+#[test]
+fn test_modules_get_granted_capabilities() {
+    let resolver = || MemoryResolver::new().add("clock", "(def started (fs/exists? \"Cargo.toml\"))");
+    assert_eq!(eval_with_modules("(require 'clock)", resolver()),
+               Err(error("Capability fs-read not granted, which is needed by fs/exists?").with_kind(ErrorKind::CapabilityNotGranted)));
+
+    let mut env = create_core_environment();
+    env.set_module_resolver(resolver());
+    env.grant(Capability::FsRead);
+    assert_eq!(eval_risp_script("(require '[clock :refer :all]) started", &mut env), Ok(Bool(true)));
+    assert_eq!(env.get("clock/fs/exists?"), None);
+}
+
+#[test]
+fn test_file_resolver_stays_below_root() {
+    let resolver = FileResolver::new("examples");
+    assert!(resolver.resolve("song").is_ok());
+    assert_eq!(resolver.resolve("/tmp/outside.risp"), Err(error("Module /tmp/outside.risp must be a relative path below the module root")));
+    assert_eq!(resolver.resolve("../examples/song.risp"), Err(error("Module ../examples/song.risp must be a relative path below the module root")));
+    assert_eq!(resolver.resolve("..song"), Err(error("Module ..song must be a relative path below the module root")));
+}
@@ -0,0 +1,266 @@
+#![allow(clippy::needless_pass_by_value)]
+
+use environment::Environment;
+use types::*;
+use types::RispType::*;
+
+/// Highest MIDI note number.
+pub const MAX_NOTE: i64 = 127;
+
+/// Semitones of the scales relative to their root.
+const SCALES: &[(&str, &[i64])] = &[
+    ("major", &[0, 2, 4, 5, 7, 9, 11]),
+    ("minor", &[0, 2, 3, 5, 7, 8, 10]),
+    ("harmonic-minor", &[0, 2, 3, 5, 7, 8, 11]),
+    ("melodic-minor", &[0, 2, 3, 5, 7, 9, 11]),
+    ("ionian", &[0, 2, 4, 5, 7, 9, 11]),
+    ("dorian", &[0, 2, 3, 5, 7, 9, 10]),
+    ("phrygian", &[0, 1, 3, 5, 7, 8, 10]),
+    ("lydian", &[0, 2, 4, 6, 7, 9, 11]),
+    ("mixolydian", &[0, 2, 4, 5, 7, 9, 10]),
+    ("aeolian", &[0, 2, 3, 5, 7, 8, 10]),
+    ("locrian", &[0, 1, 3, 5, 6, 8, 10]),
+    ("major-pentatonic", &[0, 2, 4, 7, 9]),
+    ("minor-pentatonic", &[0, 3, 5, 7, 10]),
+    ("blues", &[0, 3, 5, 6, 7, 10]),
+    ("chromatic", &[0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11]),
+];
+
+/// Semitones of the chords relative to their root.
+const CHORDS: &[(&str, &[i64])] = &[
+    ("maj", &[0, 4, 7]),
+    ("min", &[0, 3, 7]),
+    ("dim", &[0, 3, 6]),
+    ("aug", &[0, 4, 8]),
+    ("sus2", &[0, 2, 7]),
+    ("sus4", &[0, 5, 7]),
+    ("maj7", &[0, 4, 7, 11]),
+    ("min7", &[0, 3, 7, 10]),
+    ("dom7", &[0, 4, 7, 10]),
+    ("dim7", &[0, 3, 6, 9]),
+    ("min7b5", &[0, 3, 6, 10]),
+    ("add9", &[0, 4, 7, 14]),
+    ("maj9", &[0, 4, 7, 11, 14]),
+    ("min9", &[0, 3, 7, 10, 14]),
+    ("power", &[0, 7]),
+];
+
+const INTERVALS: &[(&str, i64)] = &[
+    ("unison", 0),
+    ("minor-second", 1),
+    ("major-second", 2),
+    ("minor-third", 3),
+    ("major-third", 4),
+    ("fourth", 5),
+    ("tritone", 6),
+    ("fifth", 7),
+    ("minor-sixth", 8),
+    ("major-sixth", 9),
+    ("minor-seventh", 10),
+    ("major-seventh", 11),
+    ("octave", 12),
+];
+
+/// Adds the music functions as music/note, music/scale ..., `(refer 'music)` makes them available as note, scale ...
+pub fn add_music_functions(env: &mut Environment) {
+    env.set("music/note", Function(note));
+    env.set("music/scale", Function(scale));
+    env.set("music/chord", Function(chord));
+    env.set("music/transpose", Function(transpose));
+    env.set("music/octave", Function(octave));
+    env.set("music/interval", Function(interval));
+}
+
+/// Returns the MIDI number of a note name like C4 (60), C#4 or Db4 (61) and A-1 (9).
+pub fn note_number(name: &str) -> Result<i64, RispError> {
+    let name_error = || error(format!("Expected note name like :C#4 but got :{}", name));
+    let mut chars = name.chars();
+    let pitch_class = match chars.next().map(|c| c.to_ascii_uppercase()) {
+        Some('C') => 0,
+        Some('D') => 2,
+        Some('E') => 4,
+        Some('F') => 5,
+        Some('G') => 7,
+        Some('A') => 9,
+        Some('B') => 11,
+        _ => return Err(name_error())
+    };
+    let rest = chars.as_str();
+    let accidentals = rest.chars().take_while(|c| *c == '#' || *c == 'b').count();
+    let shift: i64 = rest[..accidentals].chars().map(|c| if c == '#' { 1 } else { -1 }).sum();
+    let octave: i64 = rest[accidentals..].parse().map_err(|_| name_error())?;
+    if !(-1..=9).contains(&octave) {
+        return Err(name_error());
+    }
+    let number = (octave + 1) * 12 + pitch_class + shift;
+    if !(0..=MAX_NOTE).contains(&number) {
+        return Err(error(format!("Note :{} is {}, but MIDI notes are between 0 and {}", name, number, MAX_NOTE)));
+    }
+    Ok(number)
+}
+
+fn checked_note(number: i64) -> RispResult {
+    if (0..=MAX_NOTE).contains(&number) {
+        Ok(Int(number))
+    } else {
+        error_result(format!("Note {} is outside of the MIDI notes between 0 and {}", number, MAX_NOTE))
+    }
+}
+
+/// A MIDI number or a note name as keyword or string.
+fn note_of(value: &RispType) -> Result<i64, RispError> {
+    match *value {
+        Int(number) => checked_note(number).map(|_| number),
+        Keyword(ref name) | Str(ref name) => note_number(name),
+        _ => Err(error(format!("Expected note like 60 or :C4 but got {}", value)))
+    }
+}
+
+fn named<T: Copy>(kind: &str, table: &[(&str, T)], value: &RispType) -> Result<T, RispError> {
+    match *value {
+        Keyword(ref name) => table.iter().find(|entry| entry.0 == name).map(|entry| entry.1)
+            .ok_or_else(|| error(format!("Unknown {} :{}, known are {}", kind, name, table.iter().map(|entry| format!(":{}", entry.0)).collect::<Vec<_>>().join(" ")))),
+        _ => Err(error(format!("Expected {} keyword but got {}", kind, value)))
+    }
+}
+
+/// Semitones of an Int or an interval name like :fifth.
+fn semitones_of(value: &RispType) -> Result<i64, RispError> {
+    match *value {
+        Int(semitones) => Ok(semitones),
+        _ => named("interval", INTERVALS, value)
+    }
+}
+
+/// (music/note :A2) returns 45.
+fn note(args: Vec<RispType>) -> RispResult {
+    match *args {
+        [ref value] => note_of(value).map(Int),
+        _ => error_result(format!("music/note needs 1 argument but got {}", args.len()))
+    }
+}
+
+/// (music/scale :C4 :major) returns one octave, (music/scale :C4 :major 10) continues over the octave.
+fn scale(args: Vec<RispType>) -> RispResult {
+    let (root, steps, count) = match *args {
+        [ref root, ref name] => (note_of(root)?, named("scale", SCALES, name)?, None),
+        [ref root, ref name, Int(count)] if count >= 0 => (note_of(root)?, named("scale", SCALES, name)?, Some(count as usize)),
+        _ => return error_result(format!("music/scale wants (music/scale root name) or (music/scale root name count) but got {}", Vector(args)))
+    };
+    let count = count.unwrap_or(steps.len() + 1);
+    (0..count)
+        .map(|i| checked_note(root + 12 * (i / steps.len()) as i64 + steps[i % steps.len()]))
+        .collect::<Result<_, _>>()
+        .map(Vector)
+}
+
+/// (music/chord :C4 :maj) returns [60 64 67], (music/chord :C4 :maj 1) the first inversion [64 67 72].
+fn chord(args: Vec<RispType>) -> RispResult {
+    let (root, steps, inversion) = match *args {
+        [ref root, ref name] => (note_of(root)?, named("chord", CHORDS, name)?, 0),
+        [ref root, ref name, Int(inversion)] if inversion >= 0 => (note_of(root)?, named("chord", CHORDS, name)?, inversion as usize),
+        _ => return error_result(format!("music/chord wants (music/chord root name) or (music/chord root name inversion) but got {}", Vector(args)))
+    };
+    if inversion >= steps.len() {
+        return error_result(format!("music/chord inversion {} is too high for a chord with {} notes", inversion, steps.len()));
+    }
+    let mut notes: Vec<i64> = steps.iter().map(|step| root + step).collect();
+    for note in notes.iter_mut().take(inversion) {
+        *note += 12;
+    }
+    notes.rotate_left(inversion);
+    notes.into_iter().map(checked_note).collect::<Result<_, _>>().map(Vector)
+}
+
+/// Transposes single notes and all notes in (nested) vectors and lists.
+fn transpose_value(value: &RispType, semitones: i64) -> RispResult {
+    match *value {
+        Vector(ref notes) => notes.iter().enumerate()
+            .map(|(i, note)| transpose_value(note, semitones).map_err(|e| e.at_index(i)))
+            .collect::<Result<_, _>>().map(Vector),
+        List(ref notes) => notes.iter().enumerate()
+            .map(|(i, note)| transpose_value(note, semitones).map_err(|e| e.at_index(i)))
+            .collect::<Result<_, _>>().map(List),
+        _ => checked_note(note_of(value)?.saturating_add(semitones))
+    }
+}
+
+/// (music/transpose notes 7) or (music/transpose notes :fifth), notes is a note or a vector of notes.
+fn transpose(args: Vec<RispType>) -> RispResult {
+    match *args {
+        [ref notes, ref semitones] => transpose_value(notes, semitones_of(semitones)?),
+        _ => error_result(format!("music/transpose needs 2 arguments but got {}", args.len()))
+    }
+}
+
+/// (music/octave notes -1) transposes the notes by octaves.
+fn octave(args: Vec<RispType>) -> RispResult {
+    match *args {
+        [ref notes, Int(octaves)] => transpose_value(notes, octaves.saturating_mul(12)),
+        _ => error_result(format!("music/octave wants notes and an Int but got {}", Vector(args)))
+    }
+}
+
+/// (music/interval :fifth) returns 7.
+fn interval(args: Vec<RispType>) -> RispResult {
+    match *args {
+        [ref name] => named("interval", INTERVALS, name).map(Int),
+        _ => error_result(format!("music/interval needs 1 argument but got {}", args.len()))
+    }
+}
+
+/* ------------------------------ Tests ----------------------------------------------- */
+
+#[cfg(test)]
+use {eval_str, ints};
+
+#[test]
+fn test_note_number() {
+    assert_eq!(note_number("A2"), Ok(45));
+    assert_eq!(note_number("C4"), Ok(60));
+    assert_eq!(note_number("C#4"), Ok(61));
+    assert_eq!(note_number("Db4"), Ok(61));
+    assert_eq!(note_number("C-1"), Ok(0));
+    assert_eq!(note_number("G9"), Ok(127));
+    assert_eq!(note_number("G#9"), Err(error("Note :G#9 is 128, but MIDI notes are between 0 and 127")));
+    assert_eq!(note_number("H2"), Err(error("Expected note name like :C#4 but got :H2")));
+    assert_eq!(note_number("C"), Err(error("Expected note name like :C#4 but got :C")));
+    assert_eq!(note_number("C10"), Err(error("Expected note name like :C#4 but got :C10")));
+    assert_eq!(note_number("C9223372036854775807"), Err(error("Expected note name like :C#4 but got :C9223372036854775807")));
+}
+
+#[test]
+fn test_note() {
+    assert_eq!(eval_str("(music/note :A2)"), Ok(Int(45)));
+    assert_eq!(eval_str("(music/note \"C#4\")"), Ok(Int(61)));
+    assert_eq!(eval_str("(music/note 128)"), error_result("Note 128 is outside of the MIDI notes between 0 and 127"));
+    assert_eq!(eval_str("(refer 'music) (note :A2)"), Ok(Int(45)));
+}
+
+#[test]
+fn test_scale() {
+    assert_eq!(eval_str("(music/scale :C4 :major)"), Ok(ints(&[60, 62, 64, 65, 67, 69, 71, 72])));
+    assert_eq!(eval_str("(music/scale :A3 :minor-pentatonic 7)"), Ok(ints(&[57, 60, 62, 64, 67, 69, 72])));
+    assert_eq!(eval_str("(music/scale :D4 :dorian 3)"), Ok(ints(&[62, 64, 65])));
+    assert_eq!(eval_str("(music/scale :G9 :major)"), error_result("Note 129 is outside of the MIDI notes between 0 and 127"));
+    assert!(eval_str("(music/scale :C4 :klingon)").unwrap_err().to_string().starts_with("Unknown scale :klingon, known are :major :minor"));
+}
+
+#[test]
+fn test_chord() {
+    assert_eq!(eval_str("(music/chord :C4 :maj)"), Ok(ints(&[60, 64, 67])));
+    assert_eq!(eval_str("(music/chord :A3 :min7)"), Ok(ints(&[57, 60, 64, 67])));
+    assert_eq!(eval_str("(music/chord :C4 :maj 1)"), Ok(ints(&[64, 67, 72])));
+    assert_eq!(eval_str("(music/chord :C4 :maj 2)"), Ok(ints(&[67, 72, 76])));
+    assert_eq!(eval_str("(music/chord :C4 :sus4 3)"), error_result("music/chord inversion 3 is too high for a chord with 3 notes"));
+}
+
+#[test]
+fn test_transpose_and_octave() {
+    assert_eq!(eval_str("(music/transpose 60 :fifth)"), Ok(Int(67)));
+    assert_eq!(eval_str("(music/transpose [45 [57 :C4]] -2)"), Ok(Vector(vec![Int(43), ints(&[55, 58])])));
+    assert_eq!(eval_str("(music/octave [45 57] -1)"), Ok(ints(&[33, 45])));
+    assert_eq!(eval_str("(music/octave [45 120] 1)"), Err(error("Note 132 is outside of the MIDI notes between 0 and 127").at_index(1)));
+    assert_eq!(eval_str("(music/interval :minor-third)"), Ok(Int(3)));
+    assert!(eval_str("(music/transpose 60 :ninth)").unwrap_err().to_string().starts_with("Unknown interval :ninth"));
+}
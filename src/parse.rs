@@ -1,21 +1,96 @@
-use std::collections::HashMap;
+use std::collections::{BTreeSet, HashMap};
 use types::*;
 use types::RispType::*;
 use tokenize::*;
 
-fn parse_internal(tokenizer: &mut Iterator<Item=Token>) -> Result<RispType, RispError> {
-    let mut tokenizer = tokenizer.peekable();
+/// Options for parsing, the default is strict.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct ParseOptions {
+    /// Duplicate keys in map literals and duplicate elements in set literals are no error.
+    /// Instead the last one wins and a warning is collected.
+    pub lenient: bool,
+}
+
+/// A peekable token stream, which knows the positions of its tokens and collects warnings.
+struct Reader<'a> {
+    input: &'a str,
+    tokenizer: Tokenizer,
+    peeked: Option<Option<(Token, usize)>>,
+    options: &'a ParseOptions,
+    warnings: Vec<String>,
+}
+
+impl<'a> Reader<'a> {
+    fn new(input: &'a str, options: &'a ParseOptions) -> Reader<'a> {
+        Reader { input, tokenizer: Tokenizer::new(input), peeked: None, options, warnings: vec![] }
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        match self.peeked.take() {
+            Some(peeked) => peeked.map(|(token, _)| token),
+            None => self.tokenizer.next_with_position().map(|(token, _)| token)
+        }
+    }
+
+    fn peek(&mut self) -> Option<&Token> {
+        self.peek_with_position().map(|(token, _)| token)
+    }
+
+    /// Returns the position of the next token or the end of the input.
+    fn peek_position(&mut self) -> usize {
+        let input_len = self.input.len();
+        self.peek_with_position().map_or(input_len, |&(_, position)| position)
+    }
+
+    fn peek_with_position(&mut self) -> Option<&(Token, usize)> {
+        if self.peeked.is_none() {
+            self.peeked = Some(self.tokenizer.next_with_position());
+        }
+        self.peeked.as_ref().and_then(|peeked| peeked.as_ref())
+    }
+
+    /// Fails in strict mode and collects a warning in lenient mode.
+    fn report_duplicate(&mut self, message: String, first_position: usize, position: usize) -> Result<(), RispError> {
+        let full_message = format!("{} at {} (first occurrence at {})", message,
+                                   describe_position(self.input, position), describe_position(self.input, first_position));
+        if self.options.lenient {
+            self.warnings.push(full_message);
+            Ok(())
+        } else {
+            Err(error(full_message))
+        }
+    }
+}
+
+/// Describes a byte position as line and column, both starting at 1.
+pub fn describe_position(input: &str, position: usize) -> String {
+    let before = &input[..position];
+    let line = before.matches('\n').count() + 1;
+    let column = before.chars().rev().take_while(|&c| c != '\n').count() + 1;
+    format!("line {}, column {}", line, column)
+}
+
+fn parse_internal(tokenizer: &mut Reader) -> Result<RispType, RispError> {
+    let position = tokenizer.peek_position();
     if let Some(token) = tokenizer.next() {
         return match token {
             (TokenType::Number, token_string) => {
-                Ok(Int(token_string.parse().unwrap()))
+                token_string.parse().map(Int).map_err(|_| {
+                    error(format!("Number {} is out of range at {}", token_string, describe_position(tokenizer.input, position)))
+                })
             }
 
             (TokenType::Symbol, token_string) => {
                 match &token_string[..] {
+                    "nil" => Ok(Nil),
                     "true" => Ok(Bool(true)),
                     "false" => Ok(Bool(false)),
-                    _ => Ok(symbol(token_string))
+                    _ => match split_qualified_symbol(&token_string) {
+                        Some((namespace, name)) if namespace.is_empty() || name.is_empty() || name.contains('/') => {
+                            error_result(format!("Invalid qualified symbol {}, expected namespace/name", token_string))
+                        }
+                        _ => Ok(symbol(token_string))
+                    }
                 }
             }
 
@@ -24,50 +99,54 @@ fn parse_internal(tokenizer: &mut Iterator<Item=Token>) -> Result<RispType, Risp
             }
 
             (TokenType::Str, token_string) => {
-                Ok(string(&token_string[1..(token_string.len() - 1)]))
+                unescape_string(&token_string[1..(token_string.len() - 1)]).map(Str)
             }
 
             (TokenType::ListStart, _token_string) => {
                 let mut list = vec![];
                 loop {
+                    skip_discarded(tokenizer)?;
                     let token_option = tokenizer.peek().cloned();
                     if let Some(element_token) = token_option {
                         match element_token {
                             (TokenType::ListEnd, _) => {
+                                tokenizer.next();
                                 break;
                             }
                             _ => {
-                                let parsed_element = parse_internal(&mut tokenizer)?;
+                                let parsed_element = parse_internal(tokenizer)?;
                                 list.push(parsed_element);
                             }
                         }
                     } else {
-                        return error_result("Unexpected end of list");
+                        return Err(incomplete_input("Unexpected end of list"));
                     }
                 }
                 Ok(List(list))
             }
 
             (TokenType::ListEnd, _token_string) => {
-                error_result("Unexpected end of list")
+                error_result("Unexpected )")
             }
 
             (TokenType::VectorStart, _token_string) => {
                 let mut vector = vec![];
                 loop {
+                    skip_discarded(tokenizer)?;
                     let token_option = tokenizer.peek().cloned();
                     if let Some(element_token) = token_option {
                         match element_token {
                             (TokenType::VectorEnd, _) => {
+                                tokenizer.next();
                                 break;
                             }
                             _ => {
-                                let parsed_element = parse_internal(&mut tokenizer)?;
+                                let parsed_element = parse_internal(tokenizer)?;
                                 vector.push(parsed_element);
                             }
                         }
                     } else {
-                        return error_result("Vector should end with ] but just ends");
+                        return Err(incomplete_input("Vector should end with ] but just ends"));
                     }
                 }
                 Ok(Vector(vector))
@@ -78,27 +157,41 @@ fn parse_internal(tokenizer: &mut Iterator<Item=Token>) -> Result<RispType, Risp
             }
 
             (TokenType::HashMapStart, _token_string) => {
-                let mut map = HashMap::<String, RispType>::new();
+                let mut map = IndexMap::<RispType, RispType>::new();
+                let mut key_positions = HashMap::<RispType, usize>::new();
                 loop {
+                    skip_discarded(tokenizer)?;
                     let token_option = tokenizer.peek().cloned();
                     if let Some(element_token) = token_option {
                         match element_token {
                             (TokenType::HashMapEnd, _) => {
-                                break;
-                            }
-
-                            (TokenType::Keyword, keyword) => {
                                 tokenizer.next();
-                                let parsed_element = parse_internal(&mut tokenizer)?;
-                                map.insert((&keyword[1..]).to_string(), parsed_element);
+                                break;
                             }
-
-                            (_, token_string) => {
-                                return error_result(format!("Expected keyword but got {:?}", token_string));
+                            _ => {
+                                let key_position = tokenizer.peek_position();
+                                let parsed_key = parse_internal(tokenizer)?;
+                                if let Some(&first_position) = key_positions.get(&parsed_key) {
+                                    tokenizer.report_duplicate(format!("Duplicate key {:?} in map", parsed_key), first_position, key_position)?;
+                                } else {
+                                    key_positions.insert(parsed_key.clone(), key_position);
+                                }
+                                skip_discarded(tokenizer)?;
+                                match tokenizer.peek() {
+                                    Some(&(TokenType::HashMapEnd, _)) => {
+                                        return error_result(format!("Missing value for key {:?} in map", parsed_key));
+                                    }
+                                    None => {
+                                        return Err(incomplete_input("HashMap should end with } but just ends"));
+                                    }
+                                    _ => {}
+                                }
+                                let parsed_value = parse_internal(tokenizer)?;
+                                map.insert(parsed_key, parsed_value);
                             }
                         }
                     } else {
-                        return error_result("HashMap should end with } but just ends");
+                        return Err(incomplete_input("HashMap should end with } but just ends"));
                     }
                 }
                 Ok(Map(map))
@@ -107,15 +200,145 @@ fn parse_internal(tokenizer: &mut Iterator<Item=Token>) -> Result<RispType, Risp
             (TokenType::HashMapEnd, _token_string) => {
                 error_result("Unexpected }")
             }
+
+            (TokenType::SetStart, _token_string) => {
+                let mut set = BTreeSet::new();
+                let mut element_positions = HashMap::<RispType, usize>::new();
+                loop {
+                    skip_discarded(tokenizer)?;
+                    let token_option = tokenizer.peek().cloned();
+                    if let Some(element_token) = token_option {
+                        match element_token {
+                            (TokenType::HashMapEnd, _) => {
+                                tokenizer.next();
+                                break;
+                            }
+                            _ => {
+                                let element_position = tokenizer.peek_position();
+                                let parsed_element = parse_internal(tokenizer)?;
+                                if let Some(&first_position) = element_positions.get(&parsed_element) {
+                                    tokenizer.report_duplicate(format!("Duplicate element {:?} in set", parsed_element), first_position, element_position)?;
+                                } else {
+                                    element_positions.insert(parsed_element.clone(), element_position);
+                                }
+                                set.insert(parsed_element);
+                            }
+                        }
+                    } else {
+                        return Err(incomplete_input("Set should end with } but just ends"));
+                    }
+                }
+                Ok(Set(set))
+            }
+
+            (TokenType::Quote, token_string) => parse_reader_macro("quote", &token_string, tokenizer),
+            (TokenType::SyntaxQuote, token_string) => parse_reader_macro("syntax-quote", &token_string, tokenizer),
+            (TokenType::Unquote, token_string) => parse_reader_macro("unquote", &token_string, tokenizer),
+            (TokenType::UnquoteSplicing, token_string) => parse_reader_macro("unquote-splicing", &token_string, tokenizer),
+            // Risp has no reference types, so there is nothing a deref could evaluate to.
+            (TokenType::Deref, _token_string) => error_result("@ (deref) is not supported, risp has no references"),
+
+            (TokenType::Discard, _token_string) => {
+                parse_form_after("#_", tokenizer)?;
+                parse_internal(tokenizer)
+            }
+
+            // Only emitted by the lossless tokenizer, which is not used here.
+            (TokenType::Whitespace, _) | (TokenType::Comment, _) => parse_internal(tokenizer)
         }
     }
 
     error_result("Error")
 }
 
+fn unescape_string(escaped: &str) -> Result<String, RispError> {
+    let mut result = String::new();
+    let mut chars = escaped.chars();
+    while let Some(c) = chars.next() {
+        if c == '\\' {
+            match chars.next() {
+                Some('n') => result.push('\n'),
+                Some('t') => result.push('\t'),
+                Some('r') => result.push('\r'),
+                Some('"') => result.push('"'),
+                Some('\\') => result.push('\\'),
+                Some(other) => return Err(error(format!("Unsupported escape sequence \\{} in string", other))),
+                None => return Err(error("Unexpected end of string after \\"))
+            }
+        } else {
+            result.push(c);
+        }
+    }
+    Ok(result)
+}
+
+/// Wraps the form following a reader macro character into a list, e.g. 'x => (quote x).
+fn parse_reader_macro(name: &str, token_string: &str, tokenizer: &mut Reader) -> Result<RispType, RispError> {
+    let form = parse_form_after(token_string, tokenizer)?;
+    Ok(List(vec![symbol(name), form]))
+}
+
+fn parse_form_after(token_string: &str, tokenizer: &mut Reader) -> Result<RispType, RispError> {
+    if tokenizer.peek().is_none() {
+        return Err(incomplete_input(format!("Missing form after {}", token_string)));
+    }
+    parse_internal(tokenizer)
+}
+
+/// Consumes all #_ prefixed forms, so that collections can ignore them.
+fn skip_discarded(tokenizer: &mut Reader) -> Result<(), RispError> {
+    while let Some(&(TokenType::Discard, _)) = tokenizer.peek() {
+        tokenizer.next();
+        parse_form_after("#_", tokenizer)?;
+    }
+    Ok(())
+}
+
 pub fn parse(input: &str) -> Result<RispType, RispError> {
-    let mut tokenizer = Tokenizer::new(input);
-    parse_internal(&mut tokenizer)
+    parse_with_options(input, &ParseOptions::default()).map(|(ast, _warnings)| ast)
+}
+
+/// Parses the first form and returns it together with the collected warnings.
+pub fn parse_with_options(input: &str, options: &ParseOptions) -> Result<(RispType, Vec<String>), RispError> {
+    let mut tokenizer = Reader::new(input, options);
+    let ast = parse_internal(&mut tokenizer)?;
+    Ok((ast, tokenizer.warnings))
+}
+
+/// Parses all forms of a script and returns them together with the collected warnings.
+pub fn parse_all_with_options(input: &str, options: &ParseOptions) -> Result<(Vec<RispType>, Vec<String>), RispError> {
+    let mut tokenizer = Reader::new(input, options);
+    let mut forms = vec![];
+    loop {
+        skip_discarded(&mut tokenizer)?;
+        if tokenizer.peek().is_none() {
+            let position = tokenizer.tokenizer.position();
+            if position < input.len() {
+                return Err(unreadable_input_error(input, position));
+            }
+            break;
+        }
+        forms.push(parse_internal(&mut tokenizer)?);
+    }
+    Ok((forms, tokenizer.warnings))
+}
+
+fn unreadable_input_error(input: &str, position: usize) -> RispError {
+    if input[position..].starts_with('"') {
+        incomplete_input(format!("Unterminated string at {}", describe_position(input, position)))
+    } else {
+        error(format!("Unexpected character at {}", describe_position(input, position)))
+    }
+}
+
+/// The error for input, which ends in the middle of a form.
+fn incomplete_input<S: Into<String>>(message: S) -> RispError {
+    error(message).with_kind(ErrorKind::IncompleteInput)
+}
+
+/// Returns true if the parse error would go away by appending more input, e.g. for an unclosed list.
+pub fn is_incomplete_input(error: &RispError) -> bool {
+    error.kind() == ErrorKind::IncompleteInput
 }
 
 
@@ -154,7 +377,7 @@ fn test_parse_vector() {
 
 #[test]
 fn test_parse_vector_errors() {
-    assert_eq!(parse("["), error_result("Vector should end with ] but just ends"));
+    assert_eq!(parse("["), Err(incomplete_input("Vector should end with ] but just ends")));
     assert_eq!(parse("]"), error_result("Unexpected ]"));
     assert_eq!(parse("(]"), error_result("Unexpected ]"));
 }
@@ -168,7 +391,7 @@ fn test_keyword() {
 
 #[test]
 fn test_hash_map_empty() {
-    assert_eq!(parse("{}"), Ok(Map(HashMap::new())));
+    assert_eq!(parse("{}"), Ok(Map(IndexMap::new())));
 }
 
 #[test]
@@ -195,9 +418,11 @@ fn test_map_with_var() {
 
 #[test]
 fn test_hash_map_errors() {
-    assert_eq!(parse("{"), error_result("HashMap should end with } but just ends"));
+    assert_eq!(parse("{"), Err(incomplete_input("HashMap should end with } but just ends")));
     assert_eq!(parse("}"), error_result("Unexpected }"));
-    assert_eq!(parse("{123}"), error_result("Expected keyword but got \"123\""));
+    assert_eq!(parse("{123}"), error_result("Missing value for key Int(123) in map"));
+    assert_eq!(parse("{:key #_1}"), error_result("Missing value for key Keyword(\"key\") in map"));
+    assert_eq!(parse("{:key"), Err(incomplete_input("HashMap should end with } but just ends")));
 }
 
 #[test]
@@ -211,3 +436,173 @@ fn test_bool() {
     assert_eq!(parse("true"), Ok(Bool(true)));
     assert_eq!(parse("false"), Ok(Bool(false)));
 }
+
+#[test]
+fn test_quote() {
+    assert_eq!(parse("'a"), Ok(List(vec![symbol("quote"), symbol("a")])));
+    assert_eq!(parse("'(1 2)"), Ok(List(vec![symbol("quote"), List(vec![Int(1), Int(2)])])));
+    assert_eq!(parse("['a]"), Ok(Vector(vec![List(vec![symbol("quote"), symbol("a")])])));
+}
+
+#[test]
+fn test_syntax_quote() {
+    assert_eq!(parse("`(a ~b ~@c)"), Ok(List(vec![
+        symbol("syntax-quote"),
+        List(vec![
+            symbol("a"),
+            List(vec![symbol("unquote"), symbol("b")]),
+            List(vec![symbol("unquote-splicing"), symbol("c")])
+        ])
+    ])));
+}
+
+#[test]
+fn test_deref() {
+    assert_eq!(parse("@var"), error_result("@ (deref) is not supported, risp has no references"));
+    assert_eq!(parse("[1 ~@rest]"), Ok(Vector(vec![Int(1), List(vec![symbol("unquote-splicing"), symbol("rest")])])));
+}
+
+#[test]
+fn test_reader_macro_errors() {
+    assert_eq!(parse("'"), Err(incomplete_input("Missing form after '")));
+    assert_eq!(parse("(`)"), error_result("Unexpected )"));
+    assert_eq!(parse("#_"), Err(incomplete_input("Missing form after #_")));
+}
+
+#[test]
+fn test_discard() {
+    assert_eq!(parse("#_1 2"), Ok(Int(2)));
+    assert_eq!(parse("(1 #_2 3)"), Ok(List(vec![Int(1), Int(3)])));
+    assert_eq!(parse("[1 #_[2 3]]"), Ok(Vector(vec![Int(1)])));
+    assert_eq!(parse("(#_#_1 2)"), Ok(List(vec![])));
+}
+
+#[test]
+fn test_discard_map_entry() {
+    assert_eq!(parse("{:key1 1 #_:key2 #_2 :key3 3}"), Ok(map(vec![
+        ("key1", Int(1)),
+        ("key3", Int(3))
+    ])));
+    assert_eq!(parse("{:key1 #_1 2}"), Ok(map(vec![("key1", Int(2))])));
+}
+
+#[test]
+fn test_parse_set() {
+    assert_eq!(parse("#{}"), Ok(set(vec![])));
+    assert_eq!(parse("#{2 1 :key}"), Ok(set(vec![Int(1), Int(2), keyword("key")])));
+    assert_eq!(parse("#{#{1} [1]}"), Ok(set(vec![set(vec![Int(1)]), Vector(vec![Int(1)])])));
+}
+
+#[test]
+fn test_parse_set_errors() {
+    assert_eq!(parse("#{1"), Err(incomplete_input("Set should end with } but just ends")));
+    assert_eq!(parse("#{1 2 1}"), error_result("Duplicate element Int(1) in set at line 1, column 7 (first occurrence at line 1, column 3)"));
+    assert_eq!(parse("#{[1 2] [1 2]}"), error_result("Duplicate element Vector([Int(1), Int(2)]) in set at line 1, column 9 (first occurrence at line 1, column 3)"));
+}
+
+#[test]
+fn test_hash_map_with_non_keyword_keys() {
+    assert_eq!(parse("{36 \"kick\" \"name\" 1 :name 2 [1] 3}"), Ok(map_from(vec![
+        (Int(36), string("kick")),
+        (string("name"), Int(1)),
+        (keyword("name"), Int(2)),
+        (Vector(vec![Int(1)]), Int(3))
+    ])));
+}
+
+#[test]
+fn test_hash_map_keeps_order() {
+    let parsed = parse("{:b 1 :a 2 :c 3}").unwrap();
+    assert_eq!(format!("{:?}", parsed), "Map({Keyword(\"b\"): Int(1), Keyword(\"a\"): Int(2), Keyword(\"c\"): Int(3)})");
+}
+
+#[test]
+fn test_parse_all() {
+    let options = ParseOptions::default();
+    assert_eq!(parse_all_with_options("1 (+ 2) #_3", &options), Ok((vec![Int(1), List(vec![symbol("+"), Int(2)])], vec![])));
+    assert_eq!(parse_all_with_options("", &options), Ok((vec![], vec![])));
+    assert_eq!(parse_all_with_options("1 (", &options), Err(incomplete_input("Unexpected end of list")));
+}
+
+#[test]
+fn test_duplicate_map_keys() {
+    assert_eq!(parse("{:speed 100 :speed 200}"), error_result(
+        "Duplicate key Keyword(\"speed\") in map at line 1, column 13 (first occurrence at line 1, column 2)"));
+    assert_eq!(parse("{:name \"a\"\n :speed 100\n :speed 200}"), error_result(
+        "Duplicate key Keyword(\"speed\") in map at line 3, column 2 (first occurrence at line 2, column 2)"));
+    assert_eq!(parse("{1 1 \"1\" 2 :1 3}"), Ok(map_from(vec![
+        (Int(1), Int(1)),
+        (string("1"), Int(2)),
+        (keyword("1"), Int(3))
+    ])));
+}
+
+#[test]
+fn test_duplicate_set_elements_have_positions() {
+    assert_eq!(parse("#{1 2\n  1}"), error_result(
+        "Duplicate element Int(1) in set at line 2, column 3 (first occurrence at line 1, column 3)"));
+}
+
+#[test]
+fn test_lenient_duplicate_map_keys() {
+    let options = ParseOptions { lenient: true };
+    assert_eq!(parse_with_options("{:speed 100 :name \"a\" :speed 200}", &options), Ok((
+        map(vec![("speed", Int(200)), ("name", string("a"))]),
+        vec!["Duplicate key Keyword(\"speed\") in map at line 1, column 23 (first occurrence at line 1, column 2)".to_string()]
+    )));
+}
+
+#[test]
+fn test_lenient_duplicate_set_elements() {
+    let options = ParseOptions { lenient: true };
+    let (parsed, warnings) = parse_with_options("#{1 1}", &options).unwrap();
+    assert_eq!(parsed, set(vec![Int(1)]));
+    assert_eq!(warnings.len(), 1);
+}
+
+#[test]
+fn test_nil() {
+    assert_eq!(parse("nil"), Ok(Nil));
+    assert_eq!(parse("[nil]"), Ok(Vector(vec![Nil])));
+}
+
+#[test]
+fn test_str_with_escapes() {
+    assert_eq!(parse(r#""say \"hi\"""#), Ok(string("say \"hi\"")));
+    assert_eq!(parse(r#""a\\b\n\tc""#), Ok(string("a\\b\n\tc")));
+    assert_eq!(parse(r#""\x""#), error_result("Unsupported escape sequence \\x in string"));
+}
+
+#[test]
+fn test_parse_all_unreadable_input() {
+    let options = ParseOptions::default();
+    assert_eq!(parse_all_with_options("1 \"abc", &options), Err(incomplete_input("Unterminated string at line 1, column 3")));
+    assert_eq!(parse_all_with_options("1\n #x", &options), Err(error("Unexpected character at line 2, column 2")));
+}
+
+#[test]
+fn test_parse_number_out_of_range() {
+    assert_eq!(parse("9223372036854775807"), Ok(Int(i64::MAX)));
+    assert_eq!(parse("[1\n 99999999999999999999]"), error_result("Number 99999999999999999999 is out of range at line 2, column 2"));
+}
+
+#[test]
+fn test_is_incomplete_input() {
+    let options = ParseOptions::default();
+    for input in &["(1", "[1 (2)", "{:a 1", "#{", "'", "\"abc", "(str \"abc"] {
+        assert!(is_incomplete_input(&parse_all_with_options(input, &options).unwrap_err()), "{}", input);
+    }
+    for input in &[")", "1 #x"] {
+        assert!(!is_incomplete_input(&parse_all_with_options(input, &options).unwrap_err()), "{}", input);
+    }
+    assert_eq!(parse("[1").unwrap_err().kind(), ErrorKind::IncompleteInput);
+}
+
+#[test]
+fn test_parse_qualified_symbols() {
+    assert_eq!(parse("drums/kick"), Ok(symbol("drums/kick")));
+    assert_eq!(parse("(/ 4 2)"), Ok(List(vec![symbol("/"), Int(4), Int(2)])));
+    assert_eq!(parse("drums/"), error_result("Invalid qualified symbol drums/, expected namespace/name"));
+    assert_eq!(parse("/kick"), error_result("Invalid qualified symbol /kick, expected namespace/name"));
+    assert_eq!(parse("a/b/c"), error_result("Invalid qualified symbol a/b/c, expected namespace/name"));
+}
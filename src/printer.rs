@@ -0,0 +1,141 @@
+use types::*;
+use types::RispType::*;
+
+pub const DEFAULT_WIDTH: usize = 80;
+
+/// Prints the value as readable risp source, which is broken into multiple lines
+/// if it's longer than width. The values of maps are aligned.
+pub fn pretty_print(value: &RispType, width: usize) -> String {
+    let mut output = String::new();
+    print_pretty(&measure(value), 0, width, &mut output);
+    output
+}
+
+/// A value together with the width of its single line form, which is measured only once per node.
+struct Measured<'a> {
+    value: &'a RispType,
+    width: usize,
+    /// The elements, for maps keys and values alternating.
+    children: Vec<Measured<'a>>,
+}
+
+fn measure(value: &RispType) -> Measured<'_> {
+    let (delimiters_width, children): (usize, Vec<Measured>) = match *value {
+        List(ref list) => (2, list.iter().map(measure).collect()),
+        Vector(ref vector) => (2, vector.iter().map(measure).collect()),
+        Set(ref set_value) => (3, set_value.iter().map(measure).collect()),
+        Map(ref map_value) => (2, map_value.iter().flat_map(|(key, value)| vec![measure(key), measure(value)]).collect()),
+        _ => return Measured { value, width: value.to_string().chars().count(), children: vec![] }
+    };
+    let width = delimiters_width + children.iter().map(|child| child.width).sum::<usize>() + children.len().saturating_sub(1);
+    Measured { value, width, children }
+}
+
+fn print_pretty(measured: &Measured, indent: usize, width: usize, output: &mut String) {
+    if indent + measured.width <= width {
+        output.push_str(&measured.value.to_string());
+        return;
+    }
+    match *measured.value {
+        List(_) => print_elements("(", &measured.children, ")", indent, width, output),
+        Vector(_) => print_elements("[", &measured.children, "]", indent, width, output),
+        Set(_) => print_elements("#{", &measured.children, "}", indent, width, output),
+        Map(_) => {
+            let key_indent = indent + 1;
+            let key_width = measured.children.iter().step_by(2).map(|key| key.width).max().unwrap_or(0);
+            output.push('{');
+            for (i, entry) in measured.children.chunks(2).enumerate() {
+                if i > 0 {
+                    new_line(key_indent, output);
+                }
+                output.push_str(&entry[0].value.to_string());
+                push_spaces(key_width - entry[0].width + 1, output);
+                print_pretty(&entry[1], key_indent + key_width + 1, width, output);
+            }
+            output.push('}');
+        }
+        _ => output.push_str(&measured.value.to_string())
+    }
+}
+
+fn print_elements(open: &str, elements: &[Measured], close: &str, indent: usize, width: usize, output: &mut String) {
+    let element_indent = indent + open.len();
+    output.push_str(open);
+    for (i, element) in elements.iter().enumerate() {
+        if i > 0 {
+            new_line(element_indent, output);
+        }
+        print_pretty(element, element_indent, width, output);
+    }
+    output.push_str(close);
+}
+
+fn new_line(indent: usize, output: &mut String) {
+    output.push('\n');
+    push_spaces(indent, output);
+}
+
+fn push_spaces(n: usize, output: &mut String) {
+    for _ in 0..n {
+        output.push(' ');
+    }
+}
+
+
+/* ------------------------------ Tests ----------------------------------------------- */
+
+#[cfg(test)]
+use parse::parse;
+
+#[test]
+fn test_pretty_print_short() {
+    let value = map(vec![("name", string("Amazon"))]);
+    assert_eq!(pretty_print(&value, DEFAULT_WIDTH), r#"{:name "Amazon"}"#);
+}
+
+#[test]
+fn test_pretty_print_map() {
+    let value = map(vec![
+        ("name", string("Amazon")),
+        ("time_per_note", Int(220)),
+        ("notes", Vector(vec![Int(45), Int(57), Int(48), Int(60)]))
+    ]);
+    assert_eq!(pretty_print(&value, 20), r#"{:name          "Amazon"
+ :time_per_note 220
+ :notes         [45
+                 57
+                 48
+                 60]}"#);
+}
+
+#[test]
+fn test_pretty_print_nested() {
+    let value = Vector(vec![
+        map(vec![("trigger", Int(43)), ("notes", Vector(vec![Int(1), Int(2)]))]),
+        map(vec![("trigger", Int(45))])
+    ]);
+    assert_eq!(pretty_print(&value, 30), r#"[{:trigger 43 :notes [1 2]}
+ {:trigger 45}]"#);
+}
+
+#[test]
+fn test_measure_matches_single_line_width() {
+    let value = map(vec![
+        ("name", string("Say \"Hello\"")),
+        ("notes", List(vec![Vector(vec![]), set(vec![Int(1), keyword("two")]), Nil])),
+        ("empty", map_from(vec![]))
+    ]);
+    assert_eq!(measure(&value).width, value.to_string().chars().count());
+}
+
+#[test]
+fn test_pretty_print_round_trip() {
+    let value = map(vec![
+        ("name", string("Say \"Hello\"")),
+        ("set", set(vec![Int(1), keyword("two")])),
+        ("nested", map_from(vec![(Int(36), List(vec![symbol("quote"), Nil]))]))
+    ]);
+    for width in &[0, 10, DEFAULT_WIDTH] {
+        assert_eq!(parse(&pretty_print(&value, *width)), Ok(value.clone()));
+    }
+}
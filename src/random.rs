@@ -0,0 +1,318 @@
+#![allow(clippy::needless_pass_by_value)]
+
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
+use environment::Environment;
+use limits::check_collection_size;
+use types::*;
+use types::RispType::*;
+
+pub fn add_random_functions(env: &mut Environment) {
+    env.set("rand-int", Function(rand_int));
+    env.set("rand-nth", Function(rand_nth));
+    env.set("shuffle", Function(shuffle));
+    env.set("weighted-choice", Function(weighted_choice));
+    env.set("random-walk", Function(random_walk));
+}
+
+/// A seeded pseudo random generator (SplitMix64), the same seed always gives the same numbers on every platform.
+#[derive(Debug)]
+pub struct RandomSource {
+    state: Cell<u64>,
+}
+
+impl RandomSource {
+    pub fn new(seed: u64) -> RandomSource {
+        RandomSource { state: Cell::new(seed) }
+    }
+
+    pub fn next_u64(&self) -> u64 {
+        let state = self.state.get().wrapping_add(0x9E37_79B9_7F4A_7C15);
+        self.state.set(state);
+        let mut z = state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    /// A number between 0 inclusive and n exclusive without modulo bias, n must be positive.
+    pub fn below(&self, n: u64) -> u64 {
+        let zone = u64::MAX - u64::MAX % n;
+        loop {
+            let x = self.next_u64();
+            if x < zone {
+                return x % n;
+            }
+        }
+    }
+}
+
+thread_local! {
+    /// The sources of the enclosing with-seed forms, innermost last.
+    static SEEDED: RefCell<Vec<Rc<RandomSource>>> = const { RefCell::new(Vec::new()) };
+    /// The source of the environment, which is evaluated, used outside of with-seed.
+    static ENVIRONMENT_SOURCE: RefCell<Option<Rc<RandomSource>>> = const { RefCell::new(None) };
+}
+
+/// Runs f with the source of the environment as fallback for random functions outside of with-seed.
+/// Without a source the one of an enclosing evaluation stays active.
+pub fn with_random_source<T, F: FnOnce() -> T>(source: Option<Rc<RandomSource>>, f: F) -> T {
+    if source.is_none() {
+        return f();
+    }
+    let outer = ENVIRONMENT_SOURCE.with(|active| active.replace(source));
+    let _restore = RestoreSource(outer);
+    f()
+}
+
+struct RestoreSource(Option<Rc<RandomSource>>);
+
+impl Drop for RestoreSource {
+    fn drop(&mut self) {
+        let outer = self.0.take();
+        ENVIRONMENT_SOURCE.with(|active| *active.borrow_mut() = outer);
+    }
+}
+
+/// Runs f with the seed, so that all random forms evaluated by f including called functions use it.
+pub fn with_seed<T, F: FnOnce() -> T>(seed: u64, f: F) -> T {
+    SEEDED.with(|seeded| seeded.borrow_mut().push(Rc::new(RandomSource::new(seed))));
+    let _pop = PopSeed;
+    f()
+}
+
+struct PopSeed;
+
+impl Drop for PopSeed {
+    fn drop(&mut self) {
+        SEEDED.with(|seeded| seeded.borrow_mut().pop());
+    }
+}
+
+/// The source of the innermost with-seed or otherwise the source of the evaluated environment.
+fn random_source(name: &str) -> Result<Rc<RandomSource>, RispError> {
+    SEEDED.with(|seeded| seeded.borrow().last().cloned())
+        .or_else(|| ENVIRONMENT_SOURCE.with(|active| active.borrow().clone()))
+        .ok_or_else(|| error(format!("{} needs a seed, use (with-seed 42 ...) or Environment::set_random_seed", name)))
+}
+
+/// Converts the value of a (with-seed seed ...) form.
+pub fn seed_of(value: &RispType) -> Result<u64, RispError> {
+    match *value {
+        Int(seed) => Ok(seed as u64),
+        _ => Err(error(format!("with-seed wants an Int as seed but got {}", value)))
+    }
+}
+
+/// A random Int between min inclusive and max exclusive.
+fn int_between(random: &RandomSource, min: i64, max: i64) -> i64 {
+    let range = max.wrapping_sub(min) as u64;
+    min.wrapping_add(random.below(range) as i64)
+}
+
+/// (rand-int n) between 0 and n exclusive or (rand-int min max) between min and max exclusive.
+fn rand_int(args: Vec<RispType>) -> RispResult {
+    let random = random_source("rand-int")?;
+    let (min, max) = match *args {
+        [Int(n)] => (0, n),
+        [Int(min), Int(max)] => (min, max),
+        _ => return error_result(format!("rand-int wants (rand-int n) or (rand-int min max) but got {}", Vector(args.to_vec())))
+    };
+    if min >= max {
+        return error_result(format!("rand-int needs a non-empty range but got {} to {}", min, max));
+    }
+    Ok(Int(int_between(&random, min, max)))
+}
+
+fn elements<'a>(name: &str, args: &'a [RispType]) -> Result<&'a [RispType], RispError> {
+    match args {
+        [List(elements)] | [Vector(elements)] => Ok(elements),
+        [other] => Err(error(format!("{} wants a List or Vector but got {}", name, other))),
+        _ => Err(error(format!("{} needs 1 argument but got {}", name, args.len())))
+    }
+}
+
+fn rand_nth(args: Vec<RispType>) -> RispResult {
+    let random = random_source("rand-nth")?;
+    let elements = elements("rand-nth", &args)?;
+    if elements.is_empty() {
+        return error_result("rand-nth needs a non-empty collection");
+    }
+    Ok(elements[random.below(elements.len() as u64) as usize].clone())
+}
+
+/// Fisher-Yates shuffle into a new vector.
+fn shuffle(args: Vec<RispType>) -> RispResult {
+    let random = random_source("shuffle")?;
+    let mut elements = elements("shuffle", &args)?.to_vec();
+    for i in (1..elements.len()).rev() {
+        let j = random.below(i as u64 + 1) as usize;
+        elements.swap(i, j);
+    }
+    Ok(Vector(elements))
+}
+
+/// (weighted-choice {:kick 3 :snare 1}) or (weighted-choice [[36 3] [38 1]]) picks a value proportional to its weight.
+fn weighted_choice(args: Vec<RispType>) -> RispResult {
+    let random = random_source("weighted-choice")?;
+    let choices: Vec<(RispType, RispType)> = match *args {
+        [Map(ref map_value)] => map_value.iter().map(|(value, weight)| (value.clone(), weight.clone())).collect(),
+        [Vector(ref pairs)] | [List(ref pairs)] => pairs.iter().enumerate()
+            .map(|(i, pair)| match *pair {
+                Vector(ref pair) if pair.len() == 2 => Ok((pair[0].clone(), pair[1].clone())),
+                _ => Err(error(format!("weighted-choice wants [value weight] pairs but got {}", pair)).at_index(i))
+            })
+            .collect::<Result<_, _>>()?,
+        _ => return error_result(format!("weighted-choice wants a Map or a Vector of [value weight] pairs but got {}", Vector(args.to_vec())))
+    };
+    let mut weights = Vec::with_capacity(choices.len());
+    for (value, weight) in &choices {
+        match *weight {
+            Int(weight) if weight >= 0 => weights.push(weight as u64),
+            _ => return Err(error(format!("weighted-choice wants non-negative Int weights but got {}", weight)).at_key(value.to_string()))
+        }
+    }
+    let total = weights.iter().try_fold(0u64, |sum, weight| sum.checked_add(*weight))
+        .ok_or_else(|| error("weighted-choice weights are too large"))?;
+    if total == 0 {
+        return error_result("weighted-choice needs at least one positive weight");
+    }
+    let mut pick = random.below(total);
+    for ((value, _), weight) in choices.into_iter().zip(weights) {
+        if pick < weight {
+            return Ok(value);
+        }
+        pick -= weight;
+    }
+    unreachable!("pick is below the total of the weights")
+}
+
+/// (random-walk start length max-step) or (random-walk start length max-step min max) returns a vector,
+/// which starts at start and changes by at most max-step, optionally kept between min and max.
+fn random_walk(args: Vec<RispType>) -> RispResult {
+    let random = random_source("random-walk")?;
+    let (start, length, max_step, bounds) = match *args {
+        [Int(start), Int(length), Int(max_step)] => (start, length, max_step, None),
+        [Int(start), Int(length), Int(max_step), Int(min), Int(max)] => (start, length, max_step, Some((min, max))),
+        _ => return error_result(format!("random-walk wants (random-walk start length max-step) or (random-walk start length max-step min max) but got {}", Vector(args.to_vec())))
+    };
+    if length < 0 || max_step < 0 {
+        return error_result(format!("random-walk needs a non-negative length and max-step but got {} and {}", length, max_step));
+    }
+    if let Some((min, max)) = bounds {
+        if min > max || start < min || start > max {
+            return error_result(format!("random-walk needs min <= start <= max but got {} <= {} <= {}", min, start, max));
+        }
+    }
+    check_collection_size("random-walk", Some(length as usize))?;
+    let mut value = start;
+    let mut walk = Vec::with_capacity(length as usize);
+    for _ in 0..length {
+        walk.push(Int(value));
+        let step = int_between(&random, -max_step, max_step.saturating_add(1));
+        value = value.saturating_add(step);
+        if let Some((min, max)) = bounds {
+            value = value.clamp(min, max);
+        }
+    }
+    Ok(Vector(walk))
+}
+
+/* ------------------------------ Tests ----------------------------------------------- */
+
+#[cfg(test)]
+use core::create_core_environment;
+
+#[cfg(test)]
+use eval_str;
+
+#[test]
+fn test_random_source_is_deterministic() {
+    let source1 = RandomSource::new(42);
+    let source2 = RandomSource::new(42);
+    let numbers1: Vec<u64> = (0..5).map(|_| source1.below(100)).collect();
+    let numbers2: Vec<u64> = (0..5).map(|_| source2.below(100)).collect();
+    assert_eq!(numbers1, numbers2);
+    assert!(numbers1.iter().all(|n| *n < 100));
+    assert_ne!(RandomSource::new(1).next_u64(), RandomSource::new(2).next_u64());
+}
+
+#[test]
+fn test_with_seed() {
+    let notes = "(with-seed 42 [(rand-int 10) (rand-int 40 50) (rand-nth [36 38 42]) (shuffle [1 2 3 4 5])])";
+    let result = eval_str(notes).unwrap();
+    assert_eq!(eval_str(notes), Ok(result.clone()));
+    if let Vector(ref values) = result {
+        assert!(matches!(values[0], Int(n) if (0..10).contains(&n)));
+        assert!(matches!(values[1], Int(n) if (40..50).contains(&n)));
+        assert!(matches!(values[2], Int(36) | Int(38) | Int(42)));
+        assert_eq!(set(match values[3] { Vector(ref v) => v.clone(), _ => vec![] }), set(vec![Int(1), Int(2), Int(3), Int(4), Int(5)]));
+    } else {
+        panic!("Expected vector but got {}", result);
+    }
+    assert_ne!(eval_str("(with-seed 1 (shuffle [1 2 3 4 5 6 7 8 9 10]))"), eval_str("(with-seed 2 (shuffle [1 2 3 4 5 6 7 8 9 10]))"));
+}
+
+#[test]
+fn test_with_seed_applies_to_called_functions() {
+    let mut env = create_core_environment();
+    ::eval_risp_script("(defn pick [] (rand-int 1000))", &mut env).unwrap();
+    let first = ::eval_risp_script("(with-seed 7 [(pick) (pick)])", &mut env);
+    assert_eq!(::eval_risp_script("(with-seed 7 [(pick) (pick)])", &mut env), first);
+    assert_eq!(::eval_risp_script("(pick)", &mut env), error_result("rand-int needs a seed, use (with-seed 42 ...) or Environment::set_random_seed"));
+}
+
+#[test]
+fn test_random_functions_are_values() {
+    let twice = "(defn twice [f x] [(f x) (f x)]) (with-seed 5 (twice rand-int 1000))";
+    assert_eq!(eval_str(twice), eval_str(twice));
+    assert_eq!(eval_str("(defn shuffle [xs] (conj xs 4)) (shuffle [1 2 3])"), Ok(Vector(vec![Int(1), Int(2), Int(3), Int(4)])));
+}
+
+#[test]
+fn test_seed_from_environment() {
+    let mut env1 = create_core_environment();
+    env1.set_random_seed(42);
+    let mut env2 = create_core_environment();
+    env2.set_random_seed(42);
+    let script = "(defn pick [] (rand-int 1000)) [(pick) (pick) (rand-int 1000)]";
+    assert_eq!(::eval_risp_script(script, &mut env1), ::eval_risp_script(script, &mut env2));
+}
+
+#[test]
+fn test_weighted_choice() {
+    assert_eq!(eval_str("(with-seed 1 (weighted-choice {:kick 0 :snare 5}))"), Ok(keyword("snare")));
+    assert_eq!(eval_str("(with-seed 1 (weighted-choice [[36 0] [38 1] [42 0]]))"), Ok(Int(38)));
+    assert_eq!(eval_str("(with-seed 1 (weighted-choice {:kick 0}))"), error_result("weighted-choice needs at least one positive weight"));
+    assert_eq!(eval_str("(with-seed 1 (weighted-choice {:kick -1}))"), Err(error("weighted-choice wants non-negative Int weights but got -1").at_key(":kick")));
+    assert_eq!(eval_str("(with-seed 1 (weighted-choice [[36 1] 38]))"), Err(error("weighted-choice wants [value weight] pairs but got 38").at_index(1)));
+}
+
+#[test]
+fn test_random_walk() {
+    let walk = eval_str("(with-seed 3 (random-walk 60 16 2 58 62))").unwrap();
+    match walk {
+        Vector(ref notes) => {
+            assert_eq!(notes.len(), 16);
+            assert_eq!(notes[0], Int(60));
+            for pair in notes.windows(2) {
+                if let (Int(a), Int(b)) = (&pair[0], &pair[1]) {
+                    assert!((a - b).abs() <= 2 && (58..=62).contains(b));
+                }
+            }
+        }
+        _ => panic!("Expected vector but got {}", walk)
+    }
+    assert_eq!(eval_str("(with-seed 3 (random-walk 60 0 2))"), Ok(Vector(vec![])));
+    assert_eq!(eval_str("(with-seed 3 (random-walk 60 -1 2))"), error_result("random-walk needs a non-negative length and max-step but got -1 and 2"));
+    assert_eq!(eval_str("(with-seed 3 (random-walk 70 4 2 58 62))"), error_result("random-walk needs min <= start <= max but got 58 <= 70 <= 62"));
+}
+
+#[test]
+fn test_random_errors() {
+    assert_eq!(eval_str("(with-seed 1 (rand-int 0))"), error_result("rand-int needs a non-empty range but got 0 to 0"));
+    assert_eq!(eval_str("(with-seed 1 (rand-int :a))"), error_result("rand-int wants (rand-int n) or (rand-int min max) but got [:a]"));
+    assert_eq!(eval_str("(with-seed 1 (rand-nth []))"), error_result("rand-nth needs a non-empty collection"));
+    assert_eq!(eval_str("(with-seed 1 (shuffle 1))"), error_result("shuffle wants a List or Vector but got 1"));
+    assert_eq!(eval_str("(with-seed :a 1)"), error_result("with-seed wants an Int as seed but got :a"));
+}
@@ -0,0 +1,365 @@
+use std::mem;
+use core::create_core_environment;
+use environment::Environment;
+use eval::eval_top_level;
+use modules::ModuleResolver;
+use parse::{is_incomplete_input, parse_all_with_options, ParseOptions};
+use printer::{pretty_print, DEFAULT_WIDTH};
+use types::*;
+use types::RispType::*;
+
+/// Only the latest history entries are kept.
+pub const MAX_HISTORY: usize = 1000;
+
+const HELP: &str = ":env          lists the variables defined in this session
+:doc NAME     describes a special form, builtin or defined function
+:history      lists the previous inputs
+:help         prints this help
+:quit         exits the repl
+*1 *2 *3      the last three results, *e the last error";
+
+/// Special forms and builtins with a short description.
+const DOCS: &[(&str, &str)] = &[
+    ("def", "(def name value) binds the value to the name"),
+    ("defn", "(defn name [args & rest] body) defines a function"),
+    ("defmacro", "(defmacro name [args & rest] body) defines a macro, which gets its arguments unevaluated"),
+    ("fn", "(fn [args & rest] body) creates a function"),
+    ("do", "(do forms...) evaluates the forms and returns the last result"),
+    ("comment", "(comment forms...) ignores the forms"),
+    ("quote", "(quote form) or 'form returns the form unevaluated"),
+    ("syntax-quote", "`form returns the form with ~unquoted and ~@spliced parts evaluated"),
+    ("macroexpand-1", "(macroexpand-1 'form) expands the macro call once"),
+    ("macroexpand", "(macroexpand 'form) expands the macro call until it is no macro call anymore"),
+    ("+", "(+ args...) adds numbers, vectors are added element-wise and wrap around"),
+    ("-", "(- args...) subtracts numbers, vectors element-wise"),
+    ("*", "(* args...) multiplies numbers, vectors element-wise"),
+    ("/", "(/ args...) divides numbers, vectors element-wise"),
+    ("rep", "(rep n elements...) repeats the elements n times"),
+    ("gensym", "(gensym prefix) returns a unique symbol"),
+    ("set", "(set vector) creates a set of the elements"),
+    ("conj", "(conj set elements...) adds the elements to the set"),
+    ("disj", "(disj set elements...) removes the elements from the set"),
+    ("contains?", "(contains? set element) checks if the set contains the element"),
+    ("union", "(union sets...) returns all elements of the sets"),
+    ("intersection", "(intersection sets...) returns the elements, which are in all sets"),
+    ("difference", "(difference set sets...) returns the elements of the first set, which are in no other set"),
+    ("pr-str", "(pr-str values...) prints the values readable as risp"),
+    ("str", "(str values...) concatenates the values as plain text"),
+    ("print", "(print values...) prints the values as plain text"),
+    ("println", "(println values...) prints the values as plain text followed by a new line"),
+    ("json/encode", "(json/encode value) returns the value as JSON string"),
+    ("json/decode", "(json/decode string) parses the JSON string, objects become maps with keyword keys, keys like \"hi hat\" stay strings"),
+    ("defn-", "(defn- name [args & rest] body) defines a function, which other namespaces can't use"),
+    ("ns", "(ns name) puts the following definitions into the namespace, they are available as name/x"),
+    ("refer", "(refer 'drums :only '[kick]) makes definitions of the namespace available without namespace"),
+    ("load", "(load \"path.risp\") evaluates the file in the current environment"),
+    ("require", "(require '[drums :as d :refer [kick]]) evaluates the module drums once and makes its definitions available as d/name"),
+    ("valid?", "(valid? schema value) returns true if the value matches the schema like {:speed [:int {:min 1}]}"),
+    ("explain", "(explain schema value) returns nil if valid, otherwise a vector of {:path :message} maps"),
+    ("env/get", "(env/get \"HOME\") returns the environment variable or nil, needs the env capability"),
+    ("time/now", "(time/now) returns the milliseconds since 1970, needs the time capability"),
+    ("random/seed", "(random/seed) returns a random Int, needs the random capability"),
+    ("fs/read", "(fs/read \"path\") returns the content of the file, needs the fs-read capability"),
+    ("fs/exists?", "(fs/exists? \"path\") checks if the file exists, needs the fs-read capability"),
+    ("with-seed", "(with-seed 42 forms...) evaluates the forms with random functions seeded, so that they always give the same result"),
+    ("rand-int", "(rand-int n) or (rand-int min max) returns a seeded random Int, max is exclusive"),
+    ("rand-nth", "(rand-nth coll) returns a seeded random element"),
+    ("shuffle", "(shuffle coll) returns the elements in seeded random order"),
+    ("weighted-choice", "(weighted-choice {:kick 3 :snare 1}) returns a seeded random key, proportional to its weight"),
+    ("random-walk", "(random-walk start length max-step min max) returns a vector of seeded random steps, min and max are optional"),
+    ("music/note", "(music/note :A2) returns the MIDI number 45, also for :C#4 or :Bb3"),
+    ("music/scale", "(music/scale :C4 :major) returns the notes of the scale, (music/scale :A3 :minor-pentatonic 10) the first 10 notes"),
+    ("music/chord", "(music/chord :C4 :maj) returns [60 64 67], (music/chord :C4 :min7 1) the first inversion"),
+    ("music/transpose", "(music/transpose notes 7) or (music/transpose notes :fifth) transposes a note or (nested) vectors of notes"),
+    ("music/octave", "(music/octave notes -1) transposes the notes by octaves"),
+    ("music/interval", "(music/interval :fifth) returns the semitones of the interval"),
+    ("euclid", "(euclid 3 8) returns [1 0 0 1 0 0 1 0], (euclid 3 8 36 nil) uses 36 for hits and nil for rests"),
+    ("rotate", "(rotate [1 2 3 4] 1) returns [2 3 4 1], negative counts rotate right"),
+    ("palindrome", "(palindrome [1 2 3]) returns [1 2 3 2 1]"),
+    ("stretch", "(stretch [1 2] 3) returns [1 1 1 2 2 2]"),
+    ("every-nth", "(every-nth [1 2 3 4 5] 2) returns [1 3 5], an optional third argument is the start index"),
+    ("arpeggiate", "(arpeggiate [60 64 67] :updown) returns [60 64 67 64], also :up :down :downup and an optional length"),
+    ("interleave", "(interleave [1 2 3] [10 20]) returns [1 10 2 20]"),
+    ("cycle", "(cycle [1 2 3] 7) returns [1 2 3 1 2 3 1]"),
+    ("polymeter", "(polymeter [1 2 3] [10 20]) returns the steps [[1 10] [2 20] [3 10] [1 20] [2 10] [3 20]]"),
+];
+
+/// What the caller of the repl should do after a line.
+#[derive(Debug, PartialEq)]
+pub enum ReplResponse {
+    /// The input is not complete yet and continues on the next line.
+    Incomplete,
+    /// Nothing to print, e.g. for empty lines.
+    Nothing,
+    Output(String),
+    Error(String),
+    Quit,
+}
+
+/// Evaluates input line by line in one environment.
+pub struct Repl {
+    env: Environment,
+    core_env: Environment,
+    pending: String,
+    history: Vec<String>,
+}
+
+impl Default for Repl {
+    fn default() -> Self {
+        Repl::new()
+    }
+}
+
+impl Repl {
+    pub fn new() -> Repl {
+        Repl::with_environment(create_core_environment())
+    }
+
+    /// Starts the repl in the environment, e.g. one with granted capabilities or a random seed.
+    pub fn with_environment(env: Environment) -> Repl {
+        Repl { env, core_env: create_core_environment(), pending: String::new(), history: vec![] }
+    }
+
+    /// Enables load and require in the repl.
+    pub fn set_module_resolver<R: ModuleResolver + 'static>(&mut self, resolver: R) {
+        self.env.set_module_resolver(resolver);
+    }
+
+    pub fn prompt(&self) -> &'static str {
+        if self.pending.is_empty() { "risp> " } else { "  ... " }
+    }
+
+    pub fn history(&self) -> &[String] {
+        &self.history
+    }
+
+    /// Restores the history from the content of a history file.
+    pub fn load_history(&mut self, content: &str) {
+        for line in content.lines().filter(|line| !line.is_empty()) {
+            self.add_history(unescape_history_entry(line));
+        }
+    }
+
+    /// Returns the history with one entry per line.
+    pub fn history_file_content(&self) -> String {
+        self.history.iter().map(|entry| escape_history_entry(entry) + "\n").collect()
+    }
+
+    pub fn handle_line(&mut self, line: &str) -> ReplResponse {
+        if self.pending.is_empty() {
+            let trimmed = line.trim();
+            if trimmed.is_empty() {
+                return ReplResponse::Nothing;
+            }
+            if let Some(response) = self.command(trimmed) {
+                self.add_history(trimmed.to_string());
+                return response;
+            }
+        }
+
+        self.pending.push_str(line);
+        self.pending.push('\n');
+        let forms = match parse_all_with_options(&self.pending, &ParseOptions::default()) {
+            Err(ref error) if is_incomplete_input(error) => return ReplResponse::Incomplete,
+            result => result.map(|(forms, _warnings)| forms)
+        };
+        let input = mem::take(&mut self.pending);
+        self.add_history(input.trim_end().to_string());
+
+        match forms {
+            Ok(ref forms) if forms.is_empty() => ReplResponse::Nothing,
+            Ok(forms) => {
+                let mut do_form = vec![symbol("do")];
+                do_form.extend(forms);
+                match eval_top_level(List(do_form), &mut self.env) {
+                    Ok(value) => {
+                        self.remember_result(value.clone());
+                        ReplResponse::Output(pretty_print(&value, DEFAULT_WIDTH))
+                    }
+                    Err(error) => self.fail(error)
+                }
+            }
+            Err(error) => self.fail(error)
+        }
+    }
+
+    fn add_history(&mut self, entry: String) {
+        if self.history.last() != Some(&entry) {
+            self.history.push(entry);
+        }
+        if self.history.len() > MAX_HISTORY {
+            self.history.remove(0);
+        }
+    }
+
+    fn remember_result(&mut self, value: RispType) {
+        for &(from, to) in &[("*2", "*3"), ("*1", "*2")] {
+            if let Some(previous) = self.env.get(from) {
+                self.env.set(to, previous);
+            }
+        }
+        self.env.set("*1", value);
+    }
+
+    fn fail(&mut self, error: RispError) -> ReplResponse {
+        self.env.set("*e", Str(error.to_string()));
+        ReplResponse::Error(error.to_string())
+    }
+
+    /// Returns None for unknown commands, which are evaluated as keywords then.
+    fn command(&self, input: &str) -> Option<ReplResponse> {
+        let mut parts = input.split_whitespace();
+        let response = match (parts.next(), parts.next()) {
+            (Some(":quit"), None) | (Some(":q"), None) => ReplResponse::Quit,
+            (Some(":help"), None) => ReplResponse::Output(HELP.to_string()),
+            (Some(":env"), None) => ReplResponse::Output(self.describe_env()),
+            (Some(":history"), None) => ReplResponse::Output(self.history.iter().enumerate()
+                .map(|(i, entry)| format!("{:4}  {}", i + 1, entry))
+                .collect::<Vec<_>>()
+                .join("\n")),
+            (Some(":doc"), Some(name)) => match self.doc(name) {
+                Some(doc) => ReplResponse::Output(doc),
+                None => ReplResponse::Error(format!("Unknown name {}", name))
+            },
+            _ => return None
+        };
+        Some(response)
+    }
+
+    /// Lists the variables, which are not part of the core environment, without the result vars.
+    fn describe_env(&self) -> String {
+        self.env.names().into_iter()
+            .filter(|name| !name.starts_with('*') && self.env.get(name) != self.core_env.get(name))
+            .map(|name| format!("{} {}", name, self.env.get(&name).unwrap()))
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    fn doc(&self, name: &str) -> Option<String> {
+        if let Some(value) = self.env.get(name) {
+            if self.core_env.get(name) != Some(value.clone()) {
+                return Some(match value {
+                    RispFunction(ref risp_func) => format!("(defn {} {})", name, signature(risp_func)),
+                    Macro(ref risp_func) => format!("(defmacro {} {})", name, signature(risp_func)),
+                    _ => format!("(def {} {})", name, value)
+                });
+            }
+        }
+        DOCS.iter().find(|&&(doc_name, _)| doc_name == name).map(|&(_, doc)| doc.to_string())
+            .or_else(|| self.env.get(name).map(|_| format!("{} is a builtin function", name)))
+    }
+}
+
+/// Returns the argument vector like [a b & rest].
+fn signature(risp_func: &RispFunc) -> String {
+    let mut args: Vec<String> = risp_func.args.iter().map(|arg| arg.to_string()).collect();
+    if let Some(ref variadic_arg) = risp_func.variadic_arg {
+        args.push("&".to_string());
+        args.push(variadic_arg.clone());
+    }
+    format!("[{}]", args.join(" "))
+}
+
+/// Multi-line entries are written as one line into the history file.
+fn escape_history_entry(entry: &str) -> String {
+    entry.replace('\\', "\\\\").replace('\n', "\\n")
+}
+
+fn unescape_history_entry(line: &str) -> String {
+    let mut result = String::new();
+    let mut chars = line.chars();
+    while let Some(c) = chars.next() {
+        match (c, chars.clone().next()) {
+            ('\\', Some('n')) => {
+                chars.next();
+                result.push('\n');
+            }
+            ('\\', Some('\\')) => {
+                chars.next();
+                result.push('\\');
+            }
+            _ => result.push(c)
+        }
+    }
+    result
+}
+
+
+/* ------------------------------ Tests ----------------------------------------------- */
+
+#[cfg(test)]
+fn output<S: Into<String>>(s: S) -> ReplResponse {
+    ReplResponse::Output(s.into())
+}
+
+#[test]
+fn test_repl_keeps_environment() {
+    let mut repl = Repl::new();
+    assert_eq!(repl.handle_line("(def a 20)"), output("20"));
+    assert_eq!(repl.handle_line("(+ a 1) (+ a 2)"), output("22"));
+    assert_eq!(repl.handle_line(""), ReplResponse::Nothing);
+    assert_eq!(repl.handle_line("; just a comment"), ReplResponse::Nothing);
+}
+
+#[test]
+fn test_repl_multi_line_input() {
+    let mut repl = Repl::new();
+    assert_eq!(repl.prompt(), "risp> ");
+    assert_eq!(repl.handle_line("(defn double [x]"), ReplResponse::Incomplete);
+    assert_eq!(repl.prompt(), "  ... ");
+    assert_eq!(repl.handle_line("  (* x 2))"), output("(fn [x] (* x 2))"));
+    assert_eq!(repl.prompt(), "risp> ");
+    assert_eq!(repl.handle_line("[(double 1) \"multi"), ReplResponse::Incomplete);
+    assert_eq!(repl.handle_line("line\"]"), output("[2 \"multi\\nline\"]"));
+    assert_eq!(repl.history(), &["(defn double [x]\n  (* x 2))".to_string(), "[(double 1) \"multi\nline\"]".to_string()][..]);
+}
+
+#[test]
+fn test_repl_result_vars() {
+    let mut repl = Repl::new();
+    repl.handle_line("1");
+    repl.handle_line("2");
+    repl.handle_line("3");
+    assert_eq!(repl.handle_line("[*1 *2 *3]"), output("[3 2 1]"));
+    assert_eq!(repl.handle_line("(unknown)"), ReplResponse::Error("Undefined symbol\"unknown\"".to_string()));
+    assert_eq!(repl.handle_line(")"), ReplResponse::Error("Unexpected )".to_string()));
+    assert_eq!(repl.handle_line("*e"), output("\"Unexpected )\""));
+    assert_eq!(repl.handle_line("*2"), output("[3 2 1]"));
+}
+
+#[test]
+fn test_repl_number_out_of_range() {
+    let mut repl = Repl::new();
+    assert_eq!(repl.handle_line("99999999999999999999"), ReplResponse::Error("Number 99999999999999999999 is out of range at line 1, column 1".to_string()));
+    assert_eq!(repl.handle_line("1"), output("1"));
+}
+
+#[test]
+fn test_repl_commands() {
+    let mut repl = Repl::new();
+    repl.handle_line("(def speed 120)");
+    repl.handle_line("(defn song [name & notes] notes)");
+    assert_eq!(repl.handle_line(":env"), output("song (fn [name & notes] notes)\nspeed 120"));
+    assert_eq!(repl.handle_line(":doc song"), output("(defn song [name & notes])"));
+    assert_eq!(repl.handle_line(":doc speed"), output("(def speed 120)"));
+    assert_eq!(repl.handle_line(":doc rep"), output("(rep n elements...) repeats the elements n times"));
+    assert_eq!(repl.handle_line(":doc defn"), output("(defn name [args & rest] body) defines a function"));
+    assert_eq!(repl.handle_line(":doc unknown"), ReplResponse::Error("Unknown name unknown".to_string()));
+    assert_eq!(repl.handle_line(":keyword"), output(":keyword"));
+    assert_eq!(repl.handle_line(":history"), output("   1  (def speed 120)\n   2  (defn song [name & notes] notes)\n   3  :env\n   4  :doc song\n   5  :doc speed\n   6  :doc rep\n   7  :doc defn\n   8  :doc unknown\n   9  :keyword"));
+    assert_eq!(repl.handle_line(":quit"), ReplResponse::Quit);
+}
+
+#[test]
+fn test_repl_history_file() {
+    let mut repl = Repl::new();
+    repl.handle_line("(str \"a\\\\b\"");
+    repl.handle_line(")");
+    repl.handle_line("1");
+    let content = repl.history_file_content();
+    assert_eq!(content, "(str \"a\\\\\\\\b\"\\n)\n1\n");
+
+    let mut restored = Repl::new();
+    restored.load_history(&content);
+    assert_eq!(restored.history(), repl.history());
+}
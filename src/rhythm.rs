@@ -0,0 +1,285 @@
+#![allow(clippy::needless_pass_by_value)]
+
+use environment::Environment;
+use limits::check_collection_size;
+use types::*;
+use types::RispType::*;
+
+/// Adds the sequence functions, which all return vectors, so that their results work with flatten_into.
+pub fn add_rhythm_functions(env: &mut Environment) {
+    env.set("euclid", Function(euclid));
+    env.set("rotate", Function(rotate));
+    env.set("palindrome", Function(palindrome));
+    env.set("stretch", Function(stretch));
+    env.set("every-nth", Function(every_nth));
+    env.set("arpeggiate", Function(arpeggiate));
+    env.set("interleave", Function(interleave));
+    env.set("cycle", Function(cycle));
+    env.set("polymeter", Function(polymeter));
+}
+
+/// Distributes the hits as evenly as possible over the steps (Bjorklund's algorithm), e.g. 3 in 8 gives x..x..x.
+pub fn euclidean_rhythm(hits: usize, steps: usize) -> Vec<bool> {
+    if hits == 0 || hits >= steps {
+        return vec![hits > 0; steps];
+    }
+    let mut groups: Vec<Vec<bool>> = vec![vec![true]; hits];
+    let mut remainders: Vec<Vec<bool>> = vec![vec![false]; steps - hits];
+    while remainders.len() > 1 {
+        let paired = groups.len().min(remainders.len());
+        let new_remainders = if groups.len() > paired { groups.split_off(paired) } else { remainders.split_off(paired) };
+        for (group, remainder) in groups.iter_mut().zip(remainders) {
+            group.extend(remainder);
+        }
+        remainders = new_remainders;
+    }
+    groups.into_iter().chain(remainders).flatten().collect()
+}
+
+fn elements<'a>(name: &str, value: &'a RispType) -> Result<&'a [RispType], RispError> {
+    match *value {
+        Vector(ref elements) | List(ref elements) => Ok(elements),
+        Nil => Ok(&[]),
+        _ => Err(error(format!("{} wants a Vector or List but got {:?}", name, value)))
+    }
+}
+
+fn count_of(name: &str, value: &RispType) -> Result<usize, RispError> {
+    match *value {
+        Int(count) if count >= 0 => Ok(count as usize),
+        _ => Err(error(format!("{} wants a non-negative Int but got {:?}", name, value)))
+    }
+}
+
+fn check_product(name: &str, length: usize, factor: usize) -> Result<(), RispError> {
+    check_collection_size(name, length.checked_mul(factor))
+}
+
+/// (euclid 3 8) returns [1 0 0 1 0 0 1 0], (euclid 3 8 36 nil) uses 36 for hits and nil for rests.
+fn euclid(args: Vec<RispType>) -> RispResult {
+    let (hits, steps, hit, rest) = match *args {
+        [ref hits, ref steps] => (hits, steps, Int(1), Int(0)),
+        [ref hits, ref steps, ref hit, ref rest] => (hits, steps, hit.clone(), rest.clone()),
+        _ => return error_result(format!("euclid wants (euclid hits steps) or (euclid hits steps hit rest) but got {} arguments", args.len()))
+    };
+    let hits = count_of("euclid", hits)?;
+    let steps = count_of("euclid", steps)?;
+    if hits > steps {
+        return error_result(format!("euclid needs at most as many hits as steps but got {} hits for {} steps", hits, steps));
+    }
+    check_collection_size("euclid", Some(steps))?;
+    Ok(Vector(euclidean_rhythm(hits, steps).into_iter().map(|is_hit| if is_hit { hit.clone() } else { rest.clone() }).collect()))
+}
+
+/// (rotate [1 2 3 4] 1) moves the first element to the end and returns [2 3 4 1], negative counts rotate right.
+fn rotate(args: Vec<RispType>) -> RispResult {
+    match *args {
+        [ref coll, Int(n)] => {
+            let mut elements = elements("rotate", coll)?.to_vec();
+            if !elements.is_empty() {
+                let shift = n.rem_euclid(elements.len() as i64) as usize;
+                elements.rotate_left(shift);
+            }
+            Ok(Vector(elements))
+        }
+        _ => error_result(format!("rotate wants a collection and an Int but got {:?}", Vector(args)))
+    }
+}
+
+/// (palindrome [1 2 3]) returns [1 2 3 2 1].
+fn palindrome(args: Vec<RispType>) -> RispResult {
+    match *args {
+        [ref coll] => {
+            let elements = elements("palindrome", coll)?;
+            check_collection_size("palindrome", Some((elements.len() * 2).saturating_sub(1)))?;
+            Ok(Vector(elements.iter().chain(elements.iter().rev().skip(1)).cloned().collect()))
+        }
+        _ => error_result(format!("palindrome needs 1 argument but got {}", args.len()))
+    }
+}
+
+/// (stretch [1 2] 3) repeats every element and returns [1 1 1 2 2 2].
+fn stretch(args: Vec<RispType>) -> RispResult {
+    match *args {
+        [ref coll, ref factor] => {
+            let elements = elements("stretch", coll)?;
+            let factor = count_of("stretch", factor)?;
+            check_product("stretch", elements.len(), factor)?;
+            Ok(Vector(elements.iter().flat_map(|element| vec![element.clone(); factor]).collect()))
+        }
+        _ => error_result(format!("stretch needs 2 arguments but got {}", args.len()))
+    }
+}
+
+/// (every-nth [1 2 3 4 5] 2) returns [1 3 5], (every-nth [1 2 3 4 5] 2 1) starts at index 1 and returns [2 4].
+fn every_nth(args: Vec<RispType>) -> RispResult {
+    let (coll, n, offset) = match *args {
+        [ref coll, ref n] => (coll, n, 0),
+        [ref coll, ref n, ref offset] => (coll, n, count_of("every-nth", offset)?),
+        _ => return error_result(format!("every-nth needs 2 or 3 arguments but got {}", args.len()))
+    };
+    let elements = elements("every-nth", coll)?;
+    match count_of("every-nth", n)? {
+        0 => error_result("every-nth needs a positive n but got 0"),
+        n => Ok(Vector(elements.iter().skip(offset).step_by(n).cloned().collect()))
+    }
+}
+
+/// (arpeggiate [60 64 67] :updown) returns [60 64 67 64], (arpeggiate chord :up 8) cycles to 8 notes.
+fn arpeggiate(args: Vec<RispType>) -> RispResult {
+    let (chord, mode, length) = match *args {
+        [ref chord, ref mode] => (chord, mode, None),
+        [ref chord, ref mode, ref length] => (chord, mode, Some(count_of("arpeggiate", length)?)),
+        _ => return error_result(format!("arpeggiate needs 2 or 3 arguments but got {}", args.len()))
+    };
+    let mut up = elements("arpeggiate", chord)?.to_vec();
+    up.sort();
+    let down: Vec<RispType> = up.iter().rev().cloned().collect();
+    let inner = |notes: &[RispType]| notes.iter().skip(1).take(notes.len().saturating_sub(2)).cloned().collect::<Vec<_>>();
+    let notes = match *mode {
+        Keyword(ref mode) if mode == "up" => up,
+        Keyword(ref mode) if mode == "down" => down,
+        Keyword(ref mode) if mode == "updown" => up.iter().cloned().chain(inner(&down)).collect(),
+        Keyword(ref mode) if mode == "downup" => down.iter().cloned().chain(inner(&up)).collect(),
+        _ => return error_result(format!("arpeggiate wants :up, :down, :updown or :downup but got {:?}", mode))
+    };
+    match length {
+        Some(length) => cycled("arpeggiate", &notes, length),
+        None => Ok(Vector(notes))
+    }
+}
+
+/// (interleave [1 2 3] [:a :b]) returns [1 :a 2 :b], it stops at the end of the shortest collection.
+fn interleave(args: Vec<RispType>) -> RispResult {
+    let colls = args.iter().map(|coll| elements("interleave", coll)).collect::<Result<Vec<_>, _>>()?;
+    let length = colls.iter().map(|coll| coll.len()).min().unwrap_or(0);
+    check_product("interleave", length, colls.len())?;
+    Ok(Vector((0..length).flat_map(|i| colls.iter().map(move |coll| coll[i].clone())).collect()))
+}
+
+fn cycled(name: &str, elements: &[RispType], length: usize) -> RispResult {
+    if elements.is_empty() && length > 0 {
+        return error_result(format!("{} can't cycle an empty collection", name));
+    }
+    check_collection_size(name, Some(length))?;
+    Ok(Vector(elements.iter().cycle().take(length).cloned().collect()))
+}
+
+/// (cycle [1 2 3] 7) repeats the elements up to the length and returns [1 2 3 1 2 3 1].
+fn cycle(args: Vec<RispType>) -> RispResult {
+    match *args {
+        [ref coll, ref length] => cycled("cycle", elements("cycle", coll)?, count_of("cycle", length)?),
+        _ => error_result(format!("cycle needs 2 arguments but got {}", args.len()))
+    }
+}
+
+fn gcd(a: usize, b: usize) -> usize {
+    if b == 0 { a } else { gcd(b, a % b) }
+}
+
+/// (polymeter [1 2 3] [:a :b]) plays the patterns at the same time until they meet again
+/// and returns the steps [[1 :a] [2 :b] [3 :a] [1 :b] [2 :a] [3 :b]].
+fn polymeter(args: Vec<RispType>) -> RispResult {
+    let patterns = args.iter().map(|coll| elements("polymeter", coll)).collect::<Result<Vec<_>, _>>()?;
+    if patterns.is_empty() || patterns.iter().any(|pattern| pattern.is_empty()) {
+        return error_result("polymeter needs at least one pattern and no empty patterns");
+    }
+    let length = patterns.iter().try_fold(1usize, |length, pattern| {
+        (length / gcd(length, pattern.len())).checked_mul(pattern.len())
+    });
+    check_collection_size("polymeter", length.and_then(|length| length.checked_mul(patterns.len())))?;
+    let length = length.unwrap_or(0);
+    Ok(Vector((0..length)
+        .map(|i| Vector(patterns.iter().map(|pattern| pattern[i % pattern.len()].clone()).collect()))
+        .collect()))
+}
+
+/* ------------------------------ Tests ----------------------------------------------- */
+
+#[cfg(test)]
+use convert::flatten_into;
+#[cfg(test)]
+use limits::{with_limits, EvalLimits};
+#[cfg(test)]
+use {eval_str, ints};
+
+#[cfg(test)]
+fn pattern(rhythm: &[bool]) -> String {
+    rhythm.iter().map(|hit| if *hit { 'x' } else { '.' }).collect()
+}
+
+#[test]
+fn test_euclidean_rhythm() {
+    assert_eq!(pattern(&euclidean_rhythm(3, 8)), "x..x..x.");
+    assert_eq!(pattern(&euclidean_rhythm(5, 8)), "x.xx.xx.");
+    assert_eq!(pattern(&euclidean_rhythm(4, 12)), "x..x..x..x..");
+    assert_eq!(pattern(&euclidean_rhythm(7, 16)), "x..x.x.x..x.x.x.");
+    assert_eq!(pattern(&euclidean_rhythm(0, 4)), "....");
+    assert_eq!(pattern(&euclidean_rhythm(4, 4)), "xxxx");
+}
+
+#[test]
+fn test_euclid() {
+    assert_eq!(eval_str("(euclid 3 8)"), Ok(ints(&[1, 0, 0, 1, 0, 0, 1, 0])));
+    assert_eq!(eval_str("(euclid 2 4 36 nil)"), Ok(Vector(vec![Int(36), Nil, Int(36), Nil])));
+    assert_eq!(eval_str("(euclid 5 4)"), error_result("euclid needs at most as many hits as steps but got 5 hits for 4 steps"));
+    assert_eq!(eval_str("(euclid -1 4)"), error_result("euclid wants a non-negative Int but got Int(-1)"));
+}
+
+#[test]
+fn test_rotate_palindrome_stretch() {
+    assert_eq!(eval_str("(rotate [1 2 3 4] 1)"), Ok(ints(&[2, 3, 4, 1])));
+    assert_eq!(eval_str("(rotate [1 2 3 4] -1)"), Ok(ints(&[4, 1, 2, 3])));
+    assert_eq!(eval_str("(rotate [] 3)"), Ok(ints(&[])));
+    assert_eq!(eval_str("(palindrome [1 2 3])"), Ok(ints(&[1, 2, 3, 2, 1])));
+    assert_eq!(eval_str("(palindrome [])"), Ok(ints(&[])));
+    assert_eq!(eval_str("(stretch '(1 2) 3)"), Ok(ints(&[1, 1, 1, 2, 2, 2])));
+    assert_eq!(eval_str("(stretch 1 3)"), error_result("stretch wants a Vector or List but got Int(1)"));
+}
+
+#[test]
+fn test_every_nth_and_cycle() {
+    assert_eq!(eval_str("(every-nth [1 2 3 4 5] 2)"), Ok(ints(&[1, 3, 5])));
+    assert_eq!(eval_str("(every-nth [1 2 3 4 5] 2 1)"), Ok(ints(&[2, 4])));
+    assert_eq!(eval_str("(every-nth [1 2] 0)"), error_result("every-nth needs a positive n but got 0"));
+    assert_eq!(eval_str("(cycle [1 2 3] 7)"), Ok(ints(&[1, 2, 3, 1, 2, 3, 1])));
+    assert_eq!(eval_str("(cycle [] 0)"), Ok(ints(&[])));
+    assert_eq!(eval_str("(cycle [] 2)"), error_result("cycle can't cycle an empty collection"));
+}
+
+#[test]
+fn test_arpeggiate() {
+    assert_eq!(eval_str("(arpeggiate [67 60 64] :up)"), Ok(ints(&[60, 64, 67])));
+    assert_eq!(eval_str("(arpeggiate [60 64 67] :down)"), Ok(ints(&[67, 64, 60])));
+    assert_eq!(eval_str("(arpeggiate [60 64 67 71] :updown)"), Ok(ints(&[60, 64, 67, 71, 67, 64])));
+    assert_eq!(eval_str("(arpeggiate [60 64 67] :downup 7)"), Ok(ints(&[67, 64, 60, 64, 67, 64, 60])));
+    assert_eq!(eval_str("(arpeggiate (music/chord :A2 :min) :updown)"), Ok(ints(&[45, 48, 52, 48])));
+    assert_eq!(eval_str("(arpeggiate [60] :sideways)"), error_result("arpeggiate wants :up, :down, :updown or :downup but got Keyword(\"sideways\")"));
+}
+
+#[test]
+fn test_interleave_and_polymeter() {
+    assert_eq!(eval_str("(interleave [1 2 3] [10 20])"), Ok(ints(&[1, 10, 2, 20])));
+    assert_eq!(eval_str("(interleave)"), Ok(ints(&[])));
+    assert_eq!(eval_str("(polymeter [1 2 3] [10 20])"),
+               Ok(Vector(vec![ints(&[1, 10]), ints(&[2, 20]), ints(&[3, 10]), ints(&[1, 20]), ints(&[2, 10]), ints(&[3, 20])])));
+    assert_eq!(eval_str("(polymeter [1] [])"), error_result("polymeter needs at least one pattern and no empty patterns"));
+}
+
+#[test]
+fn test_results_flatten_into_notes() {
+    let notes: Vec<i64> = flatten_into(eval_str("[(polymeter [36 38] [42 42 46]) (euclid 3 8 36 0)]").unwrap()).unwrap();
+    assert_eq!(notes.len(), 6 * 2 + 8);
+    assert_eq!(&notes[..4], &[36, 42, 38, 42]);
+}
+
+#[test]
+fn test_rhythm_limits() {
+    let limits = EvalLimits { max_collection_size: Some(100), ..EvalLimits::default() };
+    with_limits(&limits, || {
+        assert_eq!(stretch(vec![ints(&[1, 2]), Int(51)]), Err(error("stretch would create 102 elements but the limit is 100").with_kind(ErrorKind::LimitExceeded)));
+        assert_eq!(cycle(vec![ints(&[1]), Int(101)]), Err(error("cycle would create 101 elements but the limit is 100").with_kind(ErrorKind::LimitExceeded)));
+        assert_eq!(euclid(vec![Int(1), Int(1000)]), Err(error("euclid would create 1000 elements but the limit is 100").with_kind(ErrorKind::LimitExceeded)));
+        assert_eq!(polymeter(vec![ints(&[1; 7]), ints(&[1; 11])]), Err(error("polymeter would create 154 elements but the limit is 100").with_kind(ErrorKind::LimitExceeded)));
+    });
+}
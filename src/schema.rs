@@ -0,0 +1,394 @@
+use convert::{FromRisp, path_key};
+use types::*;
+use types::RispType::*;
+
+/// Describes the expected shape of an evaluated value.
+/// Built in Rust with the constructor functions or read from risp like `{:name :string :speed [:int {:min 1}]}`.
+#[derive(Debug, PartialEq, Clone)]
+pub enum Schema {
+    Any,
+    Nil,
+    Bool,
+    Int { min: Option<i64>, max: Option<i64> },
+    Str,
+    Keyword,
+    Symbol,
+    Function,
+    Vector(Box<Schema>),
+    Set(Box<Schema>),
+    Tuple(Vec<Schema>),
+    Map(Vec<MapEntry>),
+    MapOf(Box<Schema>, Box<Schema>),
+    OneOf(Vec<RispType>),
+    Or(Vec<Schema>),
+    Maybe(Box<Schema>),
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct MapEntry {
+    pub key: RispType,
+    pub schema: Schema,
+    pub optional: bool,
+}
+
+impl MapEntry {
+    /// A keyword key, which must exist.
+    pub fn required(key: &str, schema: Schema) -> MapEntry {
+        MapEntry { key: keyword(key), schema, optional: false }
+    }
+
+    /// A keyword key, which may be missing.
+    pub fn optional(key: &str, schema: Schema) -> MapEntry {
+        MapEntry { key: keyword(key), schema, optional: true }
+    }
+}
+
+impl Schema {
+    pub fn any() -> Schema {
+        Schema::Any
+    }
+
+    pub fn nil() -> Schema {
+        Schema::Nil
+    }
+
+    pub fn bool() -> Schema {
+        Schema::Bool
+    }
+
+    pub fn int() -> Schema {
+        Schema::Int { min: None, max: None }
+    }
+
+    /// An int between min and max, both inclusive.
+    pub fn int_between(min: i64, max: i64) -> Schema {
+        Schema::Int { min: Some(min), max: Some(max) }
+    }
+
+    pub fn int_at_least(min: i64) -> Schema {
+        Schema::Int { min: Some(min), max: None }
+    }
+
+    pub fn int_at_most(max: i64) -> Schema {
+        Schema::Int { min: None, max: Some(max) }
+    }
+
+    pub fn string() -> Schema {
+        Schema::Str
+    }
+
+    pub fn keyword() -> Schema {
+        Schema::Keyword
+    }
+
+    pub fn symbol() -> Schema {
+        Schema::Symbol
+    }
+
+    pub fn function() -> Schema {
+        Schema::Function
+    }
+
+    pub fn vector(elements: Schema) -> Schema {
+        Schema::Vector(Box::new(elements))
+    }
+
+    pub fn set(elements: Schema) -> Schema {
+        Schema::Set(Box::new(elements))
+    }
+
+    pub fn tuple(elements: Vec<Schema>) -> Schema {
+        Schema::Tuple(elements)
+    }
+
+    /// A map with the entries, e.g. `Schema::map(vec![MapEntry::required("name", Schema::string())])`.
+    pub fn map(entries: Vec<MapEntry>) -> Schema {
+        Schema::Map(entries)
+    }
+
+    /// A map with any number of entries like `{36 "kick" 38 "snare"}`.
+    pub fn map_of(keys: Schema, values: Schema) -> Schema {
+        Schema::MapOf(Box::new(keys), Box::new(values))
+    }
+
+    /// One of the values, e.g. `Schema::one_of(vec![keyword("kick"), keyword("snare")])`.
+    pub fn one_of(values: Vec<RispType>) -> Schema {
+        Schema::OneOf(values)
+    }
+
+    pub fn or(alternatives: Vec<Schema>) -> Schema {
+        Schema::Or(alternatives)
+    }
+
+    /// Nil or the schema.
+    pub fn maybe(schema: Schema) -> Schema {
+        Schema::Maybe(Box::new(schema))
+    }
+
+    /// Returns all violations, each error contains the path to the invalid value.
+    pub fn validate(&self, value: &RispType) -> Result<(), Vec<RispError>> {
+        let mut errors = vec![];
+        self.check(value, &mut errors);
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+
+    pub fn is_valid(&self, value: &RispType) -> bool {
+        self.validate(value).is_ok()
+    }
+
+    fn check(&self, value: &RispType, errors: &mut Vec<RispError>) {
+        match (self, value) {
+            (Schema::Any, _) |
+            (Schema::Nil, Nil) |
+            (Schema::Bool, Bool(_)) |
+            (Schema::Str, Str(_)) |
+            (Schema::Keyword, Keyword(_)) |
+            (Schema::Symbol, Symbol(_)) |
+            (Schema::Function, Function(_)) |
+            (Schema::Function, RispFunction(_)) => {}
+            (Schema::Maybe(_), Nil) => {}
+            (Schema::Maybe(schema), _) => schema.check(value, errors),
+            (Schema::Int { min, max }, Int(int)) => {
+                if min.is_some_and(|min| *int < min) || max.is_some_and(|max| *int > max) {
+                    errors.push(self.mismatch(value));
+                }
+            }
+            (Schema::Vector(elements), Vector(values)) => {
+                for (index, element) in values.iter().enumerate() {
+                    check_nested(elements, element, errors, |e| e.at_index(index));
+                }
+            }
+            (Schema::Set(elements), Set(values)) => {
+                for element in values {
+                    check_nested(elements, element, errors, |e| e.at_key(element.to_string()));
+                }
+            }
+            (Schema::Tuple(schemas), Vector(values)) if schemas.len() == values.len() => {
+                for (index, (schema, element)) in schemas.iter().zip(values).enumerate() {
+                    check_nested(schema, element, errors, |e| e.at_index(index));
+                }
+            }
+            (Schema::Map(entries), Map(map)) => {
+                for entry in entries {
+                    match map.get(&entry.key) {
+                        Some(entry_value) => check_nested(&entry.schema, entry_value, errors, |e| e.at_key(path_key(&entry.key))),
+                        None if entry.optional => {}
+                        None => errors.push(error(format!("Missing required key {}", entry.key)))
+                    }
+                }
+            }
+            (Schema::MapOf(keys, values), Map(map)) => {
+                for (key, entry_value) in map {
+                    check_nested(keys, key, errors, |e| e.at_key(path_key(key)));
+                    check_nested(values, entry_value, errors, |e| e.at_key(path_key(key)));
+                }
+            }
+            (Schema::OneOf(values), _) if values.contains(value) => {}
+            (Schema::Or(alternatives), _) if alternatives.iter().any(|schema| schema.is_valid(value)) => {}
+            _ => errors.push(self.mismatch(value))
+        }
+    }
+
+    fn mismatch(&self, value: &RispType) -> RispError {
+        error(format!("Expected {} but got {}", self.describe(), value))
+    }
+
+    /// Short description for error messages like `Int between 1 and 400`.
+    fn describe(&self) -> String {
+        match *self {
+            Schema::Any => "anything".to_string(),
+            Schema::Nil => "nil".to_string(),
+            Schema::Bool => "Bool".to_string(),
+            Schema::Int { min: Some(min), max: Some(max) } => format!("Int between {} and {}", min, max),
+            Schema::Int { min: Some(min), max: None } => format!("Int >= {}", min),
+            Schema::Int { min: None, max: Some(max) } => format!("Int <= {}", max),
+            Schema::Int { min: None, max: None } => "Int".to_string(),
+            Schema::Str => "String".to_string(),
+            Schema::Keyword => "Keyword".to_string(),
+            Schema::Symbol => "Symbol".to_string(),
+            Schema::Function => "Function".to_string(),
+            Schema::Vector(_) => "Vector".to_string(),
+            Schema::Set(_) => "Set".to_string(),
+            Schema::Tuple(ref schemas) => format!("Vector with {} elements", schemas.len()),
+            Schema::Map(_) | Schema::MapOf(_, _) => "Map".to_string(),
+            Schema::OneOf(ref values) => {
+                let values: Vec<String> = values.iter().map(|value| value.to_string()).collect();
+                format!("one of {}", values.join(" "))
+            }
+            Schema::Or(ref alternatives) => {
+                let descriptions: Vec<String> = alternatives.iter().map(Schema::describe).collect();
+                descriptions.join(" or ")
+            }
+            Schema::Maybe(ref schema) => format!("nil or {}", schema.describe())
+        }
+    }
+}
+
+fn check_nested<F: Fn(RispError) -> RispError>(schema: &Schema, value: &RispType, errors: &mut Vec<RispError>, add_path: F) {
+    let mut nested_errors = vec![];
+    schema.check(value, &mut nested_errors);
+    errors.extend(nested_errors.into_iter().map(add_path));
+}
+
+/// Reads a schema written in risp:
+/// * `:any :nil :bool :int :string :keyword :symbol :fn`
+/// * `{:name :string :tempo [:optional :int]}` for maps with keyword keys
+/// * `[:int {:min 1 :max 400}]`, `[:vector :int]`, `[:set :int]`, `[:map-of :int :string]`, `[:tuple :int :string]`
+/// * `[:enum :kick :snare]`, `[:or :int :string]` and `[:maybe :int]`
+impl FromRisp for Schema {
+    fn from_risp(risp: RispType) -> Result<Schema, RispError> {
+        match risp {
+            Keyword(ref name) => match name.as_str() {
+                "any" => Ok(Schema::Any),
+                "nil" => Ok(Schema::Nil),
+                "bool" => Ok(Schema::Bool),
+                "int" => Ok(Schema::int()),
+                "string" => Ok(Schema::Str),
+                "keyword" => Ok(Schema::Keyword),
+                "symbol" => Ok(Schema::Symbol),
+                "fn" => Ok(Schema::Function),
+                _ => Err(error(format!("Unknown schema type {}", risp)))
+            },
+            Map(map) => {
+                let mut entries = vec![];
+                for (key, value) in map {
+                    let (schema, optional) = match value {
+                        Vector(ref elements) if elements.first() == Some(&keyword("optional")) => {
+                            (schema_arg(elements, "optional").map_err(|e| e.at_key(path_key(&key)))?, true)
+                        }
+                        _ => (Schema::from_risp(value).map_err(|e| e.at_key(path_key(&key)))?, false)
+                    };
+                    entries.push(MapEntry { key, schema, optional });
+                }
+                Ok(Schema::Map(entries))
+            }
+            Vector(ref elements) => match elements.first() {
+                Some(Keyword(name)) => match name.as_str() {
+                    "int" => match elements.len() {
+                        1 => Ok(Schema::int()),
+                        2 => {
+                            let properties = elements[1].clone();
+                            Ok(Schema::Int { min: properties.get("min")?, max: properties.get("max")? })
+                        }
+                        _ => Err(error(format!("Schema {} needs a properties map like {{:min 1 :max 400}}", risp)))
+                    },
+                    "vector" => Ok(Schema::vector(schema_arg(elements, name)?)),
+                    "set" => Ok(Schema::set(schema_arg(elements, name)?)),
+                    "maybe" => Ok(Schema::maybe(schema_arg(elements, name)?)),
+                    "map-of" if elements.len() == 3 => {
+                        Ok(Schema::map_of(Schema::from_risp(elements[1].clone())?, Schema::from_risp(elements[2].clone())?))
+                    }
+                    "map-of" => Err(error(format!("Schema {} needs a key and a value schema", risp))),
+                    "tuple" => Ok(Schema::Tuple(schema_args(elements)?)),
+                    "or" => Ok(Schema::Or(schema_args(elements)?)),
+                    "enum" => Ok(Schema::OneOf(elements[1..].to_vec())),
+                    _ => Err(error(format!("Unknown schema type {}", elements[0])))
+                },
+                _ => Err(error(format!("Expected schema type keyword like :vector but got {}", risp)))
+            },
+            _ => Err(error(format!("Expected schema but got {}", risp)))
+        }
+    }
+}
+
+/// Reads the single schema argument of `[:vector :int]`.
+fn schema_arg(elements: &[RispType], name: &str) -> Result<Schema, RispError> {
+    match elements.len() {
+        2 => Schema::from_risp(elements[1].clone()),
+        len => Err(error(format!("Schema :{} needs 1 schema but got {}", name, len - 1)))
+    }
+}
+
+fn schema_args(elements: &[RispType]) -> Result<Vec<Schema>, RispError> {
+    elements[1..].iter().cloned().map(Schema::from_risp).collect()
+}
+
+
+/* ------------------------------ Tests ----------------------------------------------- */
+
+#[cfg(test)]
+use parse::parse;
+
+#[cfg(test)]
+fn schema_of(risp_code: &str) -> Schema {
+    Schema::from_risp(parse(risp_code).unwrap()).unwrap()
+}
+
+#[cfg(test)]
+fn errors_of(schema: &Schema, risp_code: &str) -> Vec<String> {
+    match schema.validate(&parse(risp_code).unwrap()) {
+        Ok(()) => vec![],
+        Err(errors) => errors.iter().map(|e| e.to_string()).collect()
+    }
+}
+
+#[test]
+fn test_parse_schema() {
+    assert_eq!(schema_of("{:name :string :speed [:int {:min 1 :max 400}] :notes [:vector :int] :tempo [:optional :int]}"),
+               Schema::map(vec![
+                   MapEntry::required("name", Schema::string()),
+                   MapEntry::required("speed", Schema::int_between(1, 400)),
+                   MapEntry::required("notes", Schema::vector(Schema::int())),
+                   MapEntry::optional("tempo", Schema::int()),
+               ]));
+    assert_eq!(schema_of("[:or :symbol [:int {:min 1}] [:int {:max 9}]]"),
+               Schema::or(vec![Schema::symbol(), Schema::int_at_least(1), Schema::int_at_most(9)]));
+    assert_eq!(schema_of("[:or [:maybe :int] [:enum :kick :snare] [:tuple :int :string] [:map-of :int [:set :keyword]]]"),
+               Schema::or(vec![
+                   Schema::maybe(Schema::int()),
+                   Schema::one_of(vec![keyword("kick"), keyword("snare")]),
+                   Schema::tuple(vec![Schema::int(), Schema::string()]),
+                   Schema::map_of(Schema::int(), Schema::set(Schema::keyword())),
+               ]));
+}
+
+#[test]
+fn test_parse_schema_errors() {
+    let parse_schema = |risp_code| Schema::from_risp(parse(risp_code).unwrap());
+    assert_eq!(parse_schema(":float"), Err(error("Unknown schema type :float")));
+    assert_eq!(parse_schema("{:speed [:vector]}"), Err(error("Schema :vector needs 1 schema but got 0").at_key("speed")));
+    assert_eq!(parse_schema("{:tempo [:optional :float]}"), Err(error("Unknown schema type :float").at_key("tempo")));
+    assert_eq!(parse_schema("[:int {:min \"1\"}]"), Err(error("Expected Int but got Str(\"1\")").at_key("min")));
+    assert_eq!(parse_schema("[1]"), Err(error("Expected schema type keyword like :vector but got [1]")));
+    assert_eq!(parse_schema("42"), Err(error("Expected schema but got 42")));
+}
+
+#[test]
+fn test_validate_valid() {
+    let schema = schema_of("{:name :string :speed [:int {:min 1 :max 400}] :notes [:vector :int] :tempo [:optional :int]}");
+    assert_eq!(schema.validate(&parse("{:name \"Amazon\" :speed 220 :notes [1 2] :extra true}").unwrap()), Ok(()));
+    assert!(schema_of("[:maybe :int]").is_valid(&Nil));
+    assert!(schema_of("[:map-of :int :string]").is_valid(&parse("{36 \"kick\"}").unwrap()));
+    assert!(schema_of(":any").is_valid(&parse("[1 :a]").unwrap()));
+}
+
+#[test]
+fn test_validate_collects_all_violations_with_paths() {
+    let schema = schema_of(r#"
+    {:name    :string
+     :speed   [:int {:min 1 :max 400}]
+     :effects [:vector {:trigger [:int {:min 0 :max 127}]
+                        :drum    [:enum :kick :snare]
+                        :notes   [:vector :int]}]}"#);
+    assert_eq!(errors_of(&schema, "{:speed 0 :effects [{:trigger 43 :drum :kick :notes [1]} {:trigger 200 :drum :hat :notes [1 \"2\"]}]}"), vec![
+        "Missing required key :name",
+        "Expected Int between 1 and 400 but got 0 at speed",
+        "Expected Int between 0 and 127 but got 200 at effects[1].trigger",
+        "Expected one of :kick :snare but got :hat at effects[1].drum",
+        "Expected Int but got \"2\" at effects[1].notes[1]",
+    ]);
+}
+
+#[test]
+fn test_validate_mismatches() {
+    assert_eq!(errors_of(&schema_of("[:tuple :int :string]"), "[1]"), vec!["Expected Vector with 2 elements but got [1]"]);
+    assert_eq!(errors_of(&schema_of("[:or :int :string]"), ":a"), vec!["Expected Int or String but got :a"]);
+    assert_eq!(errors_of(&schema_of("[:maybe [:int {:min 1}]]"), "0"), vec!["Expected Int >= 1 but got 0"]);
+    assert_eq!(errors_of(&schema_of("[:map-of :int :string]"), "{:kick 36}"),
+               vec!["Expected Int but got :kick at kick", "Expected String but got 36 at kick"]);
+    assert_eq!(errors_of(&schema_of("{:notes [:vector :int]}"), "{:notes 1}"), vec!["Expected Vector but got 1 at notes"]);
+}
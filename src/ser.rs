@@ -0,0 +1,408 @@
+use std::fmt;
+use serde::ser::{self, Serialize, SerializeMap, SerializeSeq, SerializeStruct, SerializeStructVariant, SerializeTuple,
+                 SerializeTupleStruct, SerializeTupleVariant};
+use printer::{pretty_print, DEFAULT_WIDTH};
+use types::*;
+use types::RispType::*;
+
+/// Converts the value into risp, e.g. to bind it in an environment.
+/// Structs become maps with keyword keys, unit variants keywords, other variants maps with a single entry and None nil.
+/// String keys of maps become keywords, unless one of them isn't a valid keyword, then all of them stay strings.
+pub fn to_value<T: Serialize + ?Sized>(value: &T) -> RispResult {
+    value.serialize(Serializer)
+}
+
+/// Returns the value as risp source on a single line.
+pub fn to_string<T: Serialize + ?Sized>(value: &T) -> Result<String, RispError> {
+    to_value(value).map(|risp_value| risp_value.to_string())
+}
+
+/// Returns the value as risp source broken into multiple lines like the pretty printer does.
+pub fn to_string_pretty<T: Serialize + ?Sized>(value: &T) -> Result<String, RispError> {
+    to_value(value).map(|risp_value| pretty_print(&risp_value, DEFAULT_WIDTH))
+}
+
+impl ser::Error for RispError {
+    fn custom<T: fmt::Display>(msg: T) -> Self {
+        error(msg.to_string())
+    }
+}
+
+/// String keys become keywords if all of them are valid keywords, so a map never mixes both kinds of keys.
+fn keyword_keys(map: IndexMap<RispType, RispType>) -> IndexMap<RispType, RispType> {
+    let all_keyword_names = map.keys().all(|key| match *key {
+        Str(ref s) => is_keyword_name(s),
+        _ => true
+    });
+    if !all_keyword_names {
+        return map;
+    }
+    map.into_iter()
+        .map(|(key, value)| match key {
+            Str(s) => (keyword(s.as_str()), value),
+            _ => (key, value)
+        })
+        .collect()
+}
+
+/// Returns true if the string prints as keyword, which reads back as the same keyword.
+pub fn is_keyword_name(s: &str) -> bool {
+    !s.is_empty() && !s.chars().any(|c| c.is_whitespace() || "{}()[]\"'`~@#;,".contains(c))
+}
+
+fn variant_map(variant: &str, value: RispType) -> RispType {
+    map(vec![(variant, value)])
+}
+
+struct Serializer;
+
+impl ser::Serializer for Serializer {
+    type Ok = RispType;
+    type Error = RispError;
+    type SerializeSeq = VectorSerializer;
+    type SerializeTuple = VectorSerializer;
+    type SerializeTupleStruct = VectorSerializer;
+    type SerializeTupleVariant = VectorSerializer;
+    type SerializeMap = MapSerializer;
+    type SerializeStruct = MapSerializer;
+    type SerializeStructVariant = MapSerializer;
+
+    fn serialize_bool(self, v: bool) -> RispResult {
+        Ok(Bool(v))
+    }
+
+    fn serialize_i8(self, v: i8) -> RispResult {
+        Ok(Int(i64::from(v)))
+    }
+
+    fn serialize_i16(self, v: i16) -> RispResult {
+        Ok(Int(i64::from(v)))
+    }
+
+    fn serialize_i32(self, v: i32) -> RispResult {
+        Ok(Int(i64::from(v)))
+    }
+
+    fn serialize_i64(self, v: i64) -> RispResult {
+        Ok(Int(v))
+    }
+
+    fn serialize_u8(self, v: u8) -> RispResult {
+        Ok(Int(i64::from(v)))
+    }
+
+    fn serialize_u16(self, v: u16) -> RispResult {
+        Ok(Int(i64::from(v)))
+    }
+
+    fn serialize_u32(self, v: u32) -> RispResult {
+        Ok(Int(i64::from(v)))
+    }
+
+    fn serialize_u64(self, v: u64) -> RispResult {
+        if v > i64::MAX as u64 {
+            return error_result(format!("{} is too large for a risp Int", v));
+        }
+        Ok(Int(v as i64))
+    }
+
+    fn serialize_f32(self, v: f32) -> RispResult {
+        self.serialize_f64(f64::from(v))
+    }
+
+    fn serialize_f64(self, v: f64) -> RispResult {
+        error_result(format!("Can't serialize {}, risp has no floating point numbers", v))
+    }
+
+    fn serialize_char(self, v: char) -> RispResult {
+        Ok(Str(v.to_string()))
+    }
+
+    fn serialize_str(self, v: &str) -> RispResult {
+        Ok(string(v))
+    }
+
+    fn serialize_bytes(self, v: &[u8]) -> RispResult {
+        Ok(Vector(v.iter().map(|&byte| Int(i64::from(byte))).collect()))
+    }
+
+    fn serialize_none(self) -> RispResult {
+        Ok(Nil)
+    }
+
+    fn serialize_some<T: Serialize + ?Sized>(self, value: &T) -> RispResult {
+        value.serialize(self)
+    }
+
+    fn serialize_unit(self) -> RispResult {
+        Ok(Nil)
+    }
+
+    fn serialize_unit_struct(self, _name: &'static str) -> RispResult {
+        Ok(Nil)
+    }
+
+    fn serialize_unit_variant(self, _name: &'static str, _variant_index: u32, variant: &'static str) -> RispResult {
+        Ok(keyword(variant))
+    }
+
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(self, _name: &'static str, value: &T) -> RispResult {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T: Serialize + ?Sized>(self, _name: &'static str, _variant_index: u32,
+                                                        variant: &'static str, value: &T) -> RispResult {
+        Ok(variant_map(variant, to_value(value)?))
+    }
+
+    fn serialize_seq(self, len: Option<usize>) -> Result<VectorSerializer, RispError> {
+        Ok(VectorSerializer { elements: Vec::with_capacity(len.unwrap_or(0)), variant: None })
+    }
+
+    fn serialize_tuple(self, len: usize) -> Result<VectorSerializer, RispError> {
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_tuple_struct(self, _name: &'static str, len: usize) -> Result<VectorSerializer, RispError> {
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_tuple_variant(self, _name: &'static str, _variant_index: u32, variant: &'static str,
+                               len: usize) -> Result<VectorSerializer, RispError> {
+        Ok(VectorSerializer { elements: Vec::with_capacity(len), variant: Some(variant) })
+    }
+
+    fn serialize_map(self, _len: Option<usize>) -> Result<MapSerializer, RispError> {
+        Ok(MapSerializer { map: IndexMap::new(), next_key: None, variant: None })
+    }
+
+    fn serialize_struct(self, _name: &'static str, len: usize) -> Result<MapSerializer, RispError> {
+        self.serialize_map(Some(len))
+    }
+
+    fn serialize_struct_variant(self, _name: &'static str, _variant_index: u32, variant: &'static str,
+                                _len: usize) -> Result<MapSerializer, RispError> {
+        Ok(MapSerializer { map: IndexMap::new(), next_key: None, variant: Some(variant) })
+    }
+}
+
+struct VectorSerializer {
+    elements: Vec<RispType>,
+    variant: Option<&'static str>,
+}
+
+impl VectorSerializer {
+    fn push<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), RispError> {
+        self.elements.push(to_value(value)?);
+        Ok(())
+    }
+
+    fn finish(self) -> RispResult {
+        let vector = Vector(self.elements);
+        Ok(match self.variant {
+            Some(variant) => variant_map(variant, vector),
+            None => vector
+        })
+    }
+}
+
+impl SerializeSeq for VectorSerializer {
+    type Ok = RispType;
+    type Error = RispError;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), RispError> {
+        self.push(value)
+    }
+
+    fn end(self) -> RispResult {
+        self.finish()
+    }
+}
+
+impl SerializeTuple for VectorSerializer {
+    type Ok = RispType;
+    type Error = RispError;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), RispError> {
+        self.push(value)
+    }
+
+    fn end(self) -> RispResult {
+        self.finish()
+    }
+}
+
+impl SerializeTupleStruct for VectorSerializer {
+    type Ok = RispType;
+    type Error = RispError;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), RispError> {
+        self.push(value)
+    }
+
+    fn end(self) -> RispResult {
+        self.finish()
+    }
+}
+
+impl SerializeTupleVariant for VectorSerializer {
+    type Ok = RispType;
+    type Error = RispError;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), RispError> {
+        self.push(value)
+    }
+
+    fn end(self) -> RispResult {
+        self.finish()
+    }
+}
+
+struct MapSerializer {
+    map: IndexMap<RispType, RispType>,
+    next_key: Option<RispType>,
+    variant: Option<&'static str>,
+}
+
+impl MapSerializer {
+    fn insert_field<T: Serialize + ?Sized>(&mut self, key: &'static str, value: &T) -> Result<(), RispError> {
+        self.map.insert(keyword(key), to_value(value)?);
+        Ok(())
+    }
+
+    fn finish(self) -> RispResult {
+        let map = Map(self.map);
+        Ok(match self.variant {
+            Some(variant) => variant_map(variant, map),
+            None => map
+        })
+    }
+}
+
+impl SerializeMap for MapSerializer {
+    type Ok = RispType;
+    type Error = RispError;
+
+    fn serialize_key<T: Serialize + ?Sized>(&mut self, key: &T) -> Result<(), RispError> {
+        self.next_key = Some(to_value(key)?);
+        Ok(())
+    }
+
+    fn serialize_value<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), RispError> {
+        let key = self.next_key.take().ok_or_else(|| error("serialize_value called before serialize_key"))?;
+        self.map.insert(key, to_value(value)?);
+        Ok(())
+    }
+
+    fn end(mut self) -> RispResult {
+        self.map = keyword_keys(self.map);
+        self.finish()
+    }
+}
+
+impl SerializeStruct for MapSerializer {
+    type Ok = RispType;
+    type Error = RispError;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, key: &'static str, value: &T) -> Result<(), RispError> {
+        self.insert_field(key, value)
+    }
+
+    fn end(self) -> RispResult {
+        self.finish()
+    }
+}
+
+impl SerializeStructVariant for MapSerializer {
+    type Ok = RispType;
+    type Error = RispError;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, key: &'static str, value: &T) -> Result<(), RispError> {
+        self.insert_field(key, value)
+    }
+
+    fn end(self) -> RispResult {
+        self.finish()
+    }
+}
+
+
+/* ------------------------------ Tests ----------------------------------------------- */
+
+#[cfg(test)]
+use std::collections::BTreeMap;
+#[cfg(test)]
+use de::from_value;
+
+#[cfg(test)]
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Device {
+    name: String,
+    midi_channel: u8,
+    ports: Vec<i32>,
+    alias: Option<String>,
+    kind: Kind,
+}
+
+#[cfg(test)]
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+enum Kind {
+    Keyboard,
+    Drums(u8),
+    Pad { x: i64, y: i64 },
+    Pair(i64, i64),
+}
+
+#[cfg(test)]
+fn device(kind: Kind) -> Device {
+    Device { name: "Nord".to_string(), midi_channel: 2, ports: vec![1, 2], alias: None, kind }
+}
+
+#[test]
+fn test_to_value_struct() {
+    assert_eq!(to_value(&device(Kind::Keyboard)), Ok(map(vec![
+        ("name", string("Nord")),
+        ("midiChannel", Int(2)),
+        ("ports", Vector(vec![Int(1), Int(2)])),
+        ("alias", Nil),
+        ("kind", keyword("keyboard")),
+    ])));
+}
+
+#[test]
+fn test_to_string() {
+    assert_eq!(to_string(&device(Kind::Drums(10))),
+               Ok("{:name \"Nord\" :midiChannel 2 :ports [1 2] :alias nil :kind {:drums 10}}".to_string()));
+    assert_eq!(to_string(&Kind::Pad { x: 1, y: 2 }), Ok("{:pad {:x 1 :y 2}}".to_string()));
+    assert_eq!(to_string(&Kind::Pair(1, 2)), Ok("{:pair [1 2]}".to_string()));
+    assert_eq!(to_string_pretty(&vec!["a"; 20]).unwrap().lines().count(), 20);
+}
+
+#[test]
+fn test_to_value_map_keys() {
+    let mut devices = BTreeMap::new();
+    devices.insert("kick", 36);
+    devices.insert("hi hat", 42);
+    assert_eq!(to_string(&devices), Ok("{\"hi hat\" 42 \"kick\" 36}".to_string()));
+    devices.remove("hi hat");
+    assert_eq!(to_string(&devices), Ok("{:kick 36}".to_string()));
+    let mut notes = BTreeMap::new();
+    notes.insert(36, "kick");
+    assert_eq!(to_string(&notes), Ok("{36 \"kick\"}".to_string()));
+}
+
+#[test]
+fn test_to_value_errors() {
+    assert_eq!(to_value(&1.5), error_result("Can't serialize 1.5, risp has no floating point numbers"));
+    assert_eq!(to_value(&u64::MAX), error_result("18446744073709551615 is too large for a risp Int"));
+}
+
+#[test]
+fn test_round_trip() {
+    for kind in [Kind::Keyboard, Kind::Drums(10), Kind::Pad { x: 1, y: -2 }, Kind::Pair(3, 4)] {
+        let value = device(kind);
+        assert_eq!(from_value::<Device>(to_value(&value).unwrap()).unwrap(), value);
+    }
+}
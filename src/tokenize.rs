@@ -9,28 +9,66 @@ pub enum TokenType {
     VectorEnd,
     HashMapStart,
     HashMapEnd,
+    SetStart,
     Symbol,
     Keyword,
-    Str
+    Str,
+    Quote,
+    SyntaxQuote,
+    Unquote,
+    UnquoteSplicing,
+    Discard,
+    Deref,
+    Whitespace,
+    Comment
 }
 
 lazy_static! {
     static ref COMMENT_REGEXP: Regex = Regex::new("^(\\s+|;.*?(\n|$))+").unwrap();
-    static ref STR_REGEXP: Regex = Regex::new("^\".*?\"").unwrap();
-    static ref SYMBOL_REGEXP: Regex = Regex::new(r"^[^\s\{\}()\[\]]+").unwrap();
+    static ref STR_REGEXP: Regex = Regex::new(r#"^"([^"\\]|\\(.|\n))*""#).unwrap();
+    static ref SYMBOL_REGEXP: Regex = Regex::new(r#"^[^\s\{\}()\[\]'`~@#"][^\s\{\}()\[\]`~@"]*"#).unwrap();
     static ref NUMBER_REGEXP: Regex = Regex::new(r"^-?\d+").unwrap();
+    static ref WHITESPACE_REGEXP: Regex = Regex::new(r"^\s+").unwrap();
+    static ref LINE_COMMENT_REGEXP: Regex = Regex::new(r"^;[^\n]*").unwrap();
 }
 
 pub type Token = (TokenType, String);
 
 pub struct Tokenizer {
     input: String,
-    pos: usize
+    pos: usize,
+    lossless: bool
 }
 
 impl Tokenizer {
     pub fn new(input: &str) -> Tokenizer {
-        Tokenizer { input: input.to_string(), pos: 0 }
+        Tokenizer { input: input.to_string(), pos: 0, lossless: false }
+    }
+
+    /// Emits whitespace and comments as tokens, so that the tokens add up to the input.
+    pub fn lossless(input: &str) -> Tokenizer {
+        Tokenizer { input: input.to_string(), pos: 0, lossless: true }
+    }
+
+    /// Returns the byte position of the next unread character.
+    pub fn position(&self) -> usize {
+        self.pos
+    }
+
+    /// Returns the next token together with its byte position in the input.
+    pub fn next_with_position(&mut self) -> Option<(Token, usize)> {
+        self.skip_white_space_and_comments();
+        let position = self.pos;
+        self.next().map(|token| (token, position))
+    }
+
+    fn skip_white_space_and_comments(&mut self) {
+        if self.lossless {
+            return;
+        }
+        if let Some(cap) = COMMENT_REGEXP.captures(&self.input[self.pos..]) {
+            self.pos += cap[0].len();
+        }
     }
 }
 
@@ -38,13 +76,21 @@ impl Iterator for Tokenizer {
     type Item = Token;
 
     fn next(&mut self) -> Option<Token> {
-        // Skip white space and comments
-        if let Some(cap) = COMMENT_REGEXP.captures(&self.input[self.pos..]) {
-            self.pos += cap[0].len();
-        }
+        self.skip_white_space_and_comments();
 
         let input = &self.input[self.pos..];
 
+        if self.lossless {
+            if let Some(cap) = WHITESPACE_REGEXP.captures(input) {
+                self.pos += cap[0].len();
+                return Some(token(TokenType::Whitespace, cap[0].to_string()))
+            }
+
+            if let Some(cap) = LINE_COMMENT_REGEXP.captures(input) {
+                self.pos += cap[0].len();
+                return Some(token(TokenType::Comment, cap[0].to_string()))
+            }
+        }
 
         if input.starts_with('(') {
             self.pos += 1;
@@ -71,11 +117,46 @@ impl Iterator for Tokenizer {
             return Some(token(TokenType::HashMapStart, "{"))
         }
 
+        if input.starts_with("#{") {
+            self.pos += 2;
+            return Some(token(TokenType::SetStart, "#{"));
+        }
+
         if input.starts_with('}') {
             self.pos += 1;
             return Some(token(TokenType::HashMapEnd, "}"));
         }
 
+        if input.starts_with('\'') {
+            self.pos += 1;
+            return Some(token(TokenType::Quote, "'"));
+        }
+
+        if input.starts_with('`') {
+            self.pos += 1;
+            return Some(token(TokenType::SyntaxQuote, "`"));
+        }
+
+        if input.starts_with("~@") {
+            self.pos += 2;
+            return Some(token(TokenType::UnquoteSplicing, "~@"));
+        }
+
+        if input.starts_with('~') {
+            self.pos += 1;
+            return Some(token(TokenType::Unquote, "~"));
+        }
+
+        if input.starts_with("#_") {
+            self.pos += 2;
+            return Some(token(TokenType::Discard, "#_"));
+        }
+
+        if input.starts_with('@') {
+            self.pos += 1;
+            return Some(token(TokenType::Deref, "@"));
+        }
+
         if let Some(cap) = STR_REGEXP.captures(input) {
             self.pos += cap[0].len();
             return Some(token(TokenType::Str, cap[0].to_string()))
@@ -101,6 +182,14 @@ impl Iterator for Tokenizer {
 }
 
 
+/// Splits a qualified symbol like `drums/kick` into namespace and name, the division symbol `/` is not qualified.
+pub fn split_qualified_symbol(symbol: &str) -> Option<(&str, &str)> {
+    match symbol.find('/') {
+        Some(pos) if symbol != "/" => Some((&symbol[..pos], &symbol[pos + 1..])),
+        _ => None
+    }
+}
+
 fn token<S: Into<String>>(token_type: TokenType, s: S) -> Token {
     (token_type, s.into())
 }
@@ -222,4 +311,109 @@ fn test_ignore_single_line_comment() {
 #[test]
 fn test_negative_int() {
     assert_eq!(tokenize("-23"), vec![token(TokenType::Number, "-23")]);
-}
\ No newline at end of file
+}
+
+#[test]
+fn test_quote() {
+    assert_eq!(tokenize("'symbol"), vec![
+        token(TokenType::Quote, "'"),
+        token(TokenType::Symbol, "symbol")
+    ]);
+    assert_eq!(tokenize("'(1)"), vec![
+        token(TokenType::Quote, "'"),
+        token(TokenType::ListStart, "("),
+        token(TokenType::Number, "1"),
+        token(TokenType::ListEnd, ")")
+    ]);
+}
+
+#[test]
+fn test_syntax_quote_and_unquote() {
+    assert_eq!(tokenize("`(a ~b ~@c)"), vec![
+        token(TokenType::SyntaxQuote, "`"),
+        token(TokenType::ListStart, "("),
+        token(TokenType::Symbol, "a"),
+        token(TokenType::Unquote, "~"),
+        token(TokenType::Symbol, "b"),
+        token(TokenType::UnquoteSplicing, "~@"),
+        token(TokenType::Symbol, "c"),
+        token(TokenType::ListEnd, ")")
+    ]);
+}
+
+#[test]
+fn test_discard_and_deref() {
+    assert_eq!(tokenize("#_1 @var"), vec![
+        token(TokenType::Discard, "#_"),
+        token(TokenType::Number, "1"),
+        token(TokenType::Deref, "@"),
+        token(TokenType::Symbol, "var")
+    ]);
+}
+
+#[test]
+fn test_quote_char_inside_symbol() {
+    assert_eq!(tokenize("x' x#"), vec![
+        token(TokenType::Symbol, "x'"),
+        token(TokenType::Symbol, "x#")
+    ]);
+}
+
+#[test]
+fn test_set() {
+    assert_eq!(tokenize("#{1 :key}"), vec![
+        token(TokenType::SetStart, "#{"),
+        token(TokenType::Number, "1"),
+        token(TokenType::Keyword, ":key"),
+        token(TokenType::HashMapEnd, "}")
+    ]);
+}
+
+#[test]
+fn test_next_with_position() {
+    let mut tokenizer = Tokenizer::new("(a ; comment\n  42)");
+    assert_eq!(tokenizer.next_with_position(), Some((token(TokenType::ListStart, "("), 0)));
+    assert_eq!(tokenizer.next_with_position(), Some((token(TokenType::Symbol, "a"), 1)));
+    assert_eq!(tokenizer.next_with_position(), Some((token(TokenType::Number, "42"), 15)));
+    assert_eq!(tokenizer.next_with_position(), Some((token(TokenType::ListEnd, ")"), 17)));
+    assert_eq!(tokenizer.next_with_position(), None);
+}
+
+#[test]
+fn test_string_with_escapes() {
+    assert_eq!(tokenize(r#""say \"hi\"" "\\" "line1\nline2""#), vec![
+        token(TokenType::Str, r#""say \"hi\"""#),
+        token(TokenType::Str, r#""\\""#),
+        token(TokenType::Str, r#""line1\nline2""#),
+    ]);
+}
+
+#[test]
+fn test_multi_line_string() {
+    assert_eq!(tokenize("\"line1\nline2\""), vec![token(TokenType::Str, "\"line1\nline2\"")]);
+}
+
+#[test]
+fn test_lossless() {
+    let input = "(a ; comment\n  42)";
+    let tokens: Vec<Token> = Tokenizer::lossless(input).collect();
+    assert_eq!(tokens, vec![
+        token(TokenType::ListStart, "("),
+        token(TokenType::Symbol, "a"),
+        token(TokenType::Whitespace, " "),
+        token(TokenType::Comment, "; comment"),
+        token(TokenType::Whitespace, "\n  "),
+        token(TokenType::Number, "42"),
+        token(TokenType::ListEnd, ")")
+    ]);
+    let source: String = tokens.into_iter().map(|(_, text)| text).collect();
+    assert_eq!(source, input);
+}
+
+#[test]
+fn test_split_qualified_symbol() {
+    assert_eq!(split_qualified_symbol("drums/kick"), Some(("drums", "kick")));
+    assert_eq!(split_qualified_symbol("kick"), None);
+    assert_eq!(split_qualified_symbol("/"), None);
+    assert_eq!(split_qualified_symbol("a/b/c"), Some(("a", "b/c")));
+}
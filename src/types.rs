@@ -1,15 +1,94 @@
-use std::collections::HashMap;
+use std::cmp::Ordering;
+use std::collections::BTreeSet;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+
+pub use indexmap::IndexMap;
 
 use std::rc::Rc;
 
 use environment::Environment;
+use self::RispType::*;
 
+/// Errors are equal if their message, path and kind are equal.
 #[derive(Debug, PartialEq)]
-pub struct RispError(String);
+pub struct RispError {
+    message: String,
+    /// Keys and indices from the root value to the value which caused the error, outermost first.
+    path: Vec<String>,
+    kind: ErrorKind,
+}
+
+/// Allows embedders to tell aborted evaluations apart from errors in the risp code.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorKind {
+    Eval,
+    LimitExceeded,
+    Cancelled,
+    /// The script used a builtin of a capability, which the embedder didn't grant.
+    CapabilityNotGranted,
+    /// The parsed input ends in the middle of a form, e.g. an unclosed list.
+    IncompleteInput,
+}
 
 pub type RispResult = Result<RispType, RispError>;
 
+impl RispError {
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+
+    pub fn kind(&self) -> ErrorKind {
+        self.kind
+    }
+
+    pub fn with_kind(mut self, kind: ErrorKind) -> RispError {
+        self.kind = kind;
+        self
+    }
+
+    /// The path to the failing value like `effects[2].trigger`, if the error happened inside of a value.
+    pub fn path(&self) -> Option<String> {
+        if self.path.is_empty() {
+            return None;
+        }
+        let mut result = String::new();
+        for segment in &self.path {
+            if !result.is_empty() && !segment.starts_with('[') {
+                result.push('.');
+            }
+            result.push_str(segment);
+        }
+        Some(result)
+    }
+
+    /// Adds the key in front of the path, while the error travels from an inner to an outer value.
+    pub fn at_key<S: Into<String>>(mut self, key: S) -> RispError {
+        self.path.insert(0, key.into());
+        self
+    }
+
+    /// Adds the index in front of the path.
+    pub fn at_index(mut self, index: usize) -> RispError {
+        self.path.insert(0, format!("[{}]", index));
+        self
+    }
+}
+
+impl fmt::Display for RispError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.message)?;
+        if let Some(path) = self.path() {
+            write!(f, " at {}", path)?;
+        }
+        Ok(())
+    }
+}
+
+impl ::std::error::Error for RispError {}
+
 
+#[allow(unpredictable_function_pointer_comparisons)]
 #[derive(Debug, PartialEq, Clone)]
 pub enum RispType {
     Nil,
@@ -18,14 +97,17 @@ pub enum RispType {
     Str(String),
     List(Vec<RispType>),
     Vector(Vec<RispType>),
-    Map(HashMap<String, RispType>),
+    Set(BTreeSet<RispType>),
+    /// Keeps the insertion order of its keys, so that output is deterministic.
+    Map(IndexMap<RispType, RispType>),
     Keyword(String),
     Symbol(String),
     Function(fn(Vec<RispType>) -> RispResult),
     RispFunction(RispFunc),
+    Macro(RispFunc),
 }
 
-#[derive(Debug, PartialEq, Clone)]
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Clone)]
 pub struct RispFunc {
     pub args: Vec<RispType>,
     pub variadic_arg: Option<String>,
@@ -33,9 +115,157 @@ pub struct RispFunc {
     pub env: Environment
 }
 
+impl RispType {
+    /// Position of the type in the total order of all risp values.
+    fn type_order(&self) -> u8 {
+        match *self {
+            Nil => 0,
+            Bool(_) => 1,
+            Int(_) => 2,
+            Str(_) => 3,
+            Keyword(_) => 4,
+            Symbol(_) => 5,
+            List(_) => 6,
+            Vector(_) => 7,
+            Set(_) => 8,
+            Map(_) => 9,
+            Function(_) => 10,
+            RispFunction(_) => 11,
+            Macro(_) => 12,
+        }
+    }
+}
+
+impl Eq for RispType {}
+
+impl PartialOrd for RispType {
+    fn partial_cmp(&self, other: &RispType) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// A total order, so that risp values can be elements of sets.
+/// Values of different types are ordered by their type.
+impl Ord for RispType {
+    fn cmp(&self, other: &RispType) -> Ordering {
+        match (self, other) {
+            (Bool(b1), Bool(b2)) => b1.cmp(b2),
+            (Int(i1), Int(i2)) => i1.cmp(i2),
+            (Str(s1), Str(s2)) |
+            (Keyword(s1), Keyword(s2)) |
+            (Symbol(s1), Symbol(s2)) => s1.cmp(s2),
+            (List(v1), List(v2)) |
+            (Vector(v1), Vector(v2)) => v1.cmp(v2),
+            (Set(s1), Set(s2)) => s1.cmp(s2),
+            (Map(m1), Map(m2)) => sorted_entries(m1).cmp(&sorted_entries(m2)),
+            (Function(f1), Function(f2)) => (*f1 as usize).cmp(&(*f2 as usize)),
+            (RispFunction(f1), RispFunction(f2)) |
+            (Macro(f1), Macro(f2)) => f1.cmp(f2),
+            _ => self.type_order().cmp(&other.type_order())
+        }
+    }
+}
+
+/// Consistent with Eq, so that risp values can be used as keys in maps.
+impl Hash for RispType {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.type_order().hash(state);
+        match *self {
+            Nil => {}
+            Bool(b) => b.hash(state),
+            Int(i) => i.hash(state),
+            Str(ref s) | Keyword(ref s) | Symbol(ref s) => s.hash(state),
+            List(ref v) | Vector(ref v) => v.hash(state),
+            Set(ref s) => s.hash(state),
+            Map(ref m) => sorted_entries(m).hash(state),
+            Function(f) => (f as usize).hash(state),
+            RispFunction(ref f) | Macro(ref f) => f.hash(state),
+        }
+    }
+}
+
+/// Prints the value as risp source, which can be parsed again (except for functions and macros).
+impl fmt::Display for RispType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Nil => write!(f, "nil"),
+            Bool(b) => write!(f, "{}", b),
+            Int(i) => write!(f, "{}", i),
+            Str(ref s) => write!(f, "\"{}\"", escape_string(s)),
+            Keyword(ref k) => write!(f, ":{}", k),
+            Symbol(ref s) => write!(f, "{}", s),
+            List(ref list) => write_elements(f, "(", list, ")"),
+            Vector(ref vector) => write_elements(f, "[", vector, "]"),
+            Set(ref set_value) => write_elements(f, "#{", set_value, "}"),
+            Map(ref map_value) => {
+                write!(f, "{{")?;
+                for (i, (key, value)) in map_value.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, " ")?;
+                    }
+                    write!(f, "{} {}", key, value)?;
+                }
+                write!(f, "}}")
+            }
+            Function(_) => write!(f, "#<function>"),
+            RispFunction(ref risp_func) => {
+                let mut args = risp_func.args.clone();
+                if let Some(ref variadic_arg) = risp_func.variadic_arg {
+                    args.push(symbol("&"));
+                    args.push(symbol(variadic_arg.as_str()));
+                }
+                write!(f, "(fn {} {})", Vector(args), risp_func.body)
+            }
+            Macro(_) => write!(f, "#<macro>"),
+        }
+    }
+}
+
+impl RispType {
+    /// Returns the readable risp source of the value, like pr-str.
+    pub fn pr_str(&self) -> String {
+        self.to_string()
+    }
+}
+
+fn write_elements<'a, I>(f: &mut fmt::Formatter, open: &str, elements: I, close: &str) -> fmt::Result
+    where I: IntoIterator<Item=&'a RispType> {
+    write!(f, "{}", open)?;
+    for (i, element) in elements.into_iter().enumerate() {
+        if i > 0 {
+            write!(f, " ")?;
+        }
+        write!(f, "{}", element)?;
+    }
+    write!(f, "{}", close)
+}
+
+pub fn escape_string(s: &str) -> String {
+    let mut result = String::new();
+    for c in s.chars() {
+        match c {
+            '"' => result.push_str("\\\""),
+            '\\' => result.push_str("\\\\"),
+            '\n' => result.push_str("\\n"),
+            '\t' => result.push_str("\\t"),
+            '\r' => result.push_str("\\r"),
+            _ => result.push(c)
+        }
+    }
+    result
+}
+
+/// Returns the entries sorted by key, which allows to compare and hash maps independent of their order.
+pub fn sorted_entries<'a, K: Ord + 'a, V: 'a, I>(entries: I) -> Vec<(&'a K, &'a V)>
+    where I: IntoIterator<Item=(&'a K, &'a V)> {
+    let mut entries: Vec<(&K, &V)> = entries.into_iter().collect();
+    entries.sort_by(|e1, e2| e1.0.cmp(e2.0));
+    entries
+}
+
 
 pub fn error<S: Into<String>>(message: S) -> RispError {
-    RispError(message.into())
+    RispError { message: message.into(), path: vec![], kind: ErrorKind::Eval }
 }
 
 pub fn error_result<S: Into<String>>(message: S) -> RispResult {
@@ -46,6 +276,10 @@ pub fn symbol<S: Into<String>>(s: S) -> RispType {
     RispType::Symbol(s.into())
 }
 
+pub fn set(elements: Vec<RispType>) -> RispType {
+    RispType::Set(elements.into_iter().collect())
+}
+
 pub fn keyword<S: Into<String>>(s: S) -> RispType {
     RispType::Keyword(s.into())
 }
@@ -54,10 +288,42 @@ pub fn string<S: Into<String>>(s: S) -> RispType {
     RispType::Str(s.into())
 }
 
+/// Creates a map with keyword keys.
 #[allow(dead_code)]
 pub fn map<S: Into<String>>(pairs: Vec<(S, RispType)>) -> RispType {
-    let result: HashMap<String, RispType> = pairs.into_iter()
-        .map(|(s, r)| (s.into(), r))
+    let result: IndexMap<RispType, RispType> = pairs.into_iter()
+        .map(|(s, r)| (keyword(s), r))
         .collect();
     RispType::Map(result)
-}
\ No newline at end of file
+}
+
+#[allow(dead_code)]
+pub fn map_from(pairs: Vec<(RispType, RispType)>) -> RispType {
+    RispType::Map(pairs.into_iter().collect())
+}
+
+
+/* ------------------------------ Tests ----------------------------------------------- */
+
+#[test]
+fn test_display_atoms() {
+    assert_eq!(Nil.to_string(), "nil");
+    assert_eq!(Bool(true).to_string(), "true");
+    assert_eq!(Int(-42).to_string(), "-42");
+    assert_eq!(keyword("name").to_string(), ":name");
+    assert_eq!(symbol("+").to_string(), "+");
+}
+
+#[test]
+fn test_display_escaped_string() {
+    assert_eq!(string("say \"hi\"\n\\").to_string(), r#""say \"hi\"\n\\""#);
+}
+
+#[test]
+fn test_display_collections() {
+    assert_eq!(List(vec![symbol("+"), Int(1), Int(2)]).to_string(), "(+ 1 2)");
+    assert_eq!(Vector(vec![]).to_string(), "[]");
+    assert_eq!(set(vec![Int(2), Int(1)]).to_string(), "#{1 2}");
+    assert_eq!(map(vec![("name", string("Amazon")), ("notes", Vector(vec![Int(1), Int(2)]))]).to_string(),
+               r#"{:name "Amazon" :notes [1 2]}"#);
+}
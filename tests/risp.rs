@@ -5,6 +5,7 @@ use risp::types::RispType::*;
 use risp::types::*;
 use risp::types::error_result;
 use risp::core::create_core_environment;
+use risp::parse::ParseOptions;
 
 #[test]
 fn test_sum() {
@@ -91,4 +92,27 @@ fn test_eval_do() {
 fn test_eval_do_empty() {
     let result = eval_risp("(do)");
     assert_eq!(result, error_result("Empty do block"));
-}
\ No newline at end of file
+}
+
+#[test]
+fn test_eval_script_duplicate_keys() {
+    let mut env = create_core_environment();
+    let result = eval_risp_script("(def x 1)\n{:speed 100\n :speed 200}", &mut env);
+    assert_eq!(result, error_result("Duplicate key Keyword(\"speed\") in map at line 3, column 2 (first occurrence at line 2, column 2)"));
+}
+
+#[test]
+fn test_eval_script_lenient_duplicate_keys() {
+    let mut env = create_core_environment();
+    let options = ParseOptions { lenient: true };
+    let (result, warnings) = eval_risp_script_with_options("{:speed 100 :speed 200}", &mut env, &options).unwrap();
+    assert_eq!(result, map(vec![("speed", Int(200))]));
+    assert_eq!(warnings, vec!["Duplicate key Keyword(\"speed\") in map at line 1, column 13 (first occurrence at line 1, column 2)".to_string()]);
+}
+
+#[test]
+fn test_json_builtins() {
+    assert_eq!(eval_risp(r#"(json/decode (json/encode {:name "Amazon" :notes [1 2] :drums #{36}}))"#),
+               Ok(map(vec![("name", string("Amazon")), ("notes", Vector(vec![Int(1), Int(2)])), ("drums", Vector(vec![Int(36)]))])));
+    assert_eq!(eval_risp("(json/encode (fn [x] x))"), error_result("Can't convert (fn [x] x) to JSON"));
+}
@@ -0,0 +1,162 @@
+use std::io::Write;
+use std::process::{Command, Output, Stdio};
+
+fn risp(args: &[&str], stdin: &str) -> Output {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_risp"))
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child.stdin.take().unwrap().write_all(stdin.as_bytes()).unwrap();
+    child.wait_with_output().unwrap()
+}
+
+fn stdout(output: &Output) -> String {
+    String::from_utf8_lossy(&output.stdout).to_string()
+}
+
+fn stderr(output: &Output) -> String {
+    String::from_utf8_lossy(&output.stderr).to_string()
+}
+
+#[test]
+fn test_expression() {
+    let output = risp(&["-e", "(+ 1 2)"], "");
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(stdout(&output), "3\n");
+}
+
+#[test]
+fn test_inputs_share_the_environment() {
+    let output = risp(&["-", "-e", "(double 21)"], "(defn double [x] (* x 2))");
+    assert_eq!(stdout(&output), "42\n");
+}
+
+#[test]
+fn test_definitions_and_formats() {
+    let output = risp(&["-D", "speed=120", "-Dname=Amazon", "-f", "json", "-e", "{:speed speed :name name :notes [1 2]}"], "");
+    assert_eq!(stdout(&output), "{\"speed\":120,\"name\":\"Amazon\",\"notes\":[1,2]}\n");
+
+    let output = risp(&["--format", "risp", "-e", "{:name \"Amazon\"}"], "");
+    assert_eq!(stdout(&output), "{:name \"Amazon\"}\n");
+}
+
+#[test]
+fn test_file() {
+    let output = risp(&["examples/simple_song.risp", "-f", "risp"], "");
+    assert_eq!(stdout(&output), "{:name \"Name\" :speed 220 :notes [1 2 3]}\n");
+}
+
+#[test]
+fn test_errors() {
+    let output = risp(&["-e", "(+ 1"], "");
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(stderr(&output), "risp: -e: Unexpected end of list\n");
+
+    let output = risp(&["-e", "99999999999999999999"], "");
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(stderr(&output), "risp: -e: Number 99999999999999999999 is out of range at line 1, column 1\n");
+
+    let output = risp(&["-f", "json", "-e", "(fn [x] x)"], "");
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(stderr(&output), "risp: Can't convert (fn [x] x) to JSON\n");
+
+    let output = risp(&["does_not_exist.risp"], "");
+    assert_eq!(output.status.code(), Some(2));
+
+    let output = risp(&["--unknown"], "");
+    assert_eq!(output.status.code(), Some(2));
+    assert!(stderr(&output).starts_with("risp: Unknown option --unknown\n"));
+}
+
+#[test]
+fn test_fmt_check() {
+    let output = risp(&["fmt", "--check", "examples/song.risp", "examples/kitchen_sink.risp"], "");
+    assert_eq!(output.status.code(), Some(0));
+}
+
+#[test]
+fn test_repl() {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_risp"))
+        .arg("repl")
+        .env("HOME", env!("CARGO_TARGET_TMPDIR"))
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child.stdin.take().unwrap().write_all(b"(def a\n 2)\n[a *1]\n:quit\n").unwrap();
+    let output = child.wait_with_output().unwrap();
+    assert_eq!(output.status.code(), Some(0));
+    assert!(stdout(&output).ends_with("risp>   ... 2\nrisp> [2 2]\nrisp> "));
+}
+
+#[test]
+fn test_repl_needs_fs_read_for_load() {
+    for (args, expected) in [(vec!["repl"], "risp> Error: Capability fs-read not granted, which is needed by load and require\n"),
+                             (vec!["repl", "--allow", "fs-read"], "risp> {:name \"Name\" :speed 220 :notes [1 2 3]}\n")] {
+        let mut child = Command::new(env!("CARGO_BIN_EXE_risp"))
+            .args(args)
+            .env("HOME", env!("CARGO_TARGET_TMPDIR"))
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .unwrap();
+        child.stdin.take().unwrap().write_all(b"(load \"examples/simple_song.risp\")\n").unwrap();
+        let output = child.wait_with_output().unwrap();
+        assert!(stdout(&output).ends_with(&format!("{}risp> \n", expected)), "{}", stdout(&output));
+    }
+}
+
+#[test]
+fn test_require_reads_modules_relative_to_current_directory() {
+    let dir = std::path::Path::new(env!("CARGO_TARGET_TMPDIR")).join("modules");
+    std::fs::create_dir_all(dir.join("lib")).unwrap();
+    std::fs::write(dir.join("lib/drums.risp"), "(def kick 36)\n").unwrap();
+    std::fs::write(dir.join("song.risp"), "(require '[lib.drums :as d])\n[d/kick (load \"lib/drums.risp\")]\n").unwrap();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_risp"))
+        .args(["--allow", "fs-read", "song.risp"])
+        .current_dir(&dir)
+        .output()
+        .unwrap();
+    assert_eq!(stdout(&output), "[36 36]\n");
+}
+
+#[test]
+fn test_load_and_require_need_fs_read() {
+    let output = risp(&["-e", "(load \"examples/simple_song.risp\")"], "");
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(stderr(&output), "risp: -e: Capability fs-read not granted, which is needed by load and require\n");
+
+    let output = risp(&["-e", "(require 'examples.simple_song)"], "");
+    assert_eq!(stderr(&output), "risp: -e: Capability fs-read not granted, which is needed by load and require\n");
+
+    let output = risp(&["--allow", "fs-read", "-f", "risp", "-e", "(load \"examples/simple_song.risp\")"], "");
+    assert_eq!(stdout(&output), "{:name \"Name\" :speed 220 :notes [1 2 3]}\n");
+}
+
+#[test]
+fn test_allow_capabilities() {
+    let output = risp(&["-e", "(fs/exists? \"Cargo.toml\")"], "");
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(stderr(&output), "risp: -e: Capability fs-read not granted, which is needed by fs/exists?\n");
+
+    let output = risp(&["--allow", "env,fs-read", "-e", "(fs/exists? \"Cargo.toml\")"], "");
+    assert_eq!(stdout(&output), "true\n");
+
+    let output = risp(&["--allow", "network", "-e", "1"], "");
+    assert_eq!(output.status.code(), Some(2));
+    assert!(stderr(&output).starts_with("risp: Unknown capability network\n"));
+}
+
+#[test]
+fn test_seed() {
+    let output = risp(&["--seed", "42", "-e", "(shuffle [1 2 3 4 5 6 7 8])"], "");
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(stdout(&output), stdout(&risp(&["--seed", "42", "-e", "(shuffle [1 2 3 4 5 6 7 8])"], "")));
+
+    let output = risp(&["--seed", "x", "-e", "1"], "");
+    assert_eq!(output.status.code(), Some(2));
+}
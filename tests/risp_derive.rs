@@ -0,0 +1,109 @@
+extern crate risp;
+#[macro_use]
+extern crate risp_derive;
+
+use risp::eval_risp_script;
+use risp::core::create_core_environment;
+use risp::convert::{FromRisp, IntoRisp};
+use risp::types::RispType;
+use risp::types::error;
+
+#[derive(Debug, PartialEq, FromRisp, IntoRisp)]
+struct Song {
+    name: String,
+    #[risp(rename = "timePerNote")]
+    time_per_note: i64,
+    #[risp(default)]
+    tags: Vec<String>,
+    #[risp(optional)]
+    beat_offset: Option<i64>,
+    effects: Vec<Effect>,
+}
+
+#[derive(Debug, PartialEq, FromRisp, IntoRisp)]
+enum Effect {
+    Mute,
+    #[risp(rename = "drums")]
+    DrumKit,
+    Transpose(i64),
+    NoteSequencer {
+        trigger: i64,
+        #[risp(flatten)]
+        notes: Vec<i64>,
+    },
+}
+
+#[derive(Debug, PartialEq, FromRisp, IntoRisp)]
+struct Speed(i64);
+
+fn eval(risp_code: &str) -> RispType {
+    eval_risp_script(risp_code, &mut create_core_environment()).unwrap()
+}
+
+#[test]
+fn test_derive_from_risp() {
+    let song = Song::from_risp(eval(r#"
+    {:name        "Amazon"
+     :timePerNote 220
+     :effects     [:mute :drums {:transpose 12} {:note_sequencer {:trigger 43 :notes [[45 57] 45]}}]}"#));
+
+    assert_eq!(song, Ok(Song {
+        name: "Amazon".to_string(),
+        time_per_note: 220,
+        tags: vec![],
+        beat_offset: None,
+        effects: vec![
+            Effect::Mute,
+            Effect::DrumKit,
+            Effect::Transpose(12),
+            Effect::NoteSequencer { trigger: 43, notes: vec![45, 57, 45] }
+        ],
+    }));
+}
+
+#[test]
+fn test_derive_from_risp_errors() {
+    assert_eq!(Song::from_risp(eval("{:name \"Amazon\" :effects []}")),
+               Err(error("Missing key :timePerNote for Song")));
+    assert_eq!(Effect::from_risp(eval(":reverb")), Err(error("Unknown Effect variant :reverb")));
+    assert_eq!(Effect::from_risp(eval("{:note_sequencer {:notes []}}")),
+               Err(error("Missing key :trigger for Effect::NoteSequencer").at_key("note_sequencer")));
+    assert_eq!(Song::from_risp(eval("{:name \"Amazon\" :timePerNote 220 :effects [:mute {:note_sequencer {:trigger 43 :notes [1 [2 :x]]}}]}")),
+               Err(error("Expected Int but got Keyword(\"x\")").at_index(1).at_index(1).at_key("notes").at_key("note_sequencer").at_index(1).at_key("effects")));
+    assert_eq!(Effect::from_risp(eval("[1]")),
+               Err(error("Expected Keyword or Map with a single entry for Effect but got Vector([Int(1)])")));
+    assert_eq!(Speed::from_risp(eval("\"fast\"")), Err(error("Expected Int but got Str(\"fast\")")));
+}
+
+#[test]
+fn test_derive_into_risp() {
+    let song = Song {
+        name: "Amazon".to_string(),
+        time_per_note: 220,
+        tags: vec!["rock".to_string()],
+        beat_offset: Some(4),
+        effects: vec![Effect::DrumKit, Effect::Transpose(-12)],
+    };
+
+    assert_eq!(song.into_risp(), eval(r#"
+    {:name        "Amazon"
+     :timePerNote 220
+     :tags        ["rock"]
+     :beat_offset 4
+     :effects     [:drums {:transpose -12}]}"#));
+    assert_eq!(Speed(120).into_risp(), RispType::Int(120));
+}
+
+#[test]
+fn test_derive_round_trip() {
+    let song = Song {
+        name: "Amazon".to_string(),
+        time_per_note: 220,
+        tags: vec![],
+        beat_offset: None,
+        effects: vec![Effect::Mute, Effect::NoteSequencer { trigger: 43, notes: vec![45, 57] }],
+    };
+    let risp = song.into_risp();
+    assert_eq!(risp.get::<RispType>("beat_offset"), Ok(None));
+    assert_eq!(Song::from_risp(risp.clone()).map(IntoRisp::into_risp), Ok(risp));
+}
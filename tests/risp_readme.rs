@@ -4,6 +4,7 @@ use risp::eval_risp_script;
 use risp::types::RispType::*;
 use risp::types::*;
 use risp::core::create_core_environment;
+use std::collections::BTreeSet;
 use std::fs::File;
 use std::io::prelude::*;
 
@@ -40,6 +41,20 @@ fn test_kitchen_sink() {
     assert_eq!(result_map.get("vector_sum3").unwrap(), Some(vec![11, 12, 21, 22]));
     assert_eq!(result_map.get("doubled").unwrap(), Some(Int(42)));
     assert_eq!(result_map.get("added_20").unwrap(), Some(Int(23)));
+    assert_eq!(result_map.get("infix").unwrap(), Some(42));
+    assert_eq!(result_map.get("my_string").unwrap(), Some("Hello \"World\"".to_string()));
+    assert_eq!(result_map.get("my_nil").unwrap(), Some(Nil));
+    assert_eq!(result_map.get("str").unwrap(), Some("Note 2".to_string()));
+    assert_eq!(result_map.get("pr_str").unwrap(), Some(r#""Hello" :key [1 2]"#.to_string()));
+    let my_set: Option<BTreeSet<i64>> = result_map.get("my_set").unwrap();
+    assert_eq!(my_set, Some(vec![36, 38, 43].into_iter().collect()));
+    assert_eq!(result_map.get("discarded").unwrap(), Some(vec![1, 3]));
+    assert_eq!(result_map.get("quoted").unwrap(), Some(List(vec![symbol("+"), Int(1), Int(2)])));
+    assert_eq!(result_map.get("templated").unwrap(), Some(vec![1, 2, 1, 2, 3]));
+    assert_eq!(result_map.get::<RispType>("disabled").unwrap(), None);
+
+    let drum_names: RispType = result_map.get("drum_names").unwrap().unwrap();
+    assert_eq!(drum_names.get_by_key(&Int(36)).unwrap(), Some(string("kick")));
 
     let song: RispType = result_map.get("song").unwrap().unwrap();
     assert_eq!(song.get("name").unwrap(), Some(string("Sweet Dreams")));
@@ -0,0 +1,70 @@
+extern crate risp;
+#[macro_use] extern crate serde_derive;
+
+use risp::eval_risp_script;
+use risp::core::create_core_environment;
+use risp::types::RispType::Vector;
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Song {
+    name: String,
+    time_per_note: u32,
+    effects: Vec<Effect>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Effect {
+    trigger: u8,
+    note_sequencer: NoteSequencer,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct NoteSequencer {
+    notes: Vec<i64>,
+    beat_offset: Option<i64>,
+}
+
+#[test]
+fn test_deserialize_example() {
+    let song: Song = risp::from_str(r#"
+    {:name        "Amazon"
+     :timePerNote 220
+     :effects     [{:trigger 43 :noteSequencer {:notes [45 57]}}
+                   {:trigger 45 :noteSequencer {:notes [38 50] :beatOffset 4}}]}"#).unwrap();
+
+    assert_eq!(song.name, "Amazon");
+    assert_eq!(song.time_per_note, 220);
+    assert_eq!(song.effects[1].trigger, 45);
+    assert_eq!(song.effects[1].note_sequencer.notes, vec![38, 50]);
+    assert_eq!(song.effects[1].note_sequencer.beat_offset, Some(4));
+    assert_eq!(song.effects[0].note_sequencer.beat_offset, None);
+
+    let error = risp::from_str::<Song>(r#"{:name "Amazon" :timePerNote 220 :effects [{:trigger 43 :noteSequencer {:notes ["kick"]}}]}"#).unwrap_err();
+    assert_eq!(error.to_string(), "invalid type: string \"kick\", expected i64 at effects[0].noteSequencer.notes[0]");
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Device {
+    name: String,
+    midi_channel: u8,
+}
+
+#[test]
+fn test_serialize_example() {
+    let devices = vec![
+        Device { name: "Nord".to_string(), midi_channel: 1 },
+        Device { name: "Drums".to_string(), midi_channel: 10 },
+    ];
+
+    let mut env = create_core_environment();
+    env.set("devices", risp::to_value(&devices).unwrap());
+    let drums = eval_risp_script("(def drums (rep 2 devices)) drums", &mut env).unwrap();
+    assert_eq!(drums, Vector(vec![risp::to_value(&devices).unwrap(); 2]));
+
+    assert_eq!(risp::to_string(&devices).unwrap(),
+               r#"[{:name "Nord" :midiChannel 1} {:name "Drums" :midiChannel 10}]"#);
+}